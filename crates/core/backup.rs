@@ -0,0 +1,736 @@
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+use crate::error::{JotDeckError, Result};
+use crate::journal;
+use crate::models::{Card, CardRevision, CardTag, Column, Deck, DeleteStackEntry, DeletedEntityType, Tag};
+use crate::rank;
+use crate::repository::card::{self, row_to_card, row_to_revision, CARD_COLUMNS};
+use crate::repository::column::{row_to_column, COLUMN_COLUMNS};
+use crate::repository::deck::row_to_deck;
+use crate::repository::delete_stack::row_to_entry;
+use crate::repository::tag;
+
+/// バックアップ形式のバージョン
+///
+/// 形式を変えたら上げる。読み込めるのはこのバージョン以下のバックアップのみ。
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+/// データベース全体のバックアップ
+///
+/// 削除済みのエンティティや削除スタックも含め、DB の内容をそのまま保持する。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    pub format_version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub decks: Vec<Deck>,
+    pub columns: Vec<Column>,
+    pub cards: Vec<Card>,
+    pub tags: Vec<Tag>,
+    pub card_tags: Vec<CardTag>,
+    pub delete_stack: Vec<DeleteStackEntry>,
+    #[serde(default)]
+    pub revisions: Vec<CardRevision>,
+}
+
+/// 読み込み方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// 空の DB にそのまま復元する（DB が空でなければエラー）
+    Restore,
+    /// 既存の DB にマージする。同じ ID のエンティティは updated_at が新しい方を採用する
+    Merge,
+}
+
+/// エンティティごとの読み込み件数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// 読み込み結果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportResult {
+    pub decks: ImportCounts,
+    pub columns: ImportCounts,
+    pub cards: ImportCounts,
+}
+
+/// DB 全体のバックアップを作成する
+pub fn create_backup(conn: &Connection) -> Result<Backup> {
+    let decks = conn
+        .prepare("SELECT id, name, sort_order, created_at, updated_at, deleted_at FROM decks ORDER BY created_at ASC, id ASC")?
+        .query_map([], row_to_deck)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    // 親ごとに rank 順に並べる（復元時はこの順に rank を振り直す）
+    let columns = conn
+        .prepare(&format!("SELECT {} FROM columns c ORDER BY c.deck_id ASC, c.rank ASC, c.id ASC", COLUMN_COLUMNS))?
+        .query_map([], row_to_column)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let cards = conn
+        .prepare(&format!("SELECT {} FROM cards c ORDER BY c.column_id ASC, c.rank ASC, c.id ASC", CARD_COLUMNS))?
+        .query_map([], row_to_card)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let tags = conn
+        .prepare("SELECT id, name, parent_id FROM tags ORDER BY name ASC")?
+        .query_map([], tag::row_to_tag)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let card_tags = conn
+        .prepare("SELECT card_id, tag_id FROM card_tags ORDER BY card_id ASC, tag_id ASC")?
+        .query_map([], |row| {
+            Ok(CardTag {
+                card_id: row.get(0)?,
+                tag_id: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let delete_stack = conn
+        .prepare("SELECT id, deck_id, entity_type, entity_id, deleted_at FROM delete_stack ORDER BY id ASC")?
+        .query_map([], row_to_entry)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let revisions = conn
+        .prepare("SELECT id, card_id, content, created_at FROM card_revisions ORDER BY id ASC")?
+        .query_map([], row_to_revision)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(Backup {
+        format_version: BACKUP_FORMAT_VERSION,
+        exported_at: Utc::now(),
+        decks,
+        columns,
+        cards,
+        tags,
+        card_tags,
+        delete_stack,
+        revisions,
+    })
+}
+
+/// DB 全体のバックアップを JSON 文字列にする
+pub fn backup_to_json(conn: &Connection) -> Result<String> {
+    let backup = create_backup(conn)?;
+    Ok(serde_json::to_string_pretty(&backup)?)
+}
+
+/// DB 全体のバックアップを JSON ファイルに書き出す
+pub fn write_backup(conn: &Connection, path: impl AsRef<Path>) -> Result<()> {
+    let json = backup_to_json(conn)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// JSON 文字列からバックアップを読み込む
+pub fn restore_from_json(conn: &Connection, json: &str, mode: ImportMode) -> Result<ImportResult> {
+    let backup: Backup = serde_json::from_str(json)?;
+    restore_backup(conn, &backup, mode)
+}
+
+/// JSON ファイルからバックアップを読み込む
+pub fn restore_backup_file(conn: &Connection, path: impl AsRef<Path>, mode: ImportMode) -> Result<ImportResult> {
+    let json = std::fs::read_to_string(path)?;
+    restore_from_json(conn, &json, mode)
+}
+
+/// バックアップを読み込む（単一トランザクション）
+///
+/// 読み込みは取り消せないので操作ジャーナルには記録しない。`Restore` では DB を置き換えるので、ジャーナルも消す。
+pub fn restore_backup(conn: &Connection, backup: &Backup, mode: ImportMode) -> Result<ImportResult> {
+    journal::unrecorded(conn, || {
+        if backup.format_version > BACKUP_FORMAT_VERSION {
+            return Err(JotDeckError::InvalidOperation(format!(
+                "Backup format version {} is newer than supported version {}",
+                backup.format_version, BACKUP_FORMAT_VERSION
+            )));
+        }
+
+        let tx = db::savepoint(conn)?;
+
+        let result = match mode {
+            ImportMode::Restore => {
+                let result = restore_into_empty(&tx, backup)?;
+                journal::clear(&tx)?;
+                result
+            }
+            ImportMode::Merge => merge_into(&tx, backup)?,
+        };
+
+        tx.commit()?;
+
+        Ok(result)
+    })
+}
+
+fn is_empty(conn: &Connection) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT (SELECT COUNT(*) FROM decks) + (SELECT COUNT(*) FROM tags) + (SELECT COUNT(*) FROM delete_stack)",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count == 0)
+}
+
+fn insert_deck(conn: &Connection, d: &Deck) -> Result<()> {
+    conn.execute(
+        "INSERT INTO decks (id, name, sort_order, created_at, updated_at, deleted_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            &d.id,
+            &d.name,
+            d.sort_order.to_db_value(),
+            d.created_at.to_rfc3339(),
+            d.updated_at.to_rfc3339(),
+            d.deleted_at.map(|dt| dt.to_rfc3339()),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_column(conn: &Connection, c: &Column, key: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at, deleted_at, deleted_with_deck, position_updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            &c.id,
+            &c.deck_id,
+            &c.name,
+            key,
+            c.created_at.to_rfc3339(),
+            c.updated_at.to_rfc3339(),
+            c.deleted_at.map(|dt| dt.to_rfc3339()),
+            c.deleted_with_deck as i32,
+            c.position_updated_at.map(|dt| dt.to_rfc3339()),
+        ],
+    )?;
+    Ok(())
+}
+
+fn insert_card(conn: &Connection, c: &Card, key: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO cards (id, column_id, content, score, rank, created_at, updated_at, deleted_at, deleted_with_column, deleted_with_deck, position_updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            &c.id,
+            &c.column_id,
+            &c.content,
+            c.score,
+            key,
+            c.created_at.to_rfc3339(),
+            c.updated_at.to_rfc3339(),
+            c.deleted_at.map(|dt| dt.to_rfc3339()),
+            c.deleted_with_column as i32,
+            c.deleted_with_deck as i32,
+            c.position_updated_at.map(|dt| dt.to_rfc3339()),
+        ],
+    )?;
+    Ok(())
+}
+
+/// 空の DB に ID も含めてそのまま復元する
+fn restore_into_empty(conn: &Connection, backup: &Backup) -> Result<ImportResult> {
+    if !is_empty(conn)? {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot restore a backup into a non-empty database (use merge instead)".to_string(),
+        ));
+    }
+
+    for d in &backup.decks {
+        insert_deck(conn, d)?;
+    }
+    // バックアップの並び順のまま親ごとの末尾に追加していく
+    for c in &backup.columns {
+        let key = rank::append(conn, "columns", "deck_id", &c.deck_id, &c.id)?;
+        insert_column(conn, c, &key)?;
+    }
+    for c in &backup.cards {
+        let key = rank::append(conn, "cards", "column_id", &c.column_id, &c.id)?;
+        insert_card(conn, c, &key)?;
+    }
+    // 名前順なので親タグ（`a`）が子タグ（`a/b`）より先に入る
+    for t in &backup.tags {
+        conn.execute(
+            "INSERT INTO tags (id, name, parent_id) VALUES (?1, ?2, ?3)",
+            params![&t.id, &t.name, &t.parent_id],
+        )?;
+    }
+    for ct in &backup.card_tags {
+        tag::associate_tag(conn, &ct.card_id, &ct.tag_id)?;
+    }
+    for entry in &backup.delete_stack {
+        conn.execute(
+            "INSERT INTO delete_stack (id, deck_id, entity_type, entity_id, deleted_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                entry.id,
+                &entry.deck_id,
+                entry.entity_type.to_db_value(),
+                &entry.entity_id,
+                entry.deleted_at.to_rfc3339(),
+            ],
+        )?;
+    }
+    for r in &backup.revisions {
+        conn.execute(
+            "INSERT INTO card_revisions (id, card_id, content, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![r.id, &r.card_id, &r.content, r.created_at.to_rfc3339()],
+        )?;
+    }
+
+    let inserted = |n: usize| ImportCounts {
+        inserted: n,
+        ..Default::default()
+    };
+
+    Ok(ImportResult {
+        decks: inserted(backup.decks.len()),
+        columns: inserted(backup.columns.len()),
+        cards: inserted(backup.cards.len()),
+    })
+}
+
+/// 既存のエンティティの updated_at を取得する（存在しなければ None）
+fn local_updated_at(conn: &Connection, table: &str, id: &str) -> Result<Option<String>> {
+    let updated_at = conn
+        .query_row(
+            &format!("SELECT updated_at FROM {} WHERE id = ?1", table),
+            params![id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    Ok(updated_at)
+}
+
+/// 既存の Column / Card の最後の変更日時（updated_at と position_updated_at の新しい方）
+///
+/// どちらも UTC の RFC 3339 で書いているので文字列のまま比べられる。
+fn local_changed_at(conn: &Connection, table: &str, id: &str) -> Result<Option<String>> {
+    let changed_at = conn
+        .query_row(
+            &format!(
+                "SELECT CASE WHEN position_updated_at > updated_at THEN position_updated_at ELSE updated_at END FROM {} WHERE id = ?1",
+                table
+            ),
+            params![id],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    Ok(changed_at)
+}
+
+/// 内容と並び順のどちらかを最後に変えた日時
+fn changed_at(updated_at: DateTime<Utc>, position_updated_at: Option<DateTime<Utc>>) -> DateTime<Utc> {
+    position_updated_at.map_or(updated_at, |dt| dt.max(updated_at))
+}
+
+/// バックアップ側の方が新しいか
+fn is_newer(incoming: &DateTime<Utc>, local: &str) -> bool {
+    chrono::DateTime::parse_from_rfc3339(local)
+        .map(|dt| *incoming > dt.with_timezone(&Utc))
+        .unwrap_or(true)
+}
+
+/// 既存の DB にマージする
+///
+/// ULID は DB をまたいで一意なので、同じ ID は同じエンティティとみなし、updated_at が新しい方を残す。
+/// Column / Card は並び順の変更（position_updated_at）も含めて新しい方を残す。
+/// 取り込んだ Card のタグは本文から付け直す。取り込んだ Column / Card は position の位置に入れる。
+fn merge_into(conn: &Connection, backup: &Backup) -> Result<ImportResult> {
+    let mut result = ImportResult::default();
+
+    for d in &backup.decks {
+        match local_updated_at(conn, "decks", &d.id)? {
+            None => {
+                insert_deck(conn, d)?;
+                result.decks.inserted += 1;
+            }
+            Some(local) if is_newer(&d.updated_at, &local) => {
+                conn.execute(
+                    "UPDATE decks SET name = ?1, sort_order = ?2, updated_at = ?3, deleted_at = ?4 WHERE id = ?5",
+                    params![
+                        &d.name,
+                        d.sort_order.to_db_value(),
+                        d.updated_at.to_rfc3339(),
+                        d.deleted_at.map(|dt| dt.to_rfc3339()),
+                        &d.id,
+                    ],
+                )?;
+                result.decks.updated += 1;
+            }
+            Some(_) => result.decks.skipped += 1,
+        }
+    }
+
+    for c in &backup.columns {
+        match local_changed_at(conn, "columns", &c.id)? {
+            None => {
+                let key = rank::for_index(conn, "columns", "deck_id", &c.deck_id, c.position, &c.id)?;
+                insert_column(conn, c, &key)?;
+                result.columns.inserted += 1;
+            }
+            Some(local) if is_newer(&changed_at(c.updated_at, c.position_updated_at), &local) => {
+                let key = rank::for_index(conn, "columns", "deck_id", &c.deck_id, c.position, &c.id)?;
+                conn.execute(
+                    "UPDATE columns SET deck_id = ?1, name = ?2, rank = ?3, updated_at = ?4, deleted_at = ?5, deleted_with_deck = ?6, position_updated_at = ?7 WHERE id = ?8",
+                    params![
+                        &c.deck_id,
+                        &c.name,
+                        key,
+                        c.updated_at.to_rfc3339(),
+                        c.deleted_at.map(|dt| dt.to_rfc3339()),
+                        c.deleted_with_deck as i32,
+                        c.position_updated_at.map(|dt| dt.to_rfc3339()),
+                        &c.id,
+                    ],
+                )?;
+                result.columns.updated += 1;
+            }
+            Some(_) => result.columns.skipped += 1,
+        }
+    }
+
+    for c in &backup.cards {
+        match local_changed_at(conn, "cards", &c.id)? {
+            None => {
+                let key = rank::for_index(conn, "cards", "column_id", &c.column_id, c.position, &c.id)?;
+                insert_card(conn, c, &key)?;
+                result.cards.inserted += 1;
+            }
+            Some(local) if is_newer(&changed_at(c.updated_at, c.position_updated_at), &local) => {
+                let key = rank::for_index(conn, "cards", "column_id", &c.column_id, c.position, &c.id)?;
+                let before: String =
+                    conn.query_row("SELECT content FROM cards WHERE id = ?1", params![&c.id], |row| row.get(0))?;
+                card::save_revision(conn, &c.id, &before, &c.content, Utc::now(), Duration::zero())?;
+                conn.execute(
+                    "UPDATE cards SET column_id = ?1, content = ?2, score = ?3, rank = ?4, updated_at = ?5, deleted_at = ?6, deleted_with_column = ?7, deleted_with_deck = ?8, position_updated_at = ?9 WHERE id = ?10",
+                    params![
+                        &c.column_id,
+                        &c.content,
+                        c.score,
+                        key,
+                        c.updated_at.to_rfc3339(),
+                        c.deleted_at.map(|dt| dt.to_rfc3339()),
+                        c.deleted_with_column as i32,
+                        c.deleted_with_deck as i32,
+                        c.position_updated_at.map(|dt| dt.to_rfc3339()),
+                        &c.id,
+                    ],
+                )?;
+                result.cards.updated += 1;
+            }
+            Some(_) => {
+                result.cards.skipped += 1;
+                continue;
+            }
+        }
+        tag::sync_card_tags(conn, &c.id, &c.content)?;
+    }
+
+    merge_delete_stack(conn, &backup.delete_stack)?;
+    merge_revisions(conn, &backup.revisions)?;
+
+    Ok(result)
+}
+
+/// 削除済みのまま取り込んだエンティティのエントリを削除スタックに積む（古い順、重複は積まない）
+fn merge_delete_stack(conn: &Connection, entries: &[DeleteStackEntry]) -> Result<()> {
+    for entry in entries {
+        let table = match entry.entity_type {
+            DeletedEntityType::Card => "cards",
+            DeletedEntityType::Column => "columns",
+        };
+        conn.execute(
+            &format!(
+                "INSERT INTO delete_stack (deck_id, entity_type, entity_id, deleted_at)
+                 SELECT ?1, ?2, ?3, ?4
+                 WHERE EXISTS (SELECT 1 FROM {} WHERE id = ?3 AND deleted_at IS NOT NULL)
+                   AND NOT EXISTS (SELECT 1 FROM delete_stack WHERE entity_type = ?2 AND entity_id = ?3)",
+                table
+            ),
+            params![
+                &entry.deck_id,
+                entry.entity_type.to_db_value(),
+                &entry.entity_id,
+                entry.deleted_at.to_rfc3339(),
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// 編集履歴を取り込む（同じ Card・同じ日時の履歴は既にあるものとして積まない）
+fn merge_revisions(conn: &Connection, revisions: &[CardRevision]) -> Result<()> {
+    for r in revisions {
+        conn.execute(
+            "INSERT INTO card_revisions (card_id, content, created_at)
+             SELECT ?1, ?2, ?3
+             WHERE EXISTS (SELECT 1 FROM cards WHERE id = ?1)
+               AND NOT EXISTS (SELECT 1 FROM card_revisions WHERE card_id = ?1 AND created_at = ?3)",
+            params![&r.card_id, &r.content, r.created_at.to_rfc3339()],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_in_memory;
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::{card, column, deck, delete_stack, search};
+
+    /// 削除済みのものを含む一通りのデータを作る
+    fn setup() -> (Connection, String) {
+        let conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Ideas".to_string(),
+                sort_order: SortOrder::ScoreDesc,
+            },
+        )
+        .unwrap();
+        let inbox = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Inbox".to_string(),
+            },
+        )
+        .unwrap();
+        let archive = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Archive".to_string(),
+            },
+        )
+        .unwrap();
+
+        let mut ids = Vec::new();
+        for content in ["first #idea", "second #idea #todo", "third"] {
+            let c = card::create(
+                &conn,
+                NewCard {
+                    column_id: inbox.id.clone(),
+                    content: content.to_string(),
+                },
+            )
+            .unwrap();
+            ids.push(c.id);
+        }
+        card::create(
+            &conn,
+            NewCard {
+                column_id: archive.id.clone(),
+                content: "old #done".to_string(),
+            },
+        )
+        .unwrap();
+
+        card::update_score(&conn, &ids[0], 4).unwrap();
+        card::update_content(&conn, &ids[1], "second #idea #todo (edited)").unwrap();
+        card::soft_delete(&conn, &ids[2]).unwrap();
+        column::soft_delete(&conn, &archive.id).unwrap();
+
+        (conn, d.id)
+    }
+
+    #[test]
+    fn test_restore_roundtrip() {
+        let (conn, _) = setup();
+        let backup = create_backup(&conn).unwrap();
+        assert_eq!(backup.decks.len(), 1);
+        assert_eq!(backup.columns.len(), 2);
+        assert_eq!(backup.cards.len(), 4);
+        assert_eq!(backup.delete_stack.len(), 2);
+
+        let json = backup_to_json(&conn).unwrap();
+        let target = create_in_memory().unwrap();
+        // 取り消した操作だけが残っている空の DB に復元する
+        deck::create(
+            &target,
+            NewDeck {
+                name: "Scratch".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        journal::undo(&target).unwrap();
+        let result = restore_from_json(&target, &json, ImportMode::Restore).unwrap();
+        assert_eq!(result.cards.inserted, 4);
+
+        // 復元した DB のバックアップは元と一致する
+        let restored = create_backup(&target).unwrap();
+        assert_eq!(
+            serde_json::to_value(&restored.decks).unwrap(),
+            serde_json::to_value(&backup.decks).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&restored.columns).unwrap(),
+            serde_json::to_value(&backup.columns).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&restored.cards).unwrap(),
+            serde_json::to_value(&backup.cards).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&restored.tags).unwrap(),
+            serde_json::to_value(&backup.tags).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&restored.card_tags).unwrap(),
+            serde_json::to_value(&backup.card_tags).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(&restored.delete_stack).unwrap(),
+            serde_json::to_value(&backup.delete_stack).unwrap()
+        );
+        assert_eq!(restored.revisions.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&restored.revisions).unwrap(),
+            serde_json::to_value(&backup.revisions).unwrap()
+        );
+
+        // 読み込みはジャーナルに残らず、復元前の操作も消える
+        assert!(journal::list(&target, 10).unwrap().is_empty());
+
+        // 全文検索の索引と Column ごとの復元も引き継がれる
+        let deck_id = &backup.decks[0].id;
+        assert_eq!(search::search(&target, deck_id, "second", 10).unwrap().len(), 1);
+        let entry = delete_stack::undo_last(&target, deck_id).unwrap().unwrap();
+        assert_eq!(entry.entity_type, DeletedEntityType::Column);
+        let archived = card::get_by_column_id(&target, &entry.entity_id).unwrap();
+        assert_eq!(archived.len(), 1);
+        assert!(!archived[0].deleted_with_column);
+    }
+
+    #[test]
+    fn test_restore_requires_empty_database() {
+        let (conn, _) = setup();
+        let backup = create_backup(&conn).unwrap();
+
+        let result = restore_backup(&conn, &backup, ImportMode::Restore);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_reject_newer_format() {
+        let (conn, _) = setup();
+        let mut backup = create_backup(&conn).unwrap();
+        backup.format_version = BACKUP_FORMAT_VERSION + 1;
+
+        let target = create_in_memory().unwrap();
+        let result = restore_backup(&target, &backup, ImportMode::Restore);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+
+        let result = restore_from_json(&target, "{ not json", ImportMode::Restore);
+        assert!(matches!(result, Err(JotDeckError::Serialization(_))));
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let (conn, deck_id) = setup();
+        let backup = create_backup(&conn).unwrap();
+        let inbox = column::get_by_deck_id(&conn, &deck_id).unwrap().remove(0);
+        let cards = card::get_by_column_id(&conn, &inbox.id).unwrap();
+
+        // バックアップより新しいローカルの変更と、ローカルより新しいバックアップ側の変更
+        let mut backup = backup;
+        card::update_content(&conn, &cards[0].id, "first edited locally #local").unwrap();
+        let incoming = backup.cards.iter_mut().find(|c| c.id == cards[1].id).unwrap();
+        incoming.content = "second from backup #merged".to_string();
+        incoming.updated_at = Utc::now() + chrono::Duration::seconds(60);
+
+        let operations = journal::list(&conn, 100).unwrap().len();
+        let result = restore_backup(&conn, &backup, ImportMode::Merge).unwrap();
+        assert_eq!(journal::list(&conn, 100).unwrap().len(), operations);
+        assert_eq!(result.decks.skipped, 1);
+        assert_eq!(result.cards.updated, 1);
+        assert_eq!(result.cards.skipped, 3);
+        assert_eq!(result.cards.inserted, 0);
+
+        assert_eq!(card::get_by_id(&conn, &cards[0].id).unwrap().content, "first edited locally #local");
+        assert_eq!(card::get_by_id(&conn, &cards[1].id).unwrap().content, "second from backup #merged");
+        let tags: Vec<_> = tag::get_tags_by_card(&conn, &cards[1].id)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(tags, vec!["merged"]);
+        let history = card::history(&conn, &cards[1].id).unwrap();
+        assert_eq!(history[0].content, "second #idea #todo (edited)");
+
+        // 削除スタックは重複しない
+        assert_eq!(delete_stack::list_stack(&conn, &deck_id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_new_entities() {
+        let (source, deck_id) = setup();
+        let backup = create_backup(&source).unwrap();
+
+        // 別の DB で作業していたところにマージする
+        let (target, local_deck_id) = setup();
+        let result = restore_backup(&target, &backup, ImportMode::Merge).unwrap();
+        assert_eq!(result.decks.inserted, 1);
+        assert_eq!(result.columns.inserted, 2);
+        assert_eq!(result.cards.inserted, 4);
+
+        assert_eq!(deck::get_all(&target).unwrap().len(), 2);
+        assert_eq!(delete_stack::list_stack(&target, &deck_id).unwrap().len(), 2);
+        assert_eq!(delete_stack::list_stack(&target, &local_deck_id).unwrap().len(), 2);
+
+        // タグは名前で既存のものに統合される
+        let tag_count: i64 = target
+            .query_row("SELECT COUNT(*) FROM tags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tag_count, 3);
+        assert_eq!(tag::get_cards_by_tag(&target, &deck_id, "idea").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_merge_renumbers_positions() {
+        let (conn, deck_id) = setup();
+        let inbox = column::get_by_deck_id(&conn, &deck_id).unwrap().remove(0);
+
+        // 同じ Column に別の DB で追加された Card を取り込む
+        let mut backup = create_backup(&conn).unwrap();
+        let mut extra = backup.cards[0].clone();
+        extra.id = ulid::Ulid::new().to_string();
+        extra.column_id = inbox.id.clone();
+        extra.content = "added elsewhere".to_string();
+        extra.position = 0;
+        extra.deleted_at = None;
+        backup.cards.push(extra.clone());
+
+        restore_backup(&conn, &backup, ImportMode::Merge).unwrap();
+
+        let positions: Vec<i32> = card::get_by_column_id(&conn, &inbox.id)
+            .unwrap()
+            .iter()
+            .map(|c| c.position)
+            .collect();
+        assert_eq!(positions, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_write_and_read_file() {
+        let (conn, deck_id) = setup();
+        let path = std::env::temp_dir().join(format!("jot-deck-backup-{}.json", ulid::Ulid::new()));
+
+        write_backup(&conn, &path).unwrap();
+        let target = create_in_memory().unwrap();
+        restore_backup_file(&target, &path, ImportMode::Restore).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(deck::get_by_id(&target, &deck_id).unwrap().sort_order, SortOrder::ScoreDesc);
+    }
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use jot_deck_core::export::{self, MarkdownOptions};
+use jot_deck_core::{card, column, deck, search, ConnectionPool, NewCard, NewColumn, NewDeck, SortOrder};
+
+/// Deck に入れる Card の枚数
+const CARDS: usize = 2_000;
+/// 書き込みを計測する回数
+const ITERATIONS: usize = 200;
+/// 読み取りの負荷をかけるスレッド数
+const LOAD_THREADS: usize = 2;
+/// 負荷をかけるスレッドが読み取りの合間に休む時間
+const LOAD_PAUSE: Duration = Duration::from_millis(5);
+
+/// 2,000 枚の Card を持つ Deck を作る
+fn setup(pool: &ConnectionPool) -> (String, Vec<String>) {
+    let conn = pool.writer().unwrap();
+    let d = deck::create(
+        &conn,
+        NewDeck {
+            name: "Bench".to_string(),
+            sort_order: SortOrder::Manual,
+        },
+    )
+    .unwrap();
+    let col = column::create(
+        &conn,
+        NewColumn {
+            deck_id: d.id.clone(),
+            name: "Bench".to_string(),
+        },
+    )
+    .unwrap();
+
+    let tx = conn.unchecked_transaction().unwrap();
+    let ids = (0..CARDS)
+        .map(|i| {
+            card::create(
+                &tx,
+                NewCard {
+                    column_id: col.id.clone(),
+                    content: format!("card {} #bench note {}", i, i % 100),
+                },
+            )
+            .unwrap()
+            .id
+        })
+        .collect();
+    tx.commit().unwrap();
+
+    (d.id, ids)
+}
+
+fn report(name: &str, mut samples: Vec<Duration>) {
+    samples.sort();
+    let percentile = |p: usize| samples[(samples.len() - 1) * p / 100].as_secs_f64() * 1e6;
+    println!(
+        "{:<40} p50 {:>9.1} µs  p99 {:>9.1} µs  max {:>9.1} µs",
+        name,
+        percentile(50),
+        percentile(99),
+        percentile(100)
+    );
+}
+
+/// 読み取りの負荷（エクスポートと全文検索）をかけながら書き込みと読み取りの待ち時間を計る
+fn run(name: &str, readers: usize) {
+    let path = std::env::temp_dir().join(format!("jot-deck-bench-{}.db", ulid::Ulid::new()));
+    let pool = ConnectionPool::open(path.to_str().unwrap(), readers).unwrap();
+    let (deck_id, ids) = setup(&pool);
+    let stop = AtomicBool::new(false);
+
+    thread::scope(|s| {
+        for _ in 0..LOAD_THREADS {
+            s.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    {
+                        let conn = pool.reader().unwrap();
+                        export::deck_to_markdown(&conn, &deck_id, &MarkdownOptions::default()).unwrap();
+                        search::search(&conn, &deck_id, "note 42", 50).unwrap();
+                    }
+                    // 接続を手放して他のスレッドに順番を回す
+                    thread::sleep(LOAD_PAUSE);
+                }
+            });
+        }
+
+        let writes = (0..ITERATIONS)
+            .map(|i| {
+                let start = Instant::now();
+                let conn = pool.writer().unwrap();
+                card::update_content(&conn, &ids[i * 7 % ids.len()], &format!("edited {}", i)).unwrap();
+                start.elapsed()
+            })
+            .collect();
+        report(&format!("{}: update_content", name), writes);
+
+        let reads = (0..ITERATIONS)
+            .map(|i| {
+                let start = Instant::now();
+                let conn = pool.reader().unwrap();
+                card::get_by_id(&conn, &ids[i * 13 % ids.len()]).unwrap();
+                start.elapsed()
+            })
+            .collect();
+        report(&format!("{}: get_by_id", name), reads);
+
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    drop(pool);
+    std::fs::remove_file(&path).unwrap();
+}
+
+fn main() {
+    run("single connection", 0);
+    run(&format!("pool ({} readers)", LOAD_THREADS + 1), LOAD_THREADS + 1);
+}
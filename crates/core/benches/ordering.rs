@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use jot_deck_core::{card, column, create_in_memory, deck, Connection, NewCard, NewColumn, NewDeck, SortOrder};
+
+/// Column に入れる Card の枚数
+const CARDS: usize = 10_000;
+/// 1 つの操作を繰り返す回数
+const ITERATIONS: u32 = 200;
+
+/// 10,000 枚の Card を持つ Column を作る
+fn setup() -> (Connection, String, Vec<String>) {
+    let conn = create_in_memory().unwrap();
+    let d = deck::create(
+        &conn,
+        NewDeck {
+            name: "Bench".to_string(),
+            sort_order: SortOrder::Manual,
+        },
+    )
+    .unwrap();
+    let col = column::create(
+        &conn,
+        NewColumn {
+            deck_id: d.id,
+            name: "Bench".to_string(),
+        },
+    )
+    .unwrap();
+
+    let tx = conn.unchecked_transaction().unwrap();
+    let ids = (0..CARDS)
+        .map(|i| {
+            card::create(
+                &tx,
+                NewCard {
+                    column_id: col.id.clone(),
+                    content: format!("card {}", i),
+                },
+            )
+            .unwrap()
+            .id
+        })
+        .collect();
+    tx.commit().unwrap();
+
+    (conn, col.id, ids)
+}
+
+fn report(name: &str, elapsed: Duration, iterations: u32) {
+    println!(
+        "{:<32} {:>10.1} µs/op ({} ops)",
+        name,
+        elapsed.as_secs_f64() * 1e6 / iterations as f64,
+        iterations
+    );
+}
+
+fn bench<F: FnMut(u32)>(name: &str, iterations: u32, mut f: F) {
+    let start = Instant::now();
+    for i in 0..iterations {
+        f(i);
+    }
+    report(name, start.elapsed(), iterations);
+}
+
+fn main() {
+    let start = Instant::now();
+    let (conn, column_id, ids) = setup();
+    report("create (append)", start.elapsed(), CARDS as u32);
+
+    let n = ids.len();
+
+    bench("move_to_position: to first", ITERATIONS, |i| {
+        card::move_to_position(&conn, &ids[n - 1 - i as usize], 0).unwrap();
+    });
+
+    bench("move_to_position: to last", ITERATIONS, |i| {
+        card::move_to_position(&conn, &ids[i as usize], n as i32).unwrap();
+    });
+
+    bench("move_to_position: to middle", ITERATIONS, |i| {
+        card::move_to_position(&conn, &ids[n / 4 + i as usize], (n / 2) as i32).unwrap();
+    });
+
+    bench("create_at_position: first", ITERATIONS, |i| {
+        card::create_at_position(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: format!("front {}", i),
+            },
+            0,
+        )
+        .unwrap();
+    });
+
+    bench("soft_delete + restore: middle", ITERATIONS, |i| {
+        let id = &ids[n / 2 + i as usize];
+        card::soft_delete(&conn, id).unwrap();
+        card::restore(&conn, id).unwrap();
+    });
+
+    bench("get_by_id", ITERATIONS, |i| {
+        card::get_by_id(&conn, &ids[i as usize * 37 % n]).unwrap();
+    });
+
+    bench("get_by_column_id (10k cards)", 20, |_| {
+        card::get_by_column_id(&conn, &column_id).unwrap();
+    });
+}
@@ -2,8 +2,8 @@ use std::env;
 use std::io::{self, BufRead, Write};
 
 use jot_deck_core::{
-    card, column, create_file_db, deck, run_cleanup_batch, tag, NewCard, NewColumn, NewDeck,
-    SortOrder,
+    backup, card, cleanup, column, create_file_db, db, deck, delete_stack, export, journal, maintenance, search, tag,
+    CleanupPolicy, NewCard, NewColumn, NewDeck, SortOrder, UndoResult,
 };
 
 fn main() {
@@ -83,7 +83,11 @@ fn main() {
                                 Ok(columns) => {
                                     for col in columns {
                                         println!("  [{}] {} (pos: {})", col.id, col.name, col.position);
-                                        match card::get_by_column_id(&conn, &col.id) {
+                                        match card::get_by_column_id_sorted(
+                                            &conn,
+                                            &col.id,
+                                            d.sort_order,
+                                        ) {
                                             Ok(cards) => {
                                                 for c in cards {
                                                     let preview: String =
@@ -107,9 +111,20 @@ fn main() {
                     println!("Usage: deck-show <deck_id>");
                 }
             }
+            "deck-sort" => {
+                if let (Some(id), Some(order)) = (parts.get(1), parts.get(2)) {
+                    let sort_order = SortOrder::from_db_value(order);
+                    match deck::update(&conn, id, None, Some(sort_order)) {
+                        Ok(d) => println!("Deck {} sort: {:?}", d.id, d.sort_order),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: deck-sort <deck_id> <created_desc|created_asc|score_desc|score_asc|manual>");
+                }
+            }
             "deck-delete" | "dd" => {
                 if let Some(id) = parts.get(1) {
-                    match deck::delete(&conn, id) {
+                    match deck::soft_delete(&conn, id) {
                         Ok(()) => println!("Deleted deck: {}", id),
                         Err(e) => println!("Error: {}", e),
                     }
@@ -117,6 +132,28 @@ fn main() {
                     println!("Usage: deck-delete <deck_id>");
                 }
             }
+            "deck-restore" => {
+                if let Some(id) = parts.get(1) {
+                    match deck::restore(&conn, id) {
+                        Ok(d) => println!("Restored deck: {} ({})", d.name, d.id),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: deck-restore <deck_id>");
+                }
+            }
+            "deck-trash" => match deck::get_deleted(&conn) {
+                Ok(decks) => {
+                    if decks.is_empty() {
+                        println!("No deleted decks.");
+                    } else {
+                        for d in decks {
+                            println!("  {} - {} (deleted: {:?})", d.id, d.name, d.deleted_at);
+                        }
+                    }
+                }
+                Err(e) => println!("Error: {}", e),
+            },
 
             // Column commands
             "col-new" | "cn" => {
@@ -181,6 +218,26 @@ fn main() {
                     println!("Usage: col-move <column_id> <position>");
                 }
             }
+            "col-move-deck" => {
+                if let (Some(id), Some(deck_id)) = (parts.get(1), parts.get(2)) {
+                    match column::move_to_deck(&conn, id, deck_id) {
+                        Ok(col) => println!("Moved column {} to deck {}", col.id, deck_id),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: col-move-deck <column_id> <deck_id>");
+                }
+            }
+            "col-dup" => {
+                if let (Some(id), Some(deck_id)) = (parts.get(1), parts.get(2)) {
+                    match column::duplicate(&conn, id, deck_id) {
+                        Ok(col) => println!("Duplicated column: {}", col.id),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: col-dup <column_id> <deck_id>");
+                }
+            }
 
             // Card commands
             "card-new" | "an" => {
@@ -278,16 +335,110 @@ fn main() {
             }
             "card-movecol" => {
                 if let (Some(id), Some(col_id)) = (parts.get(1), parts.get(2)) {
-                    match card::move_to_column(&conn, id, col_id) {
-                        Ok(c) => println!("Moved card {} to column {}", c.id, col_id),
+                    let result = match parts.get(3).map(|p| p.parse::<i32>()) {
+                        Some(Ok(position)) => card::move_to(&conn, id, col_id, position),
+                        Some(Err(_)) => {
+                            println!("Invalid position");
+                            continue;
+                        }
+                        None => card::move_to_column(&conn, id, col_id),
+                    };
+                    match result {
+                        Ok(c) => println!("Moved card {} to column {} at {}", c.id, col_id, c.position),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: card-movecol <card_id> <column_id> [position]");
+                }
+            }
+            "card-dup" => {
+                if let (Some(id), Some(col_id)) = (parts.get(1), parts.get(2)) {
+                    match card::duplicate(&conn, id, col_id) {
+                        Ok(c) => println!("Duplicated card: {}", c.id),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: card-dup <card_id> <column_id>");
+                }
+            }
+            "card-history" => {
+                if let Some(id) = parts.get(1) {
+                    match card::history(&conn, id) {
+                        Ok(revisions) if revisions.is_empty() => println!("No revisions."),
+                        Ok(revisions) => {
+                            for r in revisions {
+                                let preview: String = r.content.chars().take(50).collect();
+                                println!("  #{} ({}) - {}", r.id, r.created_at, preview);
+                            }
+                        }
                         Err(e) => println!("Error: {}", e),
                     }
                 } else {
-                    println!("Usage: card-movecol <card_id> <column_id>");
+                    println!("Usage: card-history <card_id>");
+                }
+            }
+            "card-revert" => {
+                if let (Some(id), Some(rev)) = (parts.get(1), parts.get(2)) {
+                    if let Ok(rev) = rev.parse::<i64>() {
+                        match card::revert_to(&conn, id, rev) {
+                            Ok(c) => println!("Reverted card {} to revision #{}", c.id, rev),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    } else {
+                        println!("Invalid revision");
+                    }
+                } else {
+                    println!("Usage: card-revert <card_id> <revision_id>");
+                }
+            }
+            "card-merge" => {
+                let mut score = card::ScoreMerge::Sum;
+                let mut ids = Vec::new();
+                for arg in &parts[1..] {
+                    match *arg {
+                        "--max" => score = card::ScoreMerge::Max,
+                        id => ids.push(id),
+                    }
+                }
+                if ids.len() >= 2 {
+                    match card::merge(&conn, &ids, "\n\n", score) {
+                        Ok(c) => println!("Merged into card: {} (score {})", c.id, c.score),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: card-merge <id> <id>... [--max]");
+                }
+            }
+            "card-split" => {
+                if let (Some(id), Some(at)) = (parts.get(1), parts.get(2)) {
+                    if let Ok(at) = at.parse::<usize>() {
+                        match card::split(&conn, id, at) {
+                            Ok((_, tail)) => println!("Split off card: {}", tail.id),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    } else {
+                        println!("Invalid position");
+                    }
+                } else {
+                    println!("Usage: card-split <id> <char_offset>");
                 }
             }
 
             // Tag commands
+            "tags" | "t" if parts.get(2) == Some(&"--tree") => {
+                fn print_tree(nodes: &[tag::TagNode], depth: usize) {
+                    for node in nodes {
+                        println!("  {}#{}", "  ".repeat(depth), node.tag.name);
+                        print_tree(&node.children, depth + 1);
+                    }
+                }
+
+                match tag::get_tag_tree_by_deck(&conn, parts[1]) {
+                    Ok(tree) if tree.is_empty() => println!("No tags found."),
+                    Ok(tree) => print_tree(&tree, 0),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
             "tags" | "t" => {
                 if let Some(deck_id) = parts.get(1) {
                     match tag::get_tags_by_deck(&conn, deck_id) {
@@ -306,6 +457,16 @@ fn main() {
                     println!("Usage: tags <deck_id>");
                 }
             }
+            "tags-live" => match tag::get_all_live(&conn) {
+                Ok(tags) if tags.is_empty() => println!("No tags found."),
+                Ok(tags) => {
+                    for t in tags {
+                        let count = tag::live_card_count(&conn, &t.id).unwrap_or(0);
+                        println!("  #{} ({} cards)", t.name, count);
+                    }
+                }
+                Err(e) => println!("Error: {}", e),
+            },
             "tag-search" | "ts" => {
                 if let (Some(deck_id), Some(tag_name)) = (parts.get(1), parts.get(2)) {
                     match tag::get_cards_by_tag(&conn, deck_id, tag_name) {
@@ -328,6 +489,98 @@ fn main() {
                     println!("Usage: tag-search <deck_id> <tag_name>");
                 }
             }
+            "tag-stats" => {
+                if let Some(deck_id) = parts.get(1) {
+                    match tag::stats(&conn, deck_id) {
+                        Ok(stats) => {
+                            for u in &stats.tags {
+                                println!(
+                                    "  #{} - {} card(s), score {}, {} .. {}",
+                                    u.tag.name, u.card_count, u.total_score, u.first_used_at, u.last_used_at
+                                );
+                            }
+                            if !stats.cooccurrences.is_empty() {
+                                println!("Co-occurrence:");
+                                for p in &stats.cooccurrences {
+                                    println!("  #{} + #{}: {}", p.tag_a, p.tag_b, p.count);
+                                }
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: tag-stats <deck_id>");
+                }
+            }
+            "tag-query" | "tq" => {
+                if let (Some(deck_id), Some(expr)) = (parts.get(1), parts.get(2..)) {
+                    let expr = expr.join(" ");
+                    match tag::query_cards(&conn, deck_id, &expr) {
+                        Ok(cards) => {
+                            if cards.is_empty() {
+                                println!("No cards match {}", expr);
+                            } else {
+                                for c in cards {
+                                    let preview: String = c.content.chars().take(50).collect();
+                                    println!("  {} - {}", c.id, preview);
+                                }
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: tag-query <deck_id> <expr>  (e.g. idea & !done | urgent)");
+                }
+            }
+            "tag-rename" | "tag-merge" => {
+                if let (Some(deck_id), Some(from), Some(into)) = (parts.get(1), parts.get(2), parts.get(3)) {
+                    let dry_run = parts.get(4) == Some(&"--dry-run");
+                    let result = if cmd == "tag-rename" {
+                        tag::rename(&conn, deck_id, from, into, dry_run)
+                    } else {
+                        tag::merge(&conn, deck_id, from, into, dry_run)
+                    };
+                    match result {
+                        Ok(rewrites) => {
+                            let verb = if dry_run { "Would update" } else { "Updated" };
+                            println!("{} {} card(s): #{} -> #{}", verb, rewrites.len(), from, into);
+                            for r in rewrites {
+                                let preview: String = r.after.chars().take(50).collect();
+                                println!("  {} - {}", r.card_id, preview);
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: {} <deck_id> <from> <to> [--dry-run]", cmd);
+                }
+            }
+
+            // Search commands
+            "search" | "s" => {
+                if let (Some(deck_id), Some(query)) = (parts.get(1), parts.get(2..)) {
+                    let query = query.join(" ");
+                    match search::search(&conn, deck_id, &query, 20) {
+                        Ok(results) => {
+                            if results.is_empty() {
+                                println!("No cards found for \"{}\"", query);
+                            } else {
+                                for r in results {
+                                    let snippet = r
+                                        .snippet
+                                        .replace(search::HIGHLIGHT_START, "[")
+                                        .replace(search::HIGHLIGHT_END, "]")
+                                        .replace('\n', " ");
+                                    println!("  {} - {}", r.card.id, snippet);
+                                }
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: search <deck_id> <query>");
+                }
+            }
 
             // Trash commands
             "trash" => {
@@ -363,21 +616,226 @@ fn main() {
                         }
                         Err(e) => println!("Error: {}", e),
                     }
+
+                    println!("\nDelete stack (newest first):");
+                    match delete_stack::list_stack(&conn, deck_id) {
+                        Ok(entries) => {
+                            for entry in entries {
+                                println!(
+                                    "  #{} {:?} {} (deleted: {})",
+                                    entry.id, entry.entity_type, entry.entity_id, entry.deleted_at
+                                );
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
                 } else {
                     println!("Usage: trash <deck_id>");
                 }
             }
-
-            // Cleanup
-            "cleanup" => match run_cleanup_batch(&mut conn) {
-                Ok(result) => {
-                    println!("Cleanup complete:");
-                    println!("  Deleted columns: {}", result.deleted_columns);
-                    println!("  Deleted cards: {}", result.deleted_cards);
-                    println!("  Deleted orphan tags: {}", result.deleted_orphan_tags);
+            "undo" | "u" => {
+                if let Some(deck_id) = parts.get(1) {
+                    match delete_stack::undo_last(&conn, deck_id) {
+                        Ok(Some(entry)) => {
+                            println!("Restored {:?}: {}", entry.entity_type, entry.entity_id)
+                        }
+                        Ok(None) => println!("Nothing to undo."),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: undo <deck_id>");
                 }
+            }
+            "op-undo" => match journal::undo(&conn) {
+                Ok(result) => print_undo_result(result, "Undid", "Nothing to undo."),
                 Err(e) => println!("Error: {}", e),
             },
+            "op-redo" => match journal::redo(&conn) {
+                Ok(result) => print_undo_result(result, "Redid", "Nothing to redo."),
+                Err(e) => println!("Error: {}", e),
+            },
+            "op-log" => {
+                let limit = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(20);
+                match journal::list(&conn, limit) {
+                    Ok(ops) if ops.is_empty() => println!("No operations."),
+                    Ok(ops) => {
+                        for op in ops {
+                            println!(
+                                "#{} [group {}] {}{}  {}",
+                                op.id,
+                                op.group_id,
+                                op.label,
+                                if op.undone { " (undone)" } else { "" },
+                                op.created_at.format("%Y-%m-%d %H:%M:%S")
+                            );
+                        }
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+
+            // Export
+            "export" => {
+                if let Some(deck_id) = parts.get(1) {
+                    let mut format = "md";
+                    let mut out: Option<&str> = None;
+                    let mut options = export::MarkdownOptions::default();
+                    let mut args = parts[2..].iter();
+                    while let Some(arg) = args.next() {
+                        match *arg {
+                            "--format" => format = args.next().copied().unwrap_or(""),
+                            "--out" | "-o" => out = args.next().copied(),
+                            "--score" => options.include_score = true,
+                            "--timestamps" => options.include_timestamps = true,
+                            "--tags" => options.include_tags = true,
+                            _ => println!("Ignoring unknown option: {}", arg),
+                        }
+                    }
+
+                    match (format, out) {
+                        ("md" | "markdown", Some(path)) => {
+                            match export::write_deck_markdown(&conn, deck_id, path, &options) {
+                                Ok(()) => println!("Exported deck {} to {}", deck_id, path),
+                                Err(e) => println!("Error: {}", e),
+                            }
+                        }
+                        ("md" | "markdown", None) => {
+                            match export::deck_to_markdown(&conn, deck_id, &options) {
+                                Ok(md) => print!("{}", md),
+                                Err(e) => println!("Error: {}", e),
+                            }
+                        }
+                        _ => println!("Unsupported format: {}", format),
+                    }
+                } else {
+                    println!("Usage: export <deck_id> --format md [--out <path>] [--score] [--timestamps] [--tags]");
+                }
+            }
+
+            // Backup
+            "backup" => {
+                if let Some(path) = parts.get(1) {
+                    match backup::write_backup(&conn, path) {
+                        Ok(()) => println!("Backed up database to {}", path),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: backup <path>");
+                }
+            }
+            "restore" => {
+                if let Some(path) = parts.get(1) {
+                    let mode = if parts.get(2) == Some(&"--merge") {
+                        backup::ImportMode::Merge
+                    } else {
+                        backup::ImportMode::Restore
+                    };
+                    match backup::restore_backup_file(&conn, path, mode) {
+                        Ok(result) => {
+                            println!("Imported {} ({:?}):", path, mode);
+                            for (name, counts) in [
+                                ("Decks", result.decks),
+                                ("Columns", result.columns),
+                                ("Cards", result.cards),
+                            ] {
+                                println!(
+                                    "  {}: {} inserted, {} updated, {} skipped",
+                                    name, counts.inserted, counts.updated, counts.skipped
+                                );
+                            }
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: restore <path> [--merge]");
+                }
+            }
+
+            // Cleanup
+            "check" => {
+                let repair = parts.get(1) == Some(&"--repair");
+                let result = if repair {
+                    db::repair_integrity(&conn)
+                } else {
+                    db::check_integrity(&conn)
+                };
+                match result {
+                    Ok(report) if report.is_ok() => println!("No problems found."),
+                    Ok(report) => {
+                        for issue in &report.issues {
+                            println!("  {:?}", issue);
+                        }
+                        if report.repaired {
+                            println!("Repaired {} problem(s).", report.issues.len());
+                        } else {
+                            println!("Run `check --repair` to fix them.");
+                        }
+                    }
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "maintenance" => {
+                let report = maintenance::run_maintenance(&mut conn);
+                if let Some(result) = report.cleanup {
+                    print_cleanup_result(Ok(result));
+                }
+                if report.optimized {
+                    println!("Optimized.");
+                }
+                if let Some(checkpoint) = report.checkpoint {
+                    println!(
+                        "Checkpoint: {}/{} frames{}",
+                        checkpoint.checkpointed_frames,
+                        checkpoint.log_frames,
+                        if checkpoint.busy { " (busy)" } else { "" }
+                    );
+                }
+                if let Some(integrity) = report.integrity {
+                    if integrity.is_ok() {
+                        println!("No problems found.");
+                    } else {
+                        println!(
+                            "{} integrity problem(s). Run `check --repair` to fix them.",
+                            integrity.issues.len()
+                        );
+                    }
+                }
+                for error in &report.errors {
+                    println!("Error ({:?}): {}", error.task, error.message);
+                }
+            }
+            "cleanup" => {
+                let result = cleanup::load_policy(&conn).and_then(|mut policy| {
+                    policy.dry_run |= parts.contains(&"--dry-run");
+                    policy.secure |= parts.contains(&"--secure");
+                    cleanup::cleanup_with_policy(&mut conn, &policy)
+                });
+                print_cleanup_result(result);
+            }
+            "empty-trash" => {
+                if let Some(deck_id) = parts.get(1) {
+                    let secure = parts.get(2) == Some(&"--secure");
+                    print_cleanup_result(cleanup::empty_trash(&mut conn, deck_id, secure));
+                } else {
+                    println!("Usage: empty-trash <deck_id> [--secure]");
+                }
+            }
+            "cleanup-policy" => {
+                if parts.len() > 1 {
+                    let json = parts[1..].join(" ");
+                    let result = serde_json::from_str::<CleanupPolicy>(&json)
+                        .map_err(jot_deck_core::JotDeckError::from)
+                        .and_then(|policy| cleanup::save_policy(&conn, &policy));
+                    if let Err(e) = result {
+                        println!("Error: {}", e);
+                        continue;
+                    }
+                }
+                match cleanup::load_policy(&conn) {
+                    Ok(policy) => println!("{}", serde_json::to_string(&policy).unwrap_or_default()),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
 
             "quit" | "exit" | "q" => {
                 println!("Goodbye!");
@@ -389,6 +847,44 @@ fn main() {
     }
 }
 
+fn print_undo_result(result: UndoResult, verb: &str, nothing: &str) {
+    if result.applied.is_empty() && result.discarded.is_empty() {
+        println!("{}", nothing);
+    }
+    for op in &result.applied {
+        println!("{} #{} {}", verb, op.id, op.label);
+    }
+    if !result.discarded.is_empty() {
+        println!(
+            "Discarded {} operation(s) that conflict with later changes.",
+            result.discarded.len()
+        );
+    }
+}
+
+fn print_cleanup_result(result: jot_deck_core::Result<cleanup::CleanupResult>) {
+    match result {
+        Ok(result) => {
+            let verb = if result.dry_run {
+                println!("Dry run (nothing deleted):");
+                "Would delete"
+            } else {
+                println!("Cleanup complete:");
+                "Deleted"
+            };
+            println!("  {} decks: {}", verb, result.deleted_decks);
+            println!("  {} columns: {}", verb, result.deleted_columns);
+            println!("  {} cards: {}", verb, result.deleted_cards);
+            println!("  {} trashed-only tag links: {}", verb, result.unlinked_trashed_tags);
+            println!("  {} orphan tags: {}", verb, result.deleted_orphan_tags);
+            println!("  {} revisions: {}", verb, result.deleted_revisions);
+            println!("  {} stack entries: {}", verb, result.deleted_stack_entries);
+            println!("  {} journal operations: {}", verb, result.deleted_operations);
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
 fn print_help() {
     println!(
         r#"
@@ -398,7 +894,10 @@ Deck:
   deck-new [name]         (dn)  Create a new deck
   deck-list               (dl)  List all decks
   deck-show <id>          (ds)  Show deck details
-  deck-delete <id>        (dd)  Delete a deck
+  deck-sort <id> <order>        Set card sort order
+  deck-delete <id>        (dd)  Soft delete a deck
+  deck-restore <id>             Restore a deleted deck
+  deck-trash                    Show deleted decks
 
 Column:
   col-new <deck_id> [name]      (cn)  Create a new column
@@ -406,6 +905,8 @@ Column:
   col-delete <id>               (cd)  Soft delete a column
   col-restore <id>                    Restore a deleted column
   col-move <id> <position>      (cm)  Move column to position
+  col-move-deck <id> <deck_id>        Move column (with cards) to another deck
+  col-dup <id> <deck_id>              Duplicate column with its cards
 
 Card:
   card-new <col_id> <content>   (an)  Create a new card
@@ -414,15 +915,48 @@ Card:
   card-restore <id>                   Restore a deleted card
   card-fav <id> [delta]         (af)  Update card score (+1 default)
   card-move <id> <position>     (am)  Move card to position
-  card-movecol <id> <col_id>          Move card to another column
+  card-movecol <id> <col_id> [pos]    Move card to another column (end by default)
+  card-dup <id> <col_id>              Duplicate card into a column
+  card-history <id>                   Show previous contents of a card
+  card-revert <id> <revision>         Restore a previous content
+  card-merge <id> <id>... [--max]     Merge cards (scores summed, --max: highest)
+  card-split <id> <offset>            Split a card at a character offset
 
 Tags:
-  tags <deck_id>                (t)   List tags in deck
+  tags <deck_id> [--tree]       (t)   List tags in deck (--tree: as hierarchy)
+  tags-live                           List tags used by live cards in any deck
   tag-search <deck_id> <tag>    (ts)  Find cards with tag
+  tag-stats <deck_id>                 Show tag usage and co-occurrence
+  tag-query <deck_id> <expr>    (tq)  Find cards by tag expression (& | ! ())
+  tag-rename <deck_id> <old> <new> [--dry-run]
+                                      Rename a tag in every card
+  tag-merge <deck_id> <from> <into> [--dry-run]
+                                      Merge a tag into another
+
+Search:
+  search <deck_id> <query>      (s)   Full-text search cards
+
+Export:
+  export <deck_id> --format md [--out <path>] [--score] [--timestamps] [--tags]
+                                      Export deck as Markdown
+
+Backup:
+  backup <path>                       Write a JSON backup of the database
+  restore <path> [--merge]            Restore a backup (empty DB, or merge)
 
 Other:
   trash <deck_id>                     Show deleted items
-  cleanup                             Run physical delete batch
+  undo <deck_id>                (u)   Restore the most recent deletion
+  op-undo                             Undo the last operation
+  op-redo                             Redo the last undone operation
+  op-log [n]                          Show recent operations
+  cleanup [--dry-run] [--secure]      Run physical delete batch (--dry-run: only report,
+                                      --secure: also erase purged text from the file)
+  empty-trash <deck_id> [--secure]    Permanently delete everything in a deck's trash
+  cleanup-policy [json]               Show (or set) the cleanup policy
+                                      e.g. {{"cards":{{"days":365}},"decks":"never","max_trash_items":500}}
+  check [--repair]                    Check (and repair) data integrity
+  maintenance                         Run cleanup, optimize, WAL checkpoint and integrity check
   help                          (h)   Show this help
   quit                          (q)   Exit
 "#
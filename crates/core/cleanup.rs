@@ -1,63 +1,371 @@
-use chrono::{Duration, Utc};
-use rusqlite::{params, Connection};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
 
 use crate::error::Result;
+use crate::journal;
+use crate::repository::{delete_stack, settings};
 
-/// 物理削除の対象期間（日数）
-const DELETE_AFTER_DAYS: i64 = 30;
+/// 物理削除の対象期間（日数）の既定値
+const DELETE_AFTER_DAYS: u32 = 30;
+
+/// 削除ポリシーを保存する設定のキー
+const POLICY_KEY: &str = "cleanup_policy";
+
+/// ゴミ箱に置いておく期間
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retention {
+    /// 論理削除から指定日数を過ぎたら物理削除する（0 なら次のバッチで削除する）
+    Days(u32),
+    /// 物理削除しない（ゴミ箱の上限でも削除しない）
+    Never,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Retention::Days(DELETE_AFTER_DAYS)
+    }
+}
+
+impl Retention {
+    /// これより前に論理削除されたものが対象になる日時
+    fn threshold(&self, now: DateTime<Utc>) -> Option<String> {
+        match self {
+            Retention::Days(days) => Some((now - Duration::days(*days as i64)).to_rfc3339()),
+            Retention::Never => None,
+        }
+    }
+}
+
+/// 物理削除のポリシー
+///
+/// 期間は単独でゴミ箱に入れたものに適用する。Deck / Column と一緒に削除された中身は親に従う。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CleanupPolicy {
+    pub decks: Retention,
+    pub columns: Retention,
+    pub cards: Retention,
+    /// ゴミ箱の Card にしか使われていないタグを残す期間（Card を論理削除してから）
+    ///
+    /// 期間を過ぎると Card との関連を外し、どこからも使われなくなったタグを削除する。
+    /// Card を復元するとタグは本文から付け直される。Never なら Card が物理削除されるまで残す。
+    pub tags: Retention,
+    /// ゴミ箱に置いておく件数の上限（超えた分は古いものから削除する）
+    pub max_trash_items: Option<u32>,
+    /// 削除せずに削除されるものだけを返す
+    pub dry_run: bool,
+    /// 物理削除した内容をファイルからも消す（`secure_erase`）
+    pub secure: bool,
+}
+
+/// 保存されている削除ポリシーを取得する（未設定なら既定値）
+pub fn load_policy(conn: &Connection) -> Result<CleanupPolicy> {
+    Ok(settings::get(conn, POLICY_KEY)?.unwrap_or_default())
+}
+
+/// 削除ポリシーを保存する
+pub fn save_policy(conn: &Connection, policy: &CleanupPolicy) -> Result<()> {
+    settings::set(conn, POLICY_KEY, policy)
+}
 
 /// 削除結果
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct CleanupResult {
+    pub deleted_decks: usize,
     pub deleted_columns: usize,
     pub deleted_cards: usize,
     pub deleted_orphan_tags: usize,
+    /// ゴミ箱の Card から外したタグの関連の数
+    pub unlinked_trashed_tags: usize,
+    pub deleted_revisions: usize,
+    pub deleted_stack_entries: usize,
+    /// 物理削除した行に触れていたので記録から消した操作の数
+    pub deleted_operations: usize,
+    /// 物理削除した（dry_run では削除する）Deck / Column / Card の ID
+    pub deck_ids: Vec<String>,
+    pub column_ids: Vec<String>,
+    pub card_ids: Vec<String>,
+    /// dry_run で実行したか（true なら何も削除していない）
+    pub dry_run: bool,
 }
 
-/// 論理削除から指定日数経過したデータを物理削除する
-pub fn cleanup_old_deleted(conn: &mut Connection) -> Result<CleanupResult> {
-    let threshold = Utc::now() - Duration::days(DELETE_AFTER_DAYS);
-    let threshold_str = threshold.to_rfc3339();
+/// 削除ポリシーを適用する範囲
+#[derive(Debug, Clone, Copy)]
+enum Scope<'a> {
+    All,
+    Deck(&'a str),
+}
+
+impl Scope<'_> {
+    fn deck_id(&self) -> Option<&str> {
+        match self {
+            Scope::All => None,
+            Scope::Deck(id) => Some(id),
+        }
+    }
+}
 
-    cleanup_with_threshold(conn, &threshold_str)
+/// 論理削除から既定の日数経過したデータを物理削除する
+pub fn cleanup_old_deleted(conn: &mut Connection) -> Result<CleanupResult> {
+    cleanup_with_policy(conn, &CleanupPolicy::default())
 }
 
 /// 指定した閾値より古い論理削除データを物理削除する（テスト用）
 pub fn cleanup_with_threshold(conn: &mut Connection, threshold: &str) -> Result<CleanupResult> {
+    let threshold = Some(threshold.to_string());
+    purge(
+        conn,
+        Scope::All,
+        [threshold.clone(), threshold.clone(), threshold.clone()],
+        [None, None, None],
+        threshold,
+        false,
+    )
+}
+
+/// ポリシーに従って論理削除データを物理削除する
+pub fn cleanup_with_policy(conn: &mut Connection, policy: &CleanupPolicy) -> Result<CleanupResult> {
+    apply_policy(conn, Scope::All, policy)
+}
+
+/// 1 つの Deck の中の論理削除データをポリシーに従って物理削除する
+///
+/// 対象は Deck 自身（論理削除されていれば）と所属する Column / Card。ゴミ箱の上限はこの Deck の中で数える。
+pub fn cleanup_deck(conn: &mut Connection, deck_id: &str, policy: &CleanupPolicy) -> Result<CleanupResult> {
+    apply_policy(conn, Scope::Deck(deck_id), policy)
+}
+
+/// Deck のゴミ箱を空にする（保存されているポリシーに関係なく、論理削除済みのものをすべて物理削除する）
+pub fn empty_trash(conn: &mut Connection, deck_id: &str, secure: bool) -> Result<CleanupResult> {
+    let policy = CleanupPolicy {
+        decks: Retention::Days(0),
+        columns: Retention::Days(0),
+        cards: Retention::Days(0),
+        tags: Retention::Days(0),
+        max_trash_items: Some(0),
+        dry_run: false,
+        secure,
+    };
+    cleanup_deck(conn, deck_id, &policy)
+}
+
+fn apply_policy(conn: &mut Connection, scope: Scope, policy: &CleanupPolicy) -> Result<CleanupResult> {
+    let now = Utc::now();
+    let retentions = [policy.decks, policy.columns, policy.cards];
+    let thresholds = retentions.map(|r| r.threshold(now));
+
+    let overflow = match policy.max_trash_items {
+        Some(max) => overflow_cutoff(conn, scope, &retentions, max)?,
+        None => None,
+    };
+    // Never の種類はゴミ箱の上限でも削除しない
+    let overflows = retentions.map(|r| if r == Retention::Never { None } else { overflow.clone() });
+
+    let tag_threshold = policy.tags.threshold(now);
+
+    let result = purge(conn, scope, thresholds, overflows, tag_threshold, policy.dry_run)?;
+    if policy.secure && !policy.dry_run {
+        secure_erase(conn)?;
+    }
+    Ok(result)
+}
+
+/// 単独でゴミ箱に入っている Deck / Column / Card（Deck / Column と一緒に削除された中身は数えない）
+///
+/// `?2` が NULL でなければその Deck の中だけを数える。
+const TRASH_ITEMS: [&str; 3] = [
+    "SELECT deleted_at FROM decks WHERE deleted_at IS NOT NULL AND (?2 IS NULL OR id = ?2)",
+    "SELECT deleted_at FROM columns WHERE deleted_at IS NOT NULL AND deleted_with_deck = 0
+     AND (?2 IS NULL OR deck_id = ?2)",
+    "SELECT deleted_at FROM cards WHERE deleted_at IS NOT NULL AND deleted_with_column = 0 AND deleted_with_deck = 0
+     AND (?2 IS NULL OR column_id IN (SELECT id FROM columns WHERE deck_id = ?2))",
+];
+
+/// ゴミ箱の件数を上限に収めるために削除する範囲（この日時以前に削除されたもの）
+///
+/// 同じ日時に削除されたものはまとめて扱うので、上限より少し多く削除することがある。
+fn overflow_cutoff(conn: &Connection, scope: Scope, retentions: &[Retention; 3], max: u32) -> Result<Option<String>> {
+    let items: Vec<&str> = TRASH_ITEMS
+        .iter()
+        .zip(retentions)
+        .filter(|(_, r)| **r != Retention::Never)
+        .map(|(sql, _)| *sql)
+        .collect();
+    if items.is_empty() {
+        return Ok(None);
+    }
+
+    let cutoff = conn
+        .query_row(
+            &format!(
+                "SELECT deleted_at FROM ({}) ORDER BY deleted_at DESC LIMIT 1 OFFSET ?1",
+                items.join(" UNION ALL ")
+            ),
+            params![max, scope.deck_id()],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(cutoff)
+}
+
+/// 削除対象を一時テーブルに集めて物理削除する
+///
+/// `thresholds` / `overflows` は Deck, Column, Card の順。threshold より前、または overflow 以前に
+/// 単独で削除されたものと、削除対象の Deck / Column に属するものが対象になる。
+/// `tag_threshold` より前に論理削除された Card からは、削除されていない Card に使われていないタグの関連を外す。
+/// dry_run のときは同じ処理をしてからロールバックする。
+fn purge(
+    conn: &mut Connection,
+    scope: Scope,
+    thresholds: [Option<String>; 3],
+    overflows: [Option<String>; 3],
+    tag_threshold: Option<String>,
+    dry_run: bool,
+) -> Result<CleanupResult> {
     let tx = conn.transaction()?;
-    let mut result = CleanupResult::default();
+    let mut result = CleanupResult {
+        dry_run,
+        ..Default::default()
+    };
 
-    // 1. 削除対象の Card に関連するタグの関連を削除
+    tx.execute_batch(
+        "CREATE TEMP TABLE purge_decks (id TEXT PRIMARY KEY);
+         CREATE TEMP TABLE purge_columns (id TEXT PRIMARY KEY);
+         CREATE TEMP TABLE purge_cards (id TEXT PRIMARY KEY);",
+    )?;
+
+    // 削除対象: 期間を過ぎた（またはゴミ箱の上限からあふれた）Deck / Column / Card と、
+    // 削除対象の Deck に属するすべての Column、削除対象の Column に属するすべての Card
     tx.execute(
-        "DELETE FROM card_tags WHERE card_id IN (SELECT id FROM cards WHERE deleted_at IS NOT NULL AND deleted_at < ?1)",
-        params![threshold],
+        "INSERT INTO purge_decks
+         SELECT id FROM decks
+         WHERE deleted_at IS NOT NULL AND (deleted_at < ?1 OR deleted_at <= ?2) AND (?3 IS NULL OR id = ?3)",
+        params![thresholds[0], overflows[0], scope.deck_id()],
+    )?;
+    tx.execute(
+        "INSERT INTO purge_columns
+         SELECT id FROM columns
+         WHERE (deleted_at IS NOT NULL AND deleted_with_deck = 0 AND (deleted_at < ?1 OR deleted_at <= ?2)
+                AND (?3 IS NULL OR deck_id = ?3))
+            OR deck_id IN (SELECT id FROM purge_decks)",
+        params![thresholds[1], overflows[1], scope.deck_id()],
+    )?;
+    tx.execute(
+        "INSERT INTO purge_cards
+         SELECT id FROM cards
+         WHERE (deleted_at IS NOT NULL AND deleted_with_column = 0 AND deleted_with_deck = 0
+                AND (deleted_at < ?1 OR deleted_at <= ?2)
+                AND (?3 IS NULL OR column_id IN (SELECT id FROM columns WHERE deck_id = ?3)))
+            OR column_id IN (SELECT id FROM purge_columns)",
+        params![thresholds[2], overflows[2], scope.deck_id()],
+    )?;
+
+    let ids = |table: &str| -> Result<Vec<String>> {
+        let mut stmt = tx.prepare(&format!("SELECT id FROM {} ORDER BY id", table))?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(ids)
+    };
+    result.deck_ids = ids("purge_decks")?;
+    result.column_ids = ids("purge_columns")?;
+    result.card_ids = ids("purge_cards")?;
+
+    // 0. 削除対象に触れた操作を記録から消す（記録に削除した内容を残さず、undo が行の食い違いで止まらないようにする）
+    result.deleted_operations = journal::forget(
+        &tx,
+        "SELECT id FROM purge_decks UNION ALL SELECT id FROM purge_columns UNION ALL SELECT id FROM purge_cards",
+        [],
     )?;
 
+    // 1. 削除対象の Card に関連するタグの関連を削除
+    tx.execute("DELETE FROM card_tags WHERE card_id IN (SELECT id FROM purge_cards)", [])?;
+
+    // 1b. 削除対象の Card の編集履歴を削除
+    result.deleted_revisions =
+        tx.execute("DELETE FROM card_revisions WHERE card_id IN (SELECT id FROM purge_cards)", [])?;
+
     // 2. 削除対象の Card を物理削除
-    result.deleted_cards = tx.execute(
-        "DELETE FROM cards WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
-        params![threshold],
+    result.deleted_cards = tx.execute("DELETE FROM cards WHERE id IN (SELECT id FROM purge_cards)", [])?;
+
+    // 3. 削除対象の Column を物理削除（所属する Card は既に削除済み）
+    result.deleted_columns = tx.execute("DELETE FROM columns WHERE id IN (SELECT id FROM purge_columns)", [])?;
+
+    // 4. 削除対象の Deck の削除スタックと Deck 本体を物理削除
+    tx.execute("DELETE FROM delete_stack WHERE deck_id IN (SELECT id FROM purge_decks)", [])?;
+    result.deleted_decks = tx.execute("DELETE FROM decks WHERE id IN (SELECT id FROM purge_decks)", [])?;
+
+    // 5. ゴミ箱の Card にしか使われていないタグは、期間を過ぎた Card との関連を外す
+    result.unlinked_trashed_tags = tx.execute(
+        "DELETE FROM card_tags
+         WHERE tag_id NOT IN (SELECT tag_id FROM tag_usage)
+           AND card_id IN (
+               SELECT id FROM cards
+               WHERE deleted_at IS NOT NULL AND deleted_at < ?1
+                 AND (?2 IS NULL OR column_id IN (SELECT id FROM columns WHERE deck_id = ?2))
+           )",
+        params![tag_threshold, scope.deck_id()],
     )?;
 
-    // 3. 削除対象の Column を物理削除（所属する Card は既に削除済み、または連動削除で削除されている）
-    result.deleted_columns = tx.execute(
-        "DELETE FROM columns WHERE deleted_at IS NOT NULL AND deleted_at < ?1",
-        params![threshold],
+    // 5b. どの Card にも関連付けられていない孤立タグを削除（使われているタグの祖先は残す）
+    // タグに触れた操作も記録から消す（undo で削除したタグへの関連を戻そうとして失敗しないように）
+    tx.execute_batch(
+        "CREATE TEMP TABLE purge_tags AS
+         WITH RECURSIVE live(id) AS (
+             SELECT tag_id FROM card_tags
+             UNION
+             SELECT t.parent_id FROM tags t JOIN live ON t.id = live.id WHERE t.parent_id IS NOT NULL
+         )
+         SELECT id FROM tags WHERE id NOT IN (SELECT id FROM live);",
     )?;
+    result.deleted_operations += journal::forget(&tx, "SELECT id FROM purge_tags", [])?;
+    result.deleted_orphan_tags = tx.execute("DELETE FROM tags WHERE id IN (SELECT id FROM purge_tags)", [])?;
 
-    // 4. どの Card にも関連付けられていない孤立タグを削除
-    result.deleted_orphan_tags = tx.execute(
-        "DELETE FROM tags WHERE id NOT IN (SELECT DISTINCT tag_id FROM card_tags)",
-        [],
+    // 6. 物理削除された Card / Column を指す削除スタックのエントリを削除
+    result.deleted_stack_entries = delete_stack::prune(&tx)?;
+
+    tx.execute_batch(
+        "DROP TABLE temp.purge_decks;
+         DROP TABLE temp.purge_columns;
+         DROP TABLE temp.purge_cards;
+         DROP TABLE temp.purge_tags;",
     )?;
 
-    tx.commit()?;
+    if dry_run {
+        tx.rollback()?;
+    } else {
+        tx.commit()?;
+    }
     Ok(result)
 }
 
-/// 全 Deck の物理削除バッチを実行
+/// 物理削除した内容をデータベースファイルからも消す
+///
+/// 行を削除しても、空いたページや全文検索の索引、操作の記録（undo 用の行の内容）には元の本文が残る。
+/// 索引を作り直し、操作の記録を消してから `secure_delete` を有効にして VACUUM し、WAL も空にする。
+/// 操作の記録を消すので、これより前の操作は undo できなくなる。トランザクションの中では呼べない。
+pub fn secure_erase(conn: &Connection) -> Result<()> {
+    let secure_delete: i64 = conn.query_row("PRAGMA secure_delete", [], |row| row.get(0))?;
+    conn.pragma_update(None, "secure_delete", true)?;
+
+    let result = (|| -> Result<()> {
+        conn.execute("INSERT INTO cards_fts(cards_fts) VALUES ('rebuild')", [])?;
+        journal::clear(conn)?;
+        conn.execute_batch("VACUUM")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    })();
+
+    conn.pragma_update(None, "secure_delete", secure_delete)?;
+    result
+}
+
+/// 全 Deck の物理削除バッチを実行（保存されている削除ポリシーに従う）
 pub fn run_cleanup_batch(conn: &mut Connection) -> Result<CleanupResult> {
-    cleanup_old_deleted(conn)
+    let policy = load_policy(conn)?;
+    cleanup_with_policy(conn, &policy)
 }
 
 #[cfg(test)]
@@ -102,6 +410,7 @@ mod tests {
 
         // タグを同期
         tag::sync_card_tags(&conn, &c.id, &c.content).unwrap();
+        card::update_content(&conn, &c.id, "#test content v2").unwrap();
 
         // Card を論理削除
         card::soft_delete(&conn, &c.id).unwrap();
@@ -122,6 +431,11 @@ mod tests {
         let result = cleanup_old_deleted(&mut conn).unwrap();
         assert_eq!(result.deleted_cards, 1);
         assert_eq!(result.deleted_orphan_tags, 1);
+        assert_eq!(result.deleted_revisions, 1);
+        assert_eq!(result.deleted_stack_entries, 1);
+        assert!(crate::repository::delete_stack::list_stack(&conn, &d.id)
+            .unwrap()
+            .is_empty());
 
         // Card が物理削除されていることを確認
         let cards: Vec<String> = conn
@@ -194,5 +508,535 @@ mod tests {
         let result = cleanup_old_deleted(&mut conn).unwrap();
         assert_eq!(result.deleted_columns, 1);
         assert_eq!(result.deleted_cards, 2);
+        assert_eq!(result.deleted_stack_entries, 1);
+    }
+
+    #[test]
+    fn test_cleanup_deck() {
+        let mut conn = create_in_memory().unwrap();
+
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Test".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Col".to_string(),
+            },
+        )
+        .unwrap();
+
+        let c = card::create(
+            &conn,
+            NewCard {
+                column_id: col.id.clone(),
+                content: "#deck content".to_string(),
+            },
+        )
+        .unwrap();
+        card::create(
+            &conn,
+            NewCard {
+                column_id: col.id.clone(),
+                content: "trashed".to_string(),
+            },
+        )
+        .unwrap();
+        card::soft_delete(&conn, &card::get_by_column_id(&conn, &col.id).unwrap()[1].id).unwrap();
+
+        deck::soft_delete(&conn, &d.id).unwrap();
+
+        // 30 日経過していない Deck は削除されない
+        let result = cleanup_old_deleted(&mut conn).unwrap();
+        assert_eq!(result.deleted_decks, 0);
+        assert!(deck::get_by_id(&conn, &d.id).is_ok());
+
+        // Deck の deleted_at だけを 31 日前に更新しても、配下のデータごと削除される
+        let old_date = (Utc::now() - Duration::days(31)).to_rfc3339();
+        conn.execute(
+            "UPDATE decks SET deleted_at = ?1 WHERE id = ?2",
+            params![&old_date, &d.id],
+        )
+        .unwrap();
+
+        let result = cleanup_old_deleted(&mut conn).unwrap();
+        assert_eq!(result.deleted_decks, 1);
+        assert_eq!(result.deleted_columns, 1);
+        assert_eq!(result.deleted_cards, 2);
+        assert_eq!(result.deleted_orphan_tags, 1);
+
+        assert!(deck::get_by_id(&conn, &d.id).is_err());
+        assert!(card::get_by_id(&conn, &c.id).is_err());
+        let stack_entries: i64 = conn
+            .query_row("SELECT COUNT(*) FROM delete_stack", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(stack_entries, 0);
+    }
+
+    #[test]
+    fn test_cleanup_keeps_parent_tags() {
+        let mut conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Test".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Col".to_string(),
+            },
+        )
+        .unwrap();
+        let old = card::create(
+            &conn,
+            NewCard {
+                column_id: col.id.clone(),
+                content: "#project/old".to_string(),
+            },
+        )
+        .unwrap();
+        card::create(
+            &conn,
+            NewCard {
+                column_id: col.id.clone(),
+                content: "#project/alpha/x".to_string(),
+            },
+        )
+        .unwrap();
+
+        card::soft_delete(&conn, &old.id).unwrap();
+        let old_date = (Utc::now() - Duration::days(31)).to_rfc3339();
+        conn.execute(
+            "UPDATE cards SET deleted_at = ?1 WHERE id = ?2",
+            params![&old_date, &old.id],
+        )
+        .unwrap();
+
+        // project/old だけが削除され、使われているタグの祖先（project, project/alpha）は残る
+        let result = cleanup_old_deleted(&mut conn).unwrap();
+        assert_eq!(result.deleted_orphan_tags, 1);
+        let names: Vec<String> = conn
+            .prepare("SELECT name FROM tags ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(names, vec!["project", "project/alpha", "project/alpha/x"]);
+    }
+
+    /// Column に Card を作り、論理削除してから deleted_at を `days_ago` 日前にずらす
+    fn trash_cards(conn: &Connection, column_id: &str, days_ago: &[i64]) -> Vec<String> {
+        days_ago
+            .iter()
+            .enumerate()
+            .map(|(i, days)| {
+                let c = card::create(
+                    conn,
+                    NewCard {
+                        column_id: column_id.to_string(),
+                        content: format!("trashed {}", i),
+                    },
+                )
+                .unwrap();
+                card::soft_delete(conn, &c.id).unwrap();
+                set_deleted_at(conn, "cards", &c.id, *days);
+                c.id
+            })
+            .collect()
+    }
+
+    fn set_deleted_at(conn: &Connection, table: &str, id: &str, days_ago: i64) {
+        let date = (Utc::now() - Duration::days(days_ago)).to_rfc3339();
+        conn.execute(
+            &format!("UPDATE {} SET deleted_at = ?1 WHERE id = ?2", table),
+            params![&date, id],
+        )
+        .unwrap();
+    }
+
+    fn setup_column(conn: &Connection) -> (String, String) {
+        let d = deck::create(
+            conn,
+            NewDeck {
+                name: "Test".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Col".to_string(),
+            },
+        )
+        .unwrap();
+        (d.id, col.id)
+    }
+
+    #[test]
+    fn test_cleanup_policy_retention() {
+        let mut conn = create_in_memory().unwrap();
+        let (_, column_id) = setup_column(&conn);
+        let ids = trash_cards(&conn, &column_id, &[400, 31, 0]);
+
+        // 365 日保持: 400 日前のものだけが削除される
+        let policy = CleanupPolicy {
+            cards: Retention::Days(365),
+            ..Default::default()
+        };
+        let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert_eq!(result.card_ids, vec![ids[0].clone()]);
+
+        // 削除しない
+        let policy = CleanupPolicy {
+            cards: Retention::Never,
+            ..Default::default()
+        };
+        assert_eq!(cleanup_with_policy(&mut conn, &policy).unwrap().deleted_cards, 0);
+
+        // 0 日: ゴミ箱のものはすべて削除される
+        let policy = CleanupPolicy {
+            cards: Retention::Days(0),
+            ..Default::default()
+        };
+        assert_eq!(cleanup_with_policy(&mut conn, &policy).unwrap().deleted_cards, 2);
+        assert!(card::get_deleted(&conn, &column_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cleanup_policy_cascade_follows_parent() {
+        let mut conn = create_in_memory().unwrap();
+        let (deck_id, column_id) = setup_column(&conn);
+        card::create(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "in column".to_string(),
+            },
+        )
+        .unwrap();
+        column::soft_delete(&conn, &column_id).unwrap();
+        set_deleted_at(&conn, "columns", &column_id, 10);
+        conn.execute(
+            "UPDATE cards SET deleted_at = (SELECT deleted_at FROM columns WHERE id = ?1) WHERE column_id = ?1",
+            params![&column_id],
+        )
+        .unwrap();
+
+        // Column と一緒に削除された Card は Card の期間では削除されない
+        let policy = CleanupPolicy {
+            cards: Retention::Days(0),
+            columns: Retention::Never,
+            ..Default::default()
+        };
+        assert_eq!(cleanup_with_policy(&mut conn, &policy).unwrap().deleted_cards, 0);
+        column::restore(&conn, &column_id).unwrap();
+        assert_eq!(card::get_by_column_id(&conn, &column_id).unwrap().len(), 1);
+
+        // Column の期間を過ぎれば中身ごと削除される
+        column::soft_delete(&conn, &column_id).unwrap();
+        set_deleted_at(&conn, "columns", &column_id, 10);
+        let policy = CleanupPolicy {
+            columns: Retention::Days(7),
+            cards: Retention::Never,
+            ..Default::default()
+        };
+        let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert_eq!(result.deleted_columns, 1);
+        assert_eq!(result.deleted_cards, 1);
+        assert!(deck::get_by_id(&conn, &deck_id).is_ok());
+    }
+
+    #[test]
+    fn test_cleanup_dry_run() {
+        let mut conn = create_in_memory().unwrap();
+        let (_, column_id) = setup_column(&conn);
+        let ids = trash_cards(&conn, &column_id, &[40, 1]);
+
+        let policy = CleanupPolicy {
+            dry_run: true,
+            ..Default::default()
+        };
+        let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert!(result.dry_run);
+        assert_eq!(result.deleted_cards, 1);
+        assert_eq!(result.card_ids, vec![ids[0].clone()]);
+        assert_eq!(result.deleted_stack_entries, 1);
+
+        // 何も削除されていない
+        assert_eq!(card::get_deleted(&conn, &column_id).unwrap().len(), 2);
+        assert!(card::restore(&conn, &ids[0]).is_ok());
+    }
+
+    #[test]
+    fn test_cleanup_max_trash_items() {
+        let mut conn = create_in_memory().unwrap();
+        let (_, column_id) = setup_column(&conn);
+        let ids = trash_cards(&conn, &column_id, &[5, 4, 3, 2, 1]);
+
+        // 新しい 2 件だけを残す
+        let policy = CleanupPolicy {
+            max_trash_items: Some(2),
+            ..Default::default()
+        };
+        let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+        let mut expected = ids[..3].to_vec();
+        expected.sort();
+        assert_eq!(result.card_ids, expected);
+
+        // Never の種類は上限でも削除しない
+        let policy = CleanupPolicy {
+            cards: Retention::Never,
+            max_trash_items: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(cleanup_with_policy(&mut conn, &policy).unwrap().deleted_cards, 0);
+        assert_eq!(card::get_deleted(&conn, &column_id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_run_cleanup_batch_uses_saved_policy() {
+        let mut conn = create_in_memory().unwrap();
+        let (_, column_id) = setup_column(&conn);
+        trash_cards(&conn, &column_id, &[40, 1]);
+
+        assert_eq!(load_policy(&conn).unwrap(), CleanupPolicy::default());
+        let policy = CleanupPolicy {
+            cards: Retention::Days(365),
+            max_trash_items: Some(10),
+            ..Default::default()
+        };
+        save_policy(&conn, &policy).unwrap();
+        assert_eq!(load_policy(&conn).unwrap(), policy);
+        assert_eq!(run_cleanup_batch(&mut conn).unwrap().deleted_cards, 0);
+
+        save_policy(
+            &conn,
+            &CleanupPolicy {
+                cards: Retention::Days(0),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(run_cleanup_batch(&mut conn).unwrap().deleted_cards, 2);
+    }
+
+    #[test]
+    fn test_cleanup_deck_is_scoped() {
+        let mut conn = create_in_memory().unwrap();
+        let (deck_a, column_a) = setup_column(&conn);
+        let (_, column_b) = setup_column(&conn);
+        let a = trash_cards(&conn, &column_a, &[40, 1]);
+        let b = trash_cards(&conn, &column_b, &[40]);
+
+        let result = cleanup_deck(&mut conn, &deck_a, &CleanupPolicy::default()).unwrap();
+        assert_eq!(result.card_ids, vec![a[0].clone()]);
+        assert!(card::get_by_id(&conn, &b[0]).is_ok());
+
+        // ゴミ箱の上限はこの Deck の中で数える
+        let policy = CleanupPolicy {
+            max_trash_items: Some(0),
+            ..Default::default()
+        };
+        let result = cleanup_deck(&mut conn, &deck_a, &policy).unwrap();
+        assert_eq!(result.card_ids, vec![a[1].clone()]);
+        assert!(card::get_by_id(&conn, &b[0]).is_ok());
+    }
+
+    #[test]
+    fn test_empty_trash() {
+        let mut conn = create_in_memory().unwrap();
+        let (deck_id, column_id) = setup_column(&conn);
+        let (_, other_column) = setup_column(&conn);
+        let live = card::create(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "live".to_string(),
+            },
+        )
+        .unwrap();
+        let trashed = trash_cards(&conn, &column_id, &[0, 0]);
+        let other = trash_cards(&conn, &other_column, &[0]);
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: deck_id.clone(),
+                name: "Trashed".to_string(),
+            },
+        )
+        .unwrap();
+        column::soft_delete(&conn, &col.id).unwrap();
+
+        // 保存されたポリシーが「削除しない」でも空にする
+        save_policy(
+            &conn,
+            &CleanupPolicy {
+                cards: Retention::Never,
+                columns: Retention::Never,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let result = empty_trash(&mut conn, &deck_id, false).unwrap();
+        assert_eq!(result.deleted_cards, 2);
+        assert_eq!(result.deleted_columns, 1);
+        assert!(trashed.iter().all(|id| card::get_by_id(&conn, id).is_err()));
+        assert!(delete_stack::list_stack(&conn, &deck_id).unwrap().is_empty());
+
+        // 削除されていない Card と他の Deck のゴミ箱はそのまま
+        assert!(card::get_by_id(&conn, &live.id).is_ok());
+        assert!(card::get_by_id(&conn, &other[0]).is_ok());
+    }
+
+    #[test]
+    fn test_undo_after_empty_trash() {
+        let mut conn = create_in_memory().unwrap();
+        let (deck_id, column_id) = setup_column(&conn);
+        let kept = card::create(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "kept".to_string(),
+            },
+        )
+        .unwrap();
+        let purged = card::create(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "#purged-tag purged text".to_string(),
+            },
+        )
+        .unwrap();
+        card::update_content(&conn, &purged.id, "#purged-tag purged text v2").unwrap();
+        card::update_content(&conn, &kept.id, "kept v2").unwrap();
+        card::soft_delete(&conn, &purged.id).unwrap();
+
+        let result = empty_trash(&mut conn, &deck_id, false).unwrap();
+        assert_eq!(result.deleted_cards, 1);
+        assert!(result.deleted_operations > 0);
+
+        // secure でなくても削除した本文は操作の記録に残らない
+        let leftovers: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM operation_changes WHERE old_row LIKE '%purged%' OR new_row LIKE '%purged%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(leftovers, 0);
+
+        // 削除した Card に触れない操作は取り消せる
+        let undone = journal::undo(&conn).unwrap();
+        assert!(undone.discarded.is_empty());
+        assert_eq!(undone.applied[0].label, "card.update_content");
+        assert_eq!(card::get_by_id(&conn, &kept.id).unwrap().content, "kept");
+        while !journal::undo(&conn).unwrap().applied.is_empty() {}
+        assert!(card::get_by_id(&conn, &kept.id).is_err());
+    }
+
+    #[test]
+    fn test_secure_erase_removes_content_from_file() {
+        let path = std::env::temp_dir().join(format!("jot-deck-cleanup-{}.db", ulid::Ulid::new()));
+        let path_str = path.to_str().unwrap();
+        let secret = "confidential-7f3a9c";
+
+        {
+            let mut conn = crate::db::create_file_db(path_str).unwrap();
+            let (deck_id, column_id) = setup_column(&conn);
+            let c = card::create(
+                &conn,
+                NewCard {
+                    column_id,
+                    content: format!("#{} {}", secret, secret),
+                },
+            )
+            .unwrap();
+            card::update_content(&conn, &c.id, &format!("{} v2", secret)).unwrap();
+            card::soft_delete(&conn, &c.id).unwrap();
+
+            let result = empty_trash(&mut conn, &deck_id, true).unwrap();
+            assert_eq!(result.deleted_cards, 1);
+            assert!(journal::list(&conn, 10).unwrap().is_empty());
+            assert!(crate::search::search(&conn, &deck_id, secret, 10).unwrap().is_empty());
+        }
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(secret.len()).any(|w| w == secret.as_bytes()));
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path_str, suffix));
+        }
+    }
+
+    #[test]
+    fn test_cleanup_trashed_only_tags() {
+        let mut conn = create_in_memory().unwrap();
+        let (_, column_id) = setup_column(&conn);
+        let contents = ["#old #shared", "#recent", "#shared"];
+        let ids: Vec<String> = contents
+            .iter()
+            .map(|content| {
+                card::create(
+                    &conn,
+                    NewCard {
+                        column_id: column_id.clone(),
+                        content: content.to_string(),
+                    },
+                )
+                .unwrap()
+                .id
+            })
+            .collect();
+        card::soft_delete(&conn, &ids[0]).unwrap();
+        card::soft_delete(&conn, &ids[1]).unwrap();
+        set_deleted_at(&conn, "cards", &ids[0], 10);
+        let tag_names = |conn: &Connection| -> Vec<String> {
+            conn.prepare("SELECT name FROM tags ORDER BY name")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+
+        // 既定（30 日）と Never では残す
+        for tags in [Retention::default(), Retention::Never] {
+            let policy = CleanupPolicy {
+                tags,
+                ..Default::default()
+            };
+            let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+            assert_eq!(result.unlinked_trashed_tags, 0);
+        }
+        assert_eq!(tag_names(&conn), vec!["old", "recent", "shared"]);
+
+        // 7 日: 10 日前に削除した Card のタグだけを外す（生きている Card でも使われているタグは残す）
+        let policy = CleanupPolicy {
+            tags: Retention::Days(7),
+            ..Default::default()
+        };
+        let result = cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert_eq!(result.unlinked_trashed_tags, 1);
+        assert_eq!(result.deleted_orphan_tags, 1);
+        assert_eq!(result.deleted_cards, 0);
+        assert_eq!(tag_names(&conn), vec!["recent", "shared"]);
+        assert!(crate::db::check_integrity(&conn).unwrap().is_ok());
     }
 }
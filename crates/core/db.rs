@@ -1,8 +1,31 @@
-use rusqlite::Connection;
+use std::time::Duration;
 
-use crate::error::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
 
-const SCHEMA: &str = r#"
+use crate::error::{JotDeckError, Result};
+use crate::rank;
+use crate::repository::tag;
+
+/// スキーママイグレーション
+///
+/// `version` は 1 から連番で振り、適用済みのバージョンは `PRAGMA user_version` に記録する。
+/// 一度リリースしたマイグレーションは書き換えず、変更は新しいバージョンとして追加すること。
+/// 操作ジャーナルが記録するテーブルの列を変えるときは、`operation_changes` に保存した行の内容も
+/// 同じ形に書き換えること（undo は現在の行と保存した行の内容を比べるので、形が違うと取り消せない）。
+struct Migration {
+    version: i64,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// v1: 初期スキーマ
+///
+/// マイグレーション導入前のデータベースは user_version = 0 のままテーブルを持っているため、
+/// `IF NOT EXISTS` で既存テーブルをそのまま受け入れる。
+const SCHEMA_V1: &str = r#"
 -- Deck テーブル
 CREATE TABLE IF NOT EXISTS decks (
     id TEXT PRIMARY KEY,
@@ -64,10 +87,434 @@ CREATE TABLE IF NOT EXISTS card_tags (
 CREATE INDEX IF NOT EXISTS idx_card_tags_tag_id ON card_tags(tag_id);
 "#;
 
+/// v2: Card 本文の全文検索インデックス
+///
+/// `cards` を外部コンテンツとする FTS5 テーブル。日本語は分かち書きできないため trigram で索引する。
+const SCHEMA_V2: &str = r#"
+CREATE VIRTUAL TABLE cards_fts USING fts5(
+    content,
+    content='cards',
+    content_rowid='rowid',
+    tokenize='trigram'
+);
+
+-- cards と同期するトリガー
+CREATE TRIGGER cards_fts_insert AFTER INSERT ON cards BEGIN
+    INSERT INTO cards_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
+
+CREATE TRIGGER cards_fts_delete AFTER DELETE ON cards BEGIN
+    INSERT INTO cards_fts(cards_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+END;
+
+CREATE TRIGGER cards_fts_update AFTER UPDATE OF content ON cards BEGIN
+    INSERT INTO cards_fts(cards_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+    INSERT INTO cards_fts(rowid, content) VALUES (new.rowid, new.content);
+END;
+
+-- 既存の Card を索引する
+INSERT INTO cards_fts(cards_fts) VALUES ('rebuild');
+"#;
+
+/// v3: 削除スタック
+///
+/// 削除操作の順序を記録し、アプリ再起動後も `u` で直近の削除から復元できるようにする。
+/// Column と連動削除された Card は Column のエントリで復元されるため積まない。
+const SCHEMA_V3: &str = r#"
+CREATE TABLE delete_stack (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    deck_id TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    deleted_at TEXT NOT NULL,
+    FOREIGN KEY (deck_id) REFERENCES decks(id)
+);
+
+CREATE INDEX idx_delete_stack_deck_id ON delete_stack(deck_id);
+CREATE INDEX idx_delete_stack_entity ON delete_stack(entity_type, entity_id);
+
+-- 既存のゴミ箱の内容を削除日時順に積む
+INSERT INTO delete_stack (deck_id, entity_type, entity_id, deleted_at)
+SELECT deck_id, entity_type, entity_id, deleted_at FROM (
+    SELECT deck_id, 'column' AS entity_type, id AS entity_id, deleted_at
+    FROM columns
+    WHERE deleted_at IS NOT NULL
+    UNION ALL
+    SELECT col.deck_id, 'card', c.id, c.deleted_at
+    FROM cards c
+    JOIN columns col ON c.column_id = col.id
+    WHERE c.deleted_at IS NOT NULL AND c.deleted_with_column = 0
+)
+ORDER BY deleted_at ASC;
+"#;
+
+/// v4: Deck の論理削除
+///
+/// Deck 削除時に連動削除された Column / Card を区別するフラグを持たせる（`deleted_with_column` と同様）。
+const SCHEMA_V4: &str = r#"
+ALTER TABLE decks ADD COLUMN deleted_at TEXT;
+ALTER TABLE columns ADD COLUMN deleted_with_deck INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE cards ADD COLUMN deleted_with_deck INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_decks_deleted_at ON decks(deleted_at);
+"#;
+
+/// v5: 階層タグ
+///
+/// `#a/b/c` の親子関係を記録する。既存のタグは `/` を含まないので親を持たない。
+const SCHEMA_V5: &str = r#"
+ALTER TABLE tags ADD COLUMN parent_id TEXT REFERENCES tags(id);
+
+CREATE INDEX idx_tags_parent_id ON tags(parent_id);
+"#;
+
+/// v6: Card の編集履歴
+///
+/// `card::update_content` で上書きされる前の本文を保存する。
+const SCHEMA_V6: &str = r#"
+CREATE TABLE card_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    card_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (card_id) REFERENCES cards(id)
+);
+
+CREATE INDEX idx_card_revisions_card_id ON card_revisions(card_id);
+"#;
+
+/// v7: 操作ジャーナル（undo / redo）
+///
+/// `journal::record` で記録した操作ごとに、変更された行の前後の内容を JSON で保存する。
+const SCHEMA_V7: &str = r#"
+CREATE TABLE operations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    group_id INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    undone INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX idx_operations_group_id ON operations(group_id);
+
+CREATE TABLE operation_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation_id INTEGER NOT NULL,
+    table_name TEXT NOT NULL,
+    old_row TEXT,
+    new_row TEXT,
+    FOREIGN KEY (operation_id) REFERENCES operations(id)
+);
+
+CREATE INDEX idx_operation_changes_operation_id ON operation_changes(operation_id);
+"#;
+
+/// v8: 並び順を rank（辞書順で比較する小数のキー）で持つ
+///
+/// 挿入・移動のたびに兄弟の position を書き換えなくて済むよう、`rank::between` で間に入るキーを作る。
+/// 既存の position 順（同じ位置なら削除されていない方、ID 順）に `rank::between` の末尾追加と同じキー
+/// （"V", "V001", "V002", ...）を振る。position は読み出し時に rank から求める。
+/// 記録済みのジャーナルの行の内容も position を除いて rank を加えた形に書き換える。rank は現在の値を入れるので、
+/// 移行前の移動を undo しても並び順は戻らない。
+const SCHEMA_V8: &str = r#"
+ALTER TABLE columns ADD COLUMN rank TEXT NOT NULL DEFAULT '';
+ALTER TABLE cards ADD COLUMN rank TEXT NOT NULL DEFAULT '';
+
+-- 7388168 は 62 進 4 桁の "V000"
+UPDATE columns SET rank = numbered.rank FROM (
+    SELECT id, rtrim(
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 238328) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 3844) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 62) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', v % 62 + 1, 1),
+        '0'
+    ) AS rank
+    FROM (
+        SELECT id, 7388167 + ROW_NUMBER() OVER (PARTITION BY deck_id ORDER BY position, deleted_at IS NOT NULL, id) AS v
+        FROM columns
+    )
+) AS numbered
+WHERE numbered.id = columns.id;
+
+UPDATE cards SET rank = numbered.rank FROM (
+    SELECT id, rtrim(
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 238328) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 3844) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', (v / 62) % 62 + 1, 1) ||
+        substr('0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz', v % 62 + 1, 1),
+        '0'
+    ) AS rank
+    FROM (
+        SELECT id, 7388167 + ROW_NUMBER() OVER (PARTITION BY column_id ORDER BY position, deleted_at IS NOT NULL, id) AS v
+        FROM cards
+    )
+) AS numbered
+WHERE numbered.id = cards.id;
+
+ALTER TABLE columns DROP COLUMN position;
+ALTER TABLE cards DROP COLUMN position;
+
+CREATE INDEX idx_columns_deck_rank ON columns(deck_id, rank);
+CREATE INDEX idx_cards_column_rank ON cards(column_id, rank);
+CREATE INDEX idx_cards_live_rank ON cards(column_id, rank) WHERE deleted_at IS NULL;
+
+UPDATE operation_changes SET
+    old_row = json_set(json_remove(old_row, '$.position'), '$.rank',
+                       IFNULL((SELECT rank FROM columns WHERE id = json_extract(old_row, '$.id')), '')),
+    new_row = json_set(json_remove(new_row, '$.position'), '$.rank',
+                       IFNULL((SELECT rank FROM columns WHERE id = json_extract(new_row, '$.id')), ''))
+WHERE table_name = 'columns';
+
+UPDATE operation_changes SET
+    old_row = json_set(json_remove(old_row, '$.position'), '$.rank',
+                       IFNULL((SELECT rank FROM cards WHERE id = json_extract(old_row, '$.id')), '')),
+    new_row = json_set(json_remove(new_row, '$.position'), '$.rank',
+                       IFNULL((SELECT rank FROM cards WHERE id = json_extract(new_row, '$.id')), ''))
+WHERE table_name = 'cards';
+"#;
+
+/// v9: 並び順の変更日時
+///
+/// 並び替えや別の Column / Deck への移動では updated_at（ユーザーに見える編集の日時）を変えず、
+/// position_updated_at に記録する。v8 と同じく記録済みのジャーナルの行の内容にも列を加える。
+const SCHEMA_V9: &str = r#"
+ALTER TABLE columns ADD COLUMN position_updated_at TEXT;
+ALTER TABLE cards ADD COLUMN position_updated_at TEXT;
+
+UPDATE operation_changes SET
+    old_row = json_set(old_row, '$.position_updated_at', NULL),
+    new_row = json_set(new_row, '$.position_updated_at', NULL)
+WHERE table_name IN ('columns', 'cards');
+"#;
+
+/// v10: 設定
+///
+/// キーごとに JSON の値を持つ。ジャーナルには記録しない。
+const SCHEMA_V10: &str = r#"
+CREATE TABLE settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+"#;
+
+/// v11: タグの使用数
+///
+/// 削除されていない Card から使われている数をタグごとに持ち、トリガーで追従する（0 になった行は消す）。
+/// 派生データなのでジャーナルには記録せず、undo / redo でもトリガーで追従させる。
+const SCHEMA_V11: &str = r#"
+CREATE TABLE tag_usage (
+    tag_id TEXT PRIMARY KEY,
+    live_cards INTEGER NOT NULL
+);
+
+INSERT INTO tag_usage (tag_id, live_cards)
+SELECT ct.tag_id, COUNT(*)
+FROM card_tags ct
+JOIN cards c ON c.id = ct.card_id
+WHERE c.deleted_at IS NULL
+GROUP BY ct.tag_id;
+
+CREATE TRIGGER tag_usage_link_insert AFTER INSERT ON card_tags
+WHEN EXISTS (SELECT 1 FROM cards WHERE id = new.card_id AND deleted_at IS NULL)
+BEGIN
+    INSERT INTO tag_usage (tag_id, live_cards) VALUES (new.tag_id, 1)
+    ON CONFLICT(tag_id) DO UPDATE SET live_cards = live_cards + 1;
+END;
+
+CREATE TRIGGER tag_usage_link_delete AFTER DELETE ON card_tags
+WHEN EXISTS (SELECT 1 FROM cards WHERE id = old.card_id AND deleted_at IS NULL)
+BEGIN
+    UPDATE tag_usage SET live_cards = live_cards - 1 WHERE tag_id = old.tag_id;
+    DELETE FROM tag_usage WHERE tag_id = old.tag_id AND live_cards <= 0;
+END;
+
+CREATE TRIGGER tag_usage_card_insert AFTER INSERT ON cards
+WHEN new.deleted_at IS NULL
+BEGIN
+    INSERT INTO tag_usage (tag_id, live_cards)
+    SELECT tag_id, 1 FROM card_tags WHERE card_id = new.id
+    ON CONFLICT(tag_id) DO UPDATE SET live_cards = live_cards + 1;
+END;
+
+CREATE TRIGGER tag_usage_card_delete AFTER DELETE ON cards
+WHEN old.deleted_at IS NULL
+BEGIN
+    UPDATE tag_usage SET live_cards = live_cards - 1
+    WHERE tag_id IN (SELECT tag_id FROM card_tags WHERE card_id = old.id);
+    DELETE FROM tag_usage WHERE live_cards <= 0;
+END;
+
+CREATE TRIGGER tag_usage_card_trash AFTER UPDATE OF deleted_at ON cards
+WHEN old.deleted_at IS NULL AND new.deleted_at IS NOT NULL
+BEGIN
+    UPDATE tag_usage SET live_cards = live_cards - 1
+    WHERE tag_id IN (SELECT tag_id FROM card_tags WHERE card_id = new.id);
+    DELETE FROM tag_usage WHERE live_cards <= 0;
+END;
+
+CREATE TRIGGER tag_usage_card_restore AFTER UPDATE OF deleted_at ON cards
+WHEN old.deleted_at IS NOT NULL AND new.deleted_at IS NULL
+BEGIN
+    INSERT INTO tag_usage (tag_id, live_cards)
+    SELECT tag_id, 1 FROM card_tags WHERE card_id = new.id
+    ON CONFLICT(tag_id) DO UPDATE SET live_cards = live_cards + 1;
+END;
+"#;
+
+/// 適用するマイグレーション一覧（version 昇順）
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: SCHEMA_V1,
+    },
+    Migration {
+        version: 2,
+        description: "card full-text search",
+        sql: SCHEMA_V2,
+    },
+    Migration {
+        version: 3,
+        description: "persistent delete stack",
+        sql: SCHEMA_V3,
+    },
+    Migration {
+        version: 4,
+        description: "deck soft delete",
+        sql: SCHEMA_V4,
+    },
+    Migration {
+        version: 5,
+        description: "hierarchical tags",
+        sql: SCHEMA_V5,
+    },
+    Migration {
+        version: 6,
+        description: "card revisions",
+        sql: SCHEMA_V6,
+    },
+    Migration {
+        version: 7,
+        description: "operation journal",
+        sql: SCHEMA_V7,
+    },
+    Migration {
+        version: 8,
+        description: "rank ordering keys",
+        sql: SCHEMA_V8,
+    },
+    Migration {
+        version: 9,
+        description: "position change timestamps",
+        sql: SCHEMA_V9,
+    },
+    Migration {
+        version: 10,
+        description: "settings",
+        sql: SCHEMA_V10,
+    },
+    Migration {
+        version: 11,
+        description: "tag usage counts",
+        sql: SCHEMA_V11,
+    },
+];
+
+/// このバイナリが扱えるスキーマバージョン
+pub const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// データベースに記録されているスキーマバージョンを取得する
+pub fn schema_version(conn: &Connection) -> Result<i64> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version)
+}
+
+/// 未適用のマイグレーションを順に適用する
+/// 各ステップは個別のトランザクションで実行され、失敗したステップはロールバックされる
+fn apply_migrations(conn: &Connection, migrations: &[Migration]) -> Result<i64> {
+    let supported = migrations.last().map(|m| m.version).unwrap_or(0);
+    let initial = schema_version(conn)?;
+
+    if initial > supported {
+        return Err(JotDeckError::SchemaTooNew {
+            found: initial,
+            supported,
+        });
+    }
+
+    let mut current = initial;
+    for migration in migrations.iter().filter(|m| m.version > initial) {
+        let tx = conn.unchecked_transaction()?;
+
+        tx.execute_batch(migration.sql).map_err(|e| {
+            JotDeckError::Migration(format!(
+                "v{} ({}): {}",
+                migration.version, migration.description, e
+            ))
+        })?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+
+        tx.commit()?;
+        current = migration.version;
+    }
+
+    Ok(current)
+}
+
+/// スキーマを最新バージョンまでマイグレーションする
+pub fn migrate(conn: &Connection) -> Result<i64> {
+    apply_migrations(conn, MIGRATIONS)
+}
+
+/// 入れ子にできるトランザクション（SAVEPOINT）
+///
+/// 外側にトランザクションがあればその一部になり、なければ新しく始める。
+/// `commit` せずに破棄すると、開始してからの変更を巻き戻す。
+pub(crate) struct Savepoint<'a> {
+    conn: &'a Connection,
+    committed: bool,
+}
+
+impl Savepoint<'_> {
+    pub(crate) fn commit(mut self) -> Result<()> {
+        self.conn.execute_batch("RELEASE jot_deck")?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for Savepoint<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self
+                .conn
+                .execute_batch("ROLLBACK TO jot_deck; RELEASE jot_deck");
+        }
+    }
+}
+
+impl std::ops::Deref for Savepoint<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+    }
+}
+
+/// SAVEPOINT を開始する
+pub(crate) fn savepoint(conn: &Connection) -> Result<Savepoint<'_>> {
+    conn.execute_batch("SAVEPOINT jot_deck")?;
+    Ok(Savepoint {
+        conn,
+        committed: false,
+    })
+}
+
 /// データベースを初期化する
 pub fn init_db(conn: &Connection) -> Result<()> {
     conn.execute("PRAGMA foreign_keys = ON", [])?;
-    conn.execute_batch(SCHEMA)?;
+    migrate(conn)?;
+    crate::journal::install(conn)?;
     Ok(())
 }
 
@@ -78,13 +525,228 @@ pub fn create_in_memory() -> Result<Connection> {
     Ok(conn)
 }
 
+/// ファイルデータベースでロックの解放を待つ時間の上限
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// ファイルベースのデータベースを作成する
+///
+/// WAL モードにして、読み取りが書き込みを待たずに済むようにする。
+/// `synchronous = NORMAL` は WAL では電源断でも壊れず、直前のコミットを失うだけになる。
+/// WAL にできない場合（インメモリ DB や WAL 非対応のファイルシステム）はエラーにする。
 pub fn create_file_db(path: &str) -> Result<Connection> {
     let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    let mode = conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        return Err(JotDeckError::InvalidOperation(format!(
+            "Could not enable WAL mode (journal_mode is {})",
+            mode
+        )));
+    }
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
     init_db(&conn)?;
     Ok(conn)
 }
 
+/// 読み取り専用の接続を開く
+///
+/// スキーマの作成やマイグレーションはしないので、先に `create_file_db` で開いておく。
+/// 誤って書き込むとエラーになる。
+pub fn open_reader(path: &str) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.pragma_update(None, "query_only", true)?;
+    Ok(conn)
+}
+
+/// 整合性チェックで見つかった問題
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IntegrityIssue {
+    /// 存在しない Column を参照している Card
+    OrphanedCard { card_id: String, column_id: String },
+    /// 存在しない Deck を参照している Column
+    OrphanedColumn { column_id: String, deck_id: String },
+    /// Column 内の Card の rank が重複しているか、不正な形式
+    CardRankConflict { column_id: String },
+    /// Deck 内の Column の rank が重複しているか、不正な形式
+    ColumnRankConflict { deck_id: String },
+    /// タグの使用数（tag_usage）が削除されていない Card の数と合わない
+    TagUsageMismatch { tag_id: String },
+}
+
+/// 整合性チェックの結果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub issues: Vec<IntegrityIssue>,
+    /// 見つかった問題を修復した
+    pub repaired: bool,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// 修復時に親を失った Column / Card を移す Deck / Column の名前
+const RECOVERED_NAME: &str = "Recovered";
+
+/// rank が重複しているか不正な形式の行を持つ親の ID を調べる（削除済みの行も含む）
+fn rank_conflicts(conn: &Connection, table: &str, parent: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("SELECT {parent}, rank FROM {table} ORDER BY {parent}, rank"))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut parents: Vec<String> = Vec::new();
+    for (i, (parent_id, key)) in rows.iter().enumerate() {
+        let duplicate = i > 0 && rows[i - 1] == (parent_id.clone(), key.clone());
+        if (duplicate || !rank::is_valid(key)) && parents.last() != Some(parent_id) {
+            parents.push(parent_id.clone());
+        }
+    }
+    Ok(parents)
+}
+
+/// 孤立した Card / Column、rank の重複・不正な形式、タグの使用数のずれを調べる
+pub fn check_integrity(conn: &Connection) -> Result<IntegrityReport> {
+    let mut issues = Vec::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT col.id, col.deck_id FROM columns col LEFT JOIN decks d ON col.deck_id = d.id WHERE d.id IS NULL ORDER BY col.id",
+    )?;
+    for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+        let (column_id, deck_id) = row?;
+        issues.push(IntegrityIssue::OrphanedColumn { column_id, deck_id });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.column_id FROM cards c LEFT JOIN columns col ON c.column_id = col.id WHERE col.id IS NULL ORDER BY c.id",
+    )?;
+    for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+        let (card_id, column_id) = row?;
+        issues.push(IntegrityIssue::OrphanedCard { card_id, column_id });
+    }
+
+    for deck_id in rank_conflicts(conn, "columns", "deck_id")? {
+        issues.push(IntegrityIssue::ColumnRankConflict { deck_id });
+    }
+
+    for column_id in rank_conflicts(conn, "cards", "column_id")? {
+        issues.push(IntegrityIssue::CardRankConflict { column_id });
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "WITH expected(tag_id, live_cards) AS ({usage})
+         SELECT tag_id FROM (SELECT tag_id, live_cards FROM tag_usage EXCEPT SELECT tag_id, live_cards FROM expected)
+         UNION
+         SELECT tag_id FROM (SELECT tag_id, live_cards FROM expected EXCEPT SELECT tag_id, live_cards FROM tag_usage)
+         ORDER BY tag_id",
+        usage = tag::LIVE_USAGE_SQL
+    ))?;
+    for row in stmt.query_map([], |row| row.get(0))? {
+        issues.push(IntegrityIssue::TagUsageMismatch { tag_id: row? });
+    }
+
+    Ok(IntegrityReport {
+        issues,
+        repaired: false,
+    })
+}
+
+/// `check_integrity` で見つかった問題を 1 つのトランザクションで修復する
+///
+/// 孤立した Column / Card は「Recovered」Deck（と同名の Column）の末尾に移す。
+/// rank は今の並び順のまま振り直す（重複は ID 順）。タグの使用数は数え直す。
+pub fn repair_integrity(conn: &Connection) -> Result<IntegrityReport> {
+    let mut report = check_integrity(conn)?;
+    if report.is_ok() {
+        return Ok(report);
+    }
+
+    let now = Utc::now();
+    let tx = conn.unchecked_transaction()?;
+
+    let mut recovered_deck: Option<String> = None;
+    let mut recovered_column: Option<String> = None;
+    let mut rebalance_decks = Vec::new();
+    let mut rebalance_columns = Vec::new();
+    let mut rebuild_tag_usage = false;
+
+    for issue in &report.issues {
+        match issue {
+            IntegrityIssue::OrphanedColumn { column_id, .. } => {
+                let deck_id = recovered_deck_id(&tx, &mut recovered_deck, now)?;
+                let key = rank::append(&tx, "columns", "deck_id", &deck_id, column_id)?;
+                tx.execute(
+                    "UPDATE columns SET deck_id = ?1, rank = ?2, position_updated_at = ?3 WHERE id = ?4",
+                    params![deck_id, key, now.to_rfc3339(), column_id],
+                )?;
+                tx.execute(
+                    "UPDATE delete_stack SET deck_id = ?1 WHERE entity_id = ?2 OR entity_id IN (SELECT id FROM cards WHERE column_id = ?2)",
+                    params![deck_id, column_id],
+                )?;
+            }
+            IntegrityIssue::OrphanedCard { card_id, .. } => {
+                let column_id = match &recovered_column {
+                    Some(id) => id.clone(),
+                    None => {
+                        let deck_id = recovered_deck_id(&tx, &mut recovered_deck, now)?;
+                        let id = Ulid::new().to_string();
+                        let key = rank::append(&tx, "columns", "deck_id", &deck_id, "")?;
+                        tx.execute(
+                            "INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?5)",
+                            params![&id, &deck_id, RECOVERED_NAME, key, now.to_rfc3339()],
+                        )?;
+                        recovered_column = Some(id.clone());
+                        id
+                    }
+                };
+                // 削除済みの Card も生き返らせず、そのまま移す
+                let key = rank::append(&tx, "cards", "column_id", &column_id, card_id)?;
+                tx.execute(
+                    "UPDATE cards SET column_id = ?1, rank = ?2, deleted_with_column = 0, deleted_with_deck = 0, position_updated_at = ?3 WHERE id = ?4",
+                    params![&column_id, key, now.to_rfc3339(), card_id],
+                )?;
+            }
+            IntegrityIssue::CardRankConflict { column_id } => rebalance_columns.push(column_id.clone()),
+            IntegrityIssue::ColumnRankConflict { deck_id } => rebalance_decks.push(deck_id.clone()),
+            IntegrityIssue::TagUsageMismatch { .. } => rebuild_tag_usage = true,
+        }
+    }
+
+    for deck_id in &rebalance_decks {
+        rank::rebalance(&tx, "columns", "deck_id", deck_id)?;
+    }
+    for column_id in &rebalance_columns {
+        rank::rebalance(&tx, "cards", "column_id", column_id)?;
+    }
+    if rebuild_tag_usage {
+        tag::rebuild_usage(&tx)?;
+    }
+
+    tx.commit()?;
+
+    report.repaired = true;
+    Ok(report)
+}
+
+/// 修復用の Deck の ID（なければ作る）
+fn recovered_deck_id(conn: &Connection, cache: &mut Option<String>, now: chrono::DateTime<Utc>) -> Result<String> {
+    if let Some(id) = cache {
+        return Ok(id.clone());
+    }
+
+    let id = Ulid::new().to_string();
+    conn.execute(
+        "INSERT INTO decks (id, name, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)",
+        params![&id, RECOVERED_NAME, now.to_rfc3339()],
+    )?;
+    *cache = Some(id.clone());
+    Ok(id)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,5 +769,351 @@ mod tests {
         assert!(tables.contains(&"cards".to_string()));
         assert!(tables.contains(&"tags".to_string()));
         assert!(tables.contains(&"card_tags".to_string()));
+
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    /// マイグレーション導入前（user_version = 0）のスキーマ
+    const BASELINE_FIXTURE: &str = r#"
+CREATE TABLE decks (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    sort_order TEXT NOT NULL DEFAULT 'created_desc',
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+CREATE TABLE columns (
+    id TEXT PRIMARY KEY,
+    deck_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    deleted_at TEXT,
+    FOREIGN KEY (deck_id) REFERENCES decks(id)
+);
+CREATE INDEX idx_columns_deck_id ON columns(deck_id);
+CREATE INDEX idx_columns_deleted_at ON columns(deleted_at);
+CREATE TABLE cards (
+    id TEXT PRIMARY KEY,
+    column_id TEXT NOT NULL,
+    content TEXT NOT NULL,
+    score INTEGER NOT NULL DEFAULT 0,
+    position INTEGER NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    deleted_at TEXT,
+    deleted_with_column INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (column_id) REFERENCES columns(id)
+);
+CREATE INDEX idx_cards_column_id ON cards(column_id);
+CREATE INDEX idx_cards_deleted_at ON cards(deleted_at);
+CREATE TABLE tags (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+CREATE TABLE card_tags (
+    card_id TEXT NOT NULL,
+    tag_id TEXT NOT NULL,
+    PRIMARY KEY (card_id, tag_id),
+    FOREIGN KEY (card_id) REFERENCES cards(id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id)
+);
+CREATE INDEX idx_card_tags_tag_id ON card_tags(tag_id);
+
+INSERT INTO decks VALUES ('D1', 'Old Deck', 'score_desc', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+INSERT INTO columns VALUES ('C1', 'D1', 'a-col', 0, '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00', NULL);
+INSERT INTO cards VALUES ('K1', 'C1', 'hello #old', 3, 0, '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00', NULL, 0);
+INSERT INTO cards VALUES ('K2', 'C1', 'trashed', 0, 1, '2024-01-01T00:00:00+00:00', '2024-01-02T00:00:00+00:00', '2024-01-02T00:00:00+00:00', 0);
+INSERT INTO tags VALUES ('T1', 'old');
+INSERT INTO card_tags VALUES ('K1', 'T1');
+"#;
+
+    fn baseline_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_upgrade_baseline_database() {
+        let conn = baseline_db();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        init_db(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+        // 既存データが保持されている
+        let d = crate::repository::deck::get_by_id(&conn, "D1").unwrap();
+        assert_eq!(d.name, "Old Deck");
+        assert!(d.deleted_at.is_none());
+        let k1 = crate::repository::card::get_by_id(&conn, "K1").unwrap();
+        assert_eq!(k1.content, "hello #old");
+        assert_eq!(k1.score, 3);
+        let k2 = crate::repository::card::get_by_id(&conn, "K2").unwrap();
+        assert!(k2.deleted_at.is_some());
+        let tags = crate::repository::tag::get_tags_by_card(&conn, "K1").unwrap();
+        assert_eq!(tags.len(), 1);
+        assert!(tags[0].parent_id.is_none());
+        assert!(crate::repository::card::history(&conn, "K1").unwrap().is_empty());
+        assert!(crate::journal::list(&conn, 10).unwrap().is_empty());
+        assert_eq!(tag::live_card_count(&conn, "T1").unwrap(), 1);
+        assert!(check_integrity(&conn).unwrap().is_ok());
+
+        // position 順に rank が振られ、position は rank から求まる
+        let ranks: Vec<String> = conn
+            .prepare("SELECT rank FROM cards ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(ranks, vec!["V", "V001"]);
+        assert_eq!(k1.position, 0);
+        assert_eq!(k2.position, 1);
+
+        // 既存の Card が全文検索インデックスに載っている
+        let hits = crate::repository::search::search(&conn, "D1", "hello", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].card.id, "K1");
+
+        // 既存のゴミ箱の内容が削除スタックに積まれている
+        let stack = crate::repository::delete_stack::list_stack(&conn, "D1").unwrap();
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack[0].entity_id, "K2");
+    }
+
+    #[test]
+    fn test_upgrade_keeps_journal() {
+        let path = std::env::temp_dir().join(format!("jot-deck-upgrade-{}.db", Ulid::new()));
+        let path_str = path.to_str().unwrap();
+
+        // v7 の形で操作を記録しておく
+        {
+            let conn = Connection::open(path_str).unwrap();
+            conn.execute_batch(BASELINE_FIXTURE).unwrap();
+            apply_migrations(&conn, &MIGRATIONS[..7]).unwrap();
+            crate::journal::install(&conn).unwrap();
+            conn.execute_batch(
+                "INSERT INTO operations (id, group_id, label, created_at)
+                 VALUES (1, 1, 'card.update_score', '2024-01-03T00:00:00+00:00');
+                 UPDATE journal_state SET operation_id = 1;
+                 UPDATE cards SET score = 4 WHERE id = 'K1';
+                 UPDATE journal_state SET operation_id = NULL;",
+            )
+            .unwrap();
+        }
+
+        let conn = create_file_db(path_str).unwrap();
+        assert_eq!(crate::journal::list(&conn, 10).unwrap().len(), 1);
+        let result = crate::journal::undo(&conn).unwrap();
+        assert_eq!(result.applied.len(), 1);
+        assert!(result.discarded.is_empty());
+        assert_eq!(crate::repository::card::get_by_id(&conn, "K1").unwrap().score, 3);
+        crate::journal::redo(&conn).unwrap();
+        assert_eq!(crate::repository::card::get_by_id(&conn, "K1").unwrap().score, 4);
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_is_idempotent() {
+        let conn = baseline_db();
+        init_db(&conn).unwrap();
+        init_db(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_reject_newer_schema() {
+        let conn = create_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1).unwrap();
+
+        let result = init_db(&conn);
+        assert!(matches!(
+            result,
+            Err(JotDeckError::SchemaTooNew { found, supported })
+                if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+        ));
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "create table",
+                sql: "CREATE TABLE a (id INTEGER);",
+            },
+            Migration {
+                version: 2,
+                description: "broken",
+                sql: "CREATE TABLE b (id INTEGER); INSERT INTO missing VALUES (1);",
+            },
+        ];
+
+        let result = apply_migrations(&conn, &migrations);
+        assert!(matches!(result, Err(JotDeckError::Migration(_))));
+
+        // v1 は適用済み、v2 は途中まで実行された分も含めてロールバックされている
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+        let b_exists: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'b'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(b_exists, 0);
+    }
+
+    #[test]
+    fn test_check_and_repair_integrity() {
+        let conn = create_in_memory().unwrap();
+        conn.execute_batch(
+            "INSERT INTO decks (id, name, created_at, updated_at) VALUES ('D1', 'Deck', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES
+                 ('C1', 'D1', 'a', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00'),
+                 ('C2', 'D1', 'b', 'V001', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO cards (id, column_id, content, rank, created_at, updated_at) VALUES
+                 ('K1', 'C1', 'one', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00'),
+                 ('K2', 'C1', 'two', 'V001', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00'),
+                 ('K3', 'C1', 'three', 'V002', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');",
+        )
+        .unwrap();
+        assert!(check_integrity(&conn).unwrap().is_ok());
+
+        // 外部キー制約のなかった頃のデータを再現する
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+             UPDATE cards SET rank = 'V' WHERE id = 'K2';
+             UPDATE columns SET rank = 'V0' WHERE id = 'C2';
+             INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES
+                 ('C9', 'GONE', 'lost', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO cards (id, column_id, content, rank, created_at, updated_at) VALUES
+                 ('K9', 'GONE', 'orphan', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             PRAGMA foreign_keys = ON;",
+        )
+        .unwrap();
+
+        let report = check_integrity(&conn).unwrap();
+        assert!(!report.repaired);
+        assert_eq!(
+            report.issues,
+            vec![
+                IntegrityIssue::OrphanedColumn {
+                    column_id: "C9".into(),
+                    deck_id: "GONE".into()
+                },
+                IntegrityIssue::OrphanedCard {
+                    card_id: "K9".into(),
+                    column_id: "GONE".into()
+                },
+                IntegrityIssue::ColumnRankConflict { deck_id: "D1".into() },
+                IntegrityIssue::CardRankConflict { column_id: "C1".into() },
+            ]
+        );
+
+        let report = repair_integrity(&conn).unwrap();
+        assert!(report.repaired);
+        assert!(check_integrity(&conn).unwrap().is_ok());
+
+        let cards: Vec<_> = crate::repository::card::get_by_column_id(&conn, "C1")
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.id, c.position))
+            .collect();
+        assert_eq!(cards, vec![("K1".into(), 0), ("K2".into(), 1), ("K3".into(), 2)]);
+        assert_eq!(crate::repository::column::get_by_id(&conn, "C2").unwrap().position, 1);
+
+        let recovered = crate::repository::column::get_by_id(&conn, "C9").unwrap().deck_id;
+        assert_eq!(crate::repository::deck::get_by_id(&conn, &recovered).unwrap().name, "Recovered");
+        let orphan = crate::repository::card::get_by_id(&conn, "K9").unwrap();
+        assert_eq!(
+            crate::repository::column::get_by_id(&conn, &orphan.column_id).unwrap().deck_id,
+            recovered
+        );
+
+        // 問題がなければ何もしない
+        assert!(!repair_integrity(&conn).unwrap().repaired);
+    }
+
+    #[test]
+    fn test_repair_tag_usage() {
+        let conn = create_in_memory().unwrap();
+        conn.execute_batch(
+            "INSERT INTO decks (id, name, created_at, updated_at) VALUES ('D1', 'Deck', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES
+                 ('C1', 'D1', 'a', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO cards (id, column_id, content, rank, created_at, updated_at) VALUES
+                 ('K1', 'C1', '#a', 'V', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00'),
+                 ('K2', 'C1', '#a #b', 'V001', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00');
+             INSERT INTO tags (id, name) VALUES ('TA', 'a'), ('TB', 'b');
+             INSERT INTO card_tags (card_id, tag_id) VALUES ('K1', 'TA'), ('K2', 'TA'), ('K2', 'TB');",
+        )
+        .unwrap();
+        assert!(check_integrity(&conn).unwrap().is_ok());
+        assert_eq!(tag::live_card_count(&conn, "TA").unwrap(), 2);
+
+        // トリガーを通らずに書き換えられた使用数
+        conn.execute_batch(
+            "UPDATE tag_usage SET live_cards = 5 WHERE tag_id = 'TA';
+             DELETE FROM tag_usage WHERE tag_id = 'TB';",
+        )
+        .unwrap();
+        assert_eq!(
+            check_integrity(&conn).unwrap().issues,
+            vec![
+                IntegrityIssue::TagUsageMismatch { tag_id: "TA".into() },
+                IntegrityIssue::TagUsageMismatch { tag_id: "TB".into() },
+            ]
+        );
+
+        assert!(repair_integrity(&conn).unwrap().repaired);
+        assert!(check_integrity(&conn).unwrap().is_ok());
+        assert_eq!(tag::live_card_count(&conn, "TA").unwrap(), 2);
+        assert_eq!(tag::live_card_count(&conn, "TB").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_file_db_uses_wal() {
+        let path = std::env::temp_dir().join(format!("jot-deck-wal-{}.db", Ulid::new()));
+        let path_str = path.to_str().unwrap();
+
+        let writer = create_file_db(path_str).unwrap();
+        let mode: String = writer.query_row("PRAGMA journal_mode", [], |row| row.get(0)).unwrap();
+        assert_eq!(mode, "wal");
+        let synchronous: i64 = writer.query_row("PRAGMA synchronous", [], |row| row.get(0)).unwrap();
+        assert_eq!(synchronous, 1);
+
+        let reader = open_reader(path_str).unwrap();
+        writer
+            .execute(
+                "INSERT INTO decks (id, name, created_at, updated_at)
+                 VALUES ('D1', 'Deck', '2024-01-01T00:00:00+00:00', '2024-01-01T00:00:00+00:00')",
+                [],
+            )
+            .unwrap();
+
+        // 書き込み中のトランザクションがあっても読める（コミット済みの内容が見える）
+        let tx = writer.unchecked_transaction().unwrap();
+        tx.execute("UPDATE decks SET name = 'Renamed' WHERE id = 'D1'", []).unwrap();
+        let name: String = reader
+            .query_row("SELECT name FROM decks WHERE id = 'D1'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(name, "Deck");
+        tx.commit().unwrap();
+
+        // 読み取り用の接続では書き込めない
+        assert!(reader.execute("DELETE FROM decks", []).is_err());
+
+        drop(reader);
+        drop(writer);
+        std::fs::remove_file(&path).unwrap();
+
+        // WAL にできなければ開かない
+        assert!(matches!(create_file_db(":memory:"), Err(JotDeckError::InvalidOperation(_))));
     }
 }
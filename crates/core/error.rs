@@ -5,11 +5,23 @@ pub enum JotDeckError {
     #[error("Database error: {0}")]
     Database(#[from] rusqlite::Error),
 
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
     #[error("Not found: {0}")]
     NotFound(String),
 
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
+
+    #[error("Migration failed: {0}")]
+    Migration(String),
+
+    #[error("Database schema version {found} is newer than supported version {supported}. Please update Jot Deck.")]
+    SchemaTooNew { found: i64, supported: i64 },
 }
 
 pub type Result<T> = std::result::Result<T, JotDeckError>;
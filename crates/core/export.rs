@@ -0,0 +1,251 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+
+use crate::error::Result;
+use crate::models::Card;
+use crate::repository::{card, column, deck, tag};
+
+/// Markdown 出力のオプション
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOptions {
+    /// スコアのバッジ（★ n）を出力する
+    pub include_score: bool,
+    /// 作成日時・更新日時を出力する
+    pub include_timestamps: bool,
+    /// タグ一覧を出力する
+    pub include_tags: bool,
+}
+
+impl MarkdownOptions {
+    fn has_metadata(&self) -> bool {
+        self.include_score || self.include_timestamps || self.include_tags
+    }
+}
+
+/// カード間の区切り
+const CARD_SEPARATOR: &str = "\n---\n\n";
+
+fn format_datetime(dt: &DateTime<Utc>) -> String {
+    dt.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Card のメタデータ行を組み立てる（出力する項目がなければ None）
+fn card_metadata(conn: &Connection, c: &Card, options: &MarkdownOptions) -> Result<Option<String>> {
+    if !options.has_metadata() {
+        return Ok(None);
+    }
+
+    let mut parts = Vec::new();
+
+    if options.include_score && c.score != 0 {
+        parts.push(format!("★ {}", c.score));
+    }
+
+    if options.include_timestamps {
+        let mut ts = format_datetime(&c.created_at);
+        if c.updated_at != c.created_at {
+            ts.push_str(&format!(" (updated {})", format_datetime(&c.updated_at)));
+        }
+        parts.push(ts);
+    }
+
+    if options.include_tags {
+        let tags = tag::get_tags_by_card(conn, &c.id)?;
+        if !tags.is_empty() {
+            let names: Vec<_> = tags.iter().map(|t| format!("#{}", t.name)).collect();
+            parts.push(names.join(" "));
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(format!("_{}_", parts.join(" · "))))
+}
+
+/// Deck を Markdown に変換する
+///
+/// Column ごとに `##` セクションを作り（position 順）、Card は Deck のソート順で `---` 区切りに並べる。
+/// 削除済みの Column / Card は含めない。
+pub fn deck_to_markdown(conn: &Connection, deck_id: &str, options: &MarkdownOptions) -> Result<String> {
+    let d = deck::get_by_id(conn, deck_id)?;
+    let mut out = format!("# {}\n", d.name);
+
+    for col in column::get_by_deck_id(conn, deck_id)? {
+        out.push_str(&format!("\n## {}\n\n", col.name));
+
+        let cards = card::get_by_column_id_sorted(conn, &col.id, d.sort_order)?;
+        let mut blocks = Vec::with_capacity(cards.len());
+        for c in &cards {
+            let mut block = c.content.trim_end().to_string();
+            block.push('\n');
+            if let Some(meta) = card_metadata(conn, c, options)? {
+                block.push('\n');
+                block.push_str(&meta);
+                block.push('\n');
+            }
+            blocks.push(block);
+        }
+        out.push_str(&blocks.join(CARD_SEPARATOR));
+    }
+
+    Ok(out)
+}
+
+/// Deck を Markdown ファイルに書き出す
+pub fn write_deck_markdown(
+    conn: &Connection,
+    deck_id: &str,
+    path: impl AsRef<Path>,
+    options: &MarkdownOptions,
+) -> Result<()> {
+    let markdown = deck_to_markdown(conn, deck_id, options)?;
+    std::fs::write(path, markdown)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_in_memory;
+    use crate::error::JotDeckError;
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use rusqlite::params;
+
+    fn setup(sort_order: SortOrder) -> (Connection, String) {
+        let conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Ideas".to_string(),
+                sort_order,
+            },
+        )
+        .unwrap();
+
+        for (col_name, cards) in [("Inbox", vec!["first #idea", "second"]), ("Done", vec!["shipped"])] {
+            let col = column::create(
+                &conn,
+                NewColumn {
+                    deck_id: d.id.clone(),
+                    name: col_name.to_string(),
+                },
+            )
+            .unwrap();
+            for content in cards {
+                card::create(
+                    &conn,
+                    NewCard {
+                        column_id: col.id.clone(),
+                        content: content.to_string(),
+                    },
+                )
+                .unwrap();
+            }
+        }
+
+        // 作成日時を固定して並び順と出力を決定的にする
+        conn.execute(
+            "UPDATE cards SET created_at = '2024-01-01T09:00:00+00:00', updated_at = '2024-01-01T09:00:00+00:00' WHERE content = 'first #idea'",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE cards SET created_at = '2024-01-02T09:00:00+00:00', updated_at = '2024-01-03T10:30:00+00:00' WHERE content = 'second'",
+            [],
+        )
+        .unwrap();
+
+        (conn, d.id)
+    }
+
+    #[test]
+    fn test_deck_to_markdown() {
+        let (conn, deck_id) = setup(SortOrder::Manual);
+
+        let md = deck_to_markdown(&conn, &deck_id, &MarkdownOptions::default()).unwrap();
+        assert_eq!(
+            md,
+            "# Ideas\n\n## Inbox\n\nfirst #idea\n\n---\n\nsecond\n\n## Done\n\nshipped\n"
+        );
+    }
+
+    #[test]
+    fn test_respects_sort_order() {
+        let (conn, deck_id) = setup(SortOrder::CreatedDesc);
+
+        let md = deck_to_markdown(&conn, &deck_id, &MarkdownOptions::default()).unwrap();
+        let second = md.find("second").unwrap();
+        let first = md.find("first").unwrap();
+        assert!(second < first);
+    }
+
+    #[test]
+    fn test_metadata_options() {
+        let (conn, deck_id) = setup(SortOrder::Manual);
+        conn.execute("UPDATE cards SET score = 3 WHERE content = 'first #idea'", []).unwrap();
+
+        let options = MarkdownOptions {
+            include_score: true,
+            include_timestamps: true,
+            include_tags: true,
+        };
+        let md = deck_to_markdown(&conn, &deck_id, &options).unwrap();
+
+        assert!(md.contains("first #idea\n\n_★ 3 · 2024-01-01 09:00 UTC · #idea_\n"));
+        assert!(md.contains("second\n\n_2024-01-02 09:00 UTC (updated 2024-01-03 10:30 UTC)_\n"));
+
+        // スコアのみ: スコア 0 の Card にはメタデータ行を付けない
+        let options = MarkdownOptions {
+            include_score: true,
+            ..Default::default()
+        };
+        let md = deck_to_markdown(&conn, &deck_id, &options).unwrap();
+        assert!(md.contains("first #idea\n\n_★ 3_\n"));
+        assert!(md.contains("---\n\nsecond\n\n## Done"));
+    }
+
+    #[test]
+    fn test_skips_deleted() {
+        let (conn, deck_id) = setup(SortOrder::Manual);
+        let done = column::get_by_deck_id(&conn, &deck_id).unwrap().pop().unwrap();
+        column::soft_delete(&conn, &done.id).unwrap();
+        let second: String = conn
+            .query_row("SELECT id FROM cards WHERE content = 'second'", [], |row| row.get(0))
+            .unwrap();
+        card::soft_delete(&conn, &second).unwrap();
+
+        let md = deck_to_markdown(&conn, &deck_id, &MarkdownOptions::default()).unwrap();
+        assert_eq!(md, "# Ideas\n\n## Inbox\n\nfirst #idea\n");
+    }
+
+    #[test]
+    fn test_multiline_content() {
+        let (conn, deck_id) = setup(SortOrder::Manual);
+        conn.execute(
+            "UPDATE cards SET content = ?1 WHERE content = 'shipped'",
+            params!["line 1\nline 2\n\n"],
+        )
+        .unwrap();
+
+        let md = deck_to_markdown(&conn, &deck_id, &MarkdownOptions::default()).unwrap();
+        assert!(md.ends_with("## Done\n\nline 1\nline 2\n"));
+    }
+
+    #[test]
+    fn test_write_deck_markdown() {
+        let (conn, deck_id) = setup(SortOrder::Manual);
+        let path = std::env::temp_dir().join(format!("jot-deck-export-{}.md", ulid::Ulid::new()));
+
+        write_deck_markdown(&conn, &deck_id, &path, &MarkdownOptions::default()).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with("# Ideas\n"));
+        std::fs::remove_file(&path).unwrap();
+
+        let result = deck_to_markdown(&conn, "nonexistent", &MarkdownOptions::default());
+        assert!(matches!(result, Err(JotDeckError::NotFound(_))));
+    }
+}
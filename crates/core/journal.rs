@@ -0,0 +1,942 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Params};
+
+use crate::db;
+use crate::error::{JotDeckError, Result};
+use crate::models::{Operation, UndoResult};
+use crate::repository::tag;
+
+/// 変更を記録するテーブル（FTS の索引は cards のトリガーで追従するので含めない）
+const TRACKED_TABLES: &[&str] = &[
+    "decks",
+    "columns",
+    "cards",
+    "tags",
+    "card_tags",
+    "card_revisions",
+    "delete_stack",
+];
+
+/// 保持する操作グループの上限（古いものから捨てる）
+const MAX_GROUPS: i64 = 1000;
+
+/// 上限を超えた操作グループを捨てる間隔（操作の数）
+const PRUNE_INTERVAL: i64 = 100;
+
+fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(
+                col_idx,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            )
+        })
+}
+
+fn row_to_operation(row: &rusqlite::Row) -> rusqlite::Result<Operation> {
+    let created_at_str: String = row.get(4)?;
+    Ok(Operation {
+        id: row.get(0)?,
+        group_id: row.get(1)?,
+        label: row.get(2)?,
+        undone: row.get::<_, i32>(3)? != 0,
+        created_at: parse_datetime(&created_at_str, 4)?,
+    })
+}
+
+/// テーブルの列と主キー
+struct TableInfo {
+    columns: Vec<String>,
+    primary_key: Vec<String>,
+}
+
+impl TableInfo {
+    fn load(conn: &Connection, table: &str) -> Result<Self> {
+        let mut stmt = conn.prepare(&format!("PRAGMA main.table_info(\"{}\")", table))?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(1)?, row.get::<_, i32>(5)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let columns = rows.iter().map(|(name, _)| name.clone()).collect();
+        let mut pk: Vec<_> = rows.into_iter().filter(|(_, pk)| *pk > 0).collect();
+        pk.sort_by_key(|(_, pk)| *pk);
+
+        Ok(Self {
+            columns,
+            primary_key: pk.into_iter().map(|(name, _)| name).collect(),
+        })
+    }
+
+    /// 行を JSON オブジェクトにする式（`prefix` は `OLD.` / `NEW.` / 空）
+    fn json_expr(&self, prefix: &str) -> String {
+        let pairs: Vec<_> = self
+            .columns
+            .iter()
+            .map(|c| format!("'{c}', {prefix}\"{c}\""))
+            .collect();
+        format!("json_object({})", pairs.join(", "))
+    }
+
+    /// JSON で渡した行（`?1`）の主キーに一致する条件
+    fn key_condition(&self) -> String {
+        self.primary_key
+            .iter()
+            .map(|c| format!("\"{c}\" = json_extract(?1, '$.{c}')"))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    }
+}
+
+/// 記録用の一時テーブルとトリガーを作る（接続ごとに必要。`db::init_db` から呼ばれる）
+///
+/// トリガーは `journal_state.operation_id` が設定されている間だけ、
+/// 変更された行の前後の内容を `operation_changes` に書き込む。
+pub(crate) fn install(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS journal_state (
+             operation_id INTEGER,
+             group_id INTEGER,
+             group_depth INTEGER NOT NULL DEFAULT 0
+         );
+         INSERT INTO journal_state (operation_id, group_id, group_depth)
+         SELECT NULL, NULL, 0 WHERE NOT EXISTS (SELECT 1 FROM journal_state);",
+    )?;
+
+    for table in TRACKED_TABLES {
+        let info = TableInfo::load(conn, table)?;
+        let old = info.json_expr("OLD.");
+        let new = info.json_expr("NEW.");
+
+        conn.execute_batch(&format!(
+            "CREATE TEMP TRIGGER IF NOT EXISTS journal_{table}_insert AFTER INSERT ON {table}
+             WHEN (SELECT operation_id FROM journal_state) IS NOT NULL
+             BEGIN
+                 INSERT INTO operation_changes (operation_id, table_name, old_row, new_row)
+                 SELECT operation_id, '{table}', NULL, {new} FROM journal_state;
+             END;
+
+             CREATE TEMP TRIGGER IF NOT EXISTS journal_{table}_update AFTER UPDATE ON {table}
+             WHEN (SELECT operation_id FROM journal_state) IS NOT NULL
+             BEGIN
+                 INSERT INTO operation_changes (operation_id, table_name, old_row, new_row)
+                 SELECT operation_id, '{table}', {old}, {new} FROM journal_state
+                 WHERE {old} IS NOT {new};
+             END;
+
+             CREATE TEMP TRIGGER IF NOT EXISTS journal_{table}_delete AFTER DELETE ON {table}
+             WHEN (SELECT operation_id FROM journal_state) IS NOT NULL
+             BEGIN
+                 INSERT INTO operation_changes (operation_id, table_name, old_row, new_row)
+                 SELECT operation_id, '{table}', {old}, NULL FROM journal_state;
+             END;"
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// 記録中の操作 ID と開いているグループ
+fn state(conn: &Connection) -> Result<(Option<i64>, Option<i64>, i64)> {
+    let state = conn.query_row(
+        "SELECT operation_id, group_id, group_depth FROM journal_state",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    Ok(state)
+}
+
+/// 記録中にパニックしても記録状態を残さない
+struct Recording<'a> {
+    conn: &'a Connection,
+}
+
+impl Drop for Recording<'_> {
+    fn drop(&mut self) {
+        let _ = self
+            .conn
+            .execute("UPDATE journal_state SET operation_id = NULL", []);
+    }
+}
+
+/// 変更系の処理を 1 つの操作として記録する
+///
+/// `f` の中で変更された行の前後の内容を保存し、`undo` / `redo` で巻き戻せるようにする。
+/// 記録中に呼ばれた `record` は外側の操作に含める。何も変更しなかった操作は残さない。
+/// 操作の記録と `f` の変更は 1 つのトランザクション（SAVEPOINT）にまとめ、`f` が失敗したらどちらも巻き戻す。
+/// 新しい操作を記録すると redo できる操作は破棄される。
+pub fn record<T>(conn: &Connection, label: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let (current, group_id, group_depth) = state(conn)?;
+    if current.is_some() {
+        return f();
+    }
+
+    let sp = db::savepoint(conn)?;
+
+    let now = Utc::now();
+    conn.execute(
+        "INSERT INTO operations (group_id, label, created_at) VALUES (0, ?1, ?2)",
+        params![label, now.to_rfc3339()],
+    )?;
+    let operation_id = conn.last_insert_rowid();
+    let group_id = match group_id {
+        Some(group_id) => group_id,
+        None => {
+            if group_depth > 0 {
+                conn.execute(
+                    "UPDATE journal_state SET group_id = ?1",
+                    params![operation_id],
+                )?;
+            }
+            operation_id
+        }
+    };
+    conn.execute(
+        "UPDATE operations SET group_id = ?1 WHERE id = ?2",
+        params![group_id, operation_id],
+    )?;
+
+    let value = {
+        conn.execute(
+            "UPDATE journal_state SET operation_id = ?1",
+            params![operation_id],
+        )?;
+        let _recording = Recording { conn };
+        f()?
+    };
+
+    let changes: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM operation_changes WHERE operation_id = ?1",
+        params![operation_id],
+        |row| row.get(0),
+    )?;
+
+    if changes == 0 {
+        conn.execute("DELETE FROM operations WHERE id = ?1", params![operation_id])?;
+    } else {
+        discard_redo(conn, operation_id)?;
+        if operation_id % PRUNE_INTERVAL == 0 {
+            prune(conn)?;
+        }
+    }
+
+    sp.commit()?;
+    Ok(value)
+}
+
+/// redo 待ちの操作を捨てる
+///
+/// undo 済みの操作は常に最新の側に並ぶので、直前の操作が undo 済みのときだけ削除する。
+fn discard_redo(conn: &Connection, operation_id: i64) -> Result<()> {
+    let previous_undone: Option<bool> = conn
+        .query_row(
+            "SELECT undone FROM operations WHERE id < ?1 ORDER BY id DESC LIMIT 1",
+            params![operation_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if previous_undone == Some(true) {
+        conn.execute(
+            "DELETE FROM operation_changes WHERE operation_id IN (SELECT id FROM operations WHERE undone = 1)",
+            [],
+        )?;
+        conn.execute("DELETE FROM operations WHERE undone = 1", [])?;
+    }
+    Ok(())
+}
+
+/// 上限を超えた古い操作グループを捨てる
+fn prune(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM operation_changes WHERE operation_id IN (
+             SELECT id FROM operations WHERE group_id < (
+                 SELECT MIN(group_id) FROM (SELECT DISTINCT group_id FROM operations ORDER BY group_id DESC LIMIT ?1)
+             )
+         )",
+        params![MAX_GROUPS],
+    )?;
+    conn.execute(
+        "DELETE FROM operations WHERE group_id < (
+             SELECT MIN(group_id) FROM (SELECT DISTINCT group_id FROM operations ORDER BY group_id DESC LIMIT ?1)
+         )",
+        params![MAX_GROUPS],
+    )?;
+    Ok(())
+}
+
+/// グループを開始する。`end_group` までに記録した操作はまとめて undo / redo される（入れ子可）
+pub fn begin_group(conn: &Connection) -> Result<()> {
+    conn.execute("UPDATE journal_state SET group_depth = group_depth + 1", [])?;
+    Ok(())
+}
+
+/// `begin_group` で開始したグループを閉じる
+pub fn end_group(conn: &Connection) -> Result<()> {
+    let (_, _, depth) = state(conn)?;
+    if depth == 0 {
+        return Err(JotDeckError::InvalidOperation(
+            "No operation group is open".to_string(),
+        ));
+    }
+    conn.execute(
+        "UPDATE journal_state
+         SET group_depth = group_depth - 1,
+             group_id = CASE WHEN group_depth = 1 THEN NULL ELSE group_id END",
+        [],
+    )?;
+    Ok(())
+}
+
+/// `f` の中で記録した操作を 1 つのグループにまとめる
+pub fn group<T>(conn: &Connection, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    begin_group(conn)?;
+    let result = f();
+    end_group(conn)?;
+    result
+}
+
+/// 記録された操作を新しい順に取得する（redo 待ちのものを含む）
+pub fn list(conn: &Connection, limit: usize) -> Result<Vec<Operation>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, group_id, label, undone, created_at FROM operations ORDER BY id DESC LIMIT ?1",
+    )?;
+    let operations = stmt
+        .query_map(params![limit as i64], row_to_operation)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(operations)
+}
+
+/// 記録された操作をすべて削除する（undo / redo できなくなる）
+///
+/// 記録には変更前後の行の内容がそのまま残るので、物理削除した内容を消し切るときに使う。
+pub fn clear(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "DELETE FROM operation_changes;
+         DELETE FROM operations;",
+    )?;
+    Ok(())
+}
+
+/// `f` の中の変更を記録しない
+///
+/// 物理削除やバックアップからの復元など、取り消せない（取り消させない）処理に使う。
+pub(crate) fn unrecorded<T>(conn: &Connection, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let (current, _, _) = state(conn)?;
+    conn.execute("UPDATE journal_state SET operation_id = NULL", [])?;
+    let result = f();
+    conn.execute(
+        "UPDATE journal_state SET operation_id = ?1",
+        params![current],
+    )?;
+    result
+}
+
+/// 物理削除する行に触れた操作グループを記録から捨てる（捨てた操作の数を返す）
+///
+/// 記録には変更前後の行の内容がそのまま残るので、物理削除した内容を記録からも消す。
+/// 行がなくなれば巻き戻せないので、捨てても失うものはない。`ids` は削除する行の ID を返す SELECT 文で、
+/// 行の `id` のほか `card_id` / `column_id` / `deck_id` / `tag_id` / `entity_id` で参照している行も対象になる。
+/// 行を削除する前に呼ぶ。
+pub(crate) fn forget(conn: &Connection, ids: &str, params: impl Params) -> Result<usize> {
+    const KEYS: [&str; 6] = ["id", "card_id", "column_id", "deck_id", "tag_id", "entity_id"];
+    let conditions: Vec<String> = ["oc.old_row", "oc.new_row"]
+        .iter()
+        .flat_map(|row| {
+            KEYS.iter()
+                .map(move |key| format!("json_extract({row}, '$.{key}') IN (SELECT id FROM forgotten)"))
+        })
+        .collect();
+
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS forget_groups (group_id INTEGER PRIMARY KEY);
+         DELETE FROM forget_groups;",
+    )?;
+    conn.execute(
+        &format!(
+            "WITH forgotten(id) AS ({})
+             INSERT OR IGNORE INTO forget_groups
+             SELECT o.group_id FROM operation_changes oc JOIN operations o ON oc.operation_id = o.id
+             WHERE {}",
+            ids,
+            conditions.join(" OR ")
+        ),
+        params,
+    )?;
+    conn.execute(
+        "DELETE FROM operation_changes WHERE operation_id IN (
+             SELECT id FROM operations WHERE group_id IN (SELECT group_id FROM forget_groups)
+         )",
+        [],
+    )?;
+    let removed = conn.execute(
+        "DELETE FROM operations WHERE group_id IN (SELECT group_id FROM forget_groups)",
+        [],
+    )?;
+    conn.execute("DELETE FROM forget_groups", [])?;
+    Ok(removed)
+}
+
+/// 変更を 1 行分巻き戻す（または再適用する）
+///
+/// 行が `expected` の状態でなければ、操作の後に別の変更が入っているので何もせずに false を返す。
+fn apply_change(
+    conn: &Connection,
+    info: &TableInfo,
+    table: &str,
+    expected: Option<&str>,
+    target: Option<&str>,
+) -> Result<bool> {
+    let Some(key) = expected.or(target) else {
+        return Ok(true);
+    };
+
+    let current: Option<String> = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM {} WHERE {}",
+                info.json_expr(""),
+                table,
+                info.key_condition()
+            ),
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    if current.as_deref() != expected {
+        return Ok(false);
+    }
+
+    match target {
+        None => {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE {}", table, info.key_condition()),
+                params![key],
+            )?;
+        }
+        Some(row) if current.is_none() => {
+            let columns: Vec<_> = info.columns.iter().map(|c| format!("\"{c}\"")).collect();
+            let values: Vec<_> = info
+                .columns
+                .iter()
+                .map(|c| format!("json_extract(?1, '$.{c}')"))
+                .collect();
+            conn.execute(
+                &format!(
+                    "INSERT INTO {} ({}) SELECT {}",
+                    table,
+                    columns.join(", "),
+                    values.join(", ")
+                ),
+                params![row],
+            )?;
+        }
+        Some(row) => {
+            let assignments: Vec<_> = info
+                .columns
+                .iter()
+                .map(|c| format!("\"{c}\" = json_extract(?1, '$.{c}')"))
+                .collect();
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET {} WHERE {}",
+                    table,
+                    assignments.join(", "),
+                    info.key_condition()
+                ),
+                params![row],
+            )?;
+        }
+    }
+
+    Ok(true)
+}
+
+/// グループの操作をまとめて巻き戻す（`undo == false` なら再適用する）
+///
+/// 記録されていない変更と食い違う行があれば、何も変更せずに None を返す。
+fn apply_group(conn: &Connection, group_id: i64, undo: bool) -> Result<Option<Vec<Operation>>> {
+    let (current, _, depth) = state(conn)?;
+    if current.is_some() || depth > 0 {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot undo or redo while an operation is being recorded".to_string(),
+        ));
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    // 1 行ずつ戻すので、途中の状態で外部キーを検査しない
+    tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+
+    let changes = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT oc.table_name, oc.old_row, oc.new_row
+             FROM operation_changes oc
+             JOIN operations o ON oc.operation_id = o.id
+             WHERE o.group_id = ?1 AND o.undone = ?2
+             ORDER BY oc.id {}",
+            if undo { "DESC" } else { "ASC" }
+        ))?;
+        let changes = stmt
+            .query_map(params![group_id, !undo as i32], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        changes
+    };
+
+    let mut infos = std::collections::HashMap::new();
+    for (table, old_row, new_row) in &changes {
+        let Some(table) = TRACKED_TABLES.iter().find(|t| *t == table) else {
+            return Err(JotDeckError::InvalidOperation(format!(
+                "Unknown table in journal: {}",
+                table
+            )));
+        };
+        if !infos.contains_key(table) {
+            infos.insert(*table, TableInfo::load(&tx, table)?);
+        }
+        let info = &infos[table];
+
+        let applied = if undo {
+            apply_change(&tx, info, table, new_row.as_deref(), old_row.as_deref())?
+        } else {
+            apply_change(&tx, info, table, old_row.as_deref(), new_row.as_deref())?
+        };
+        if !applied {
+            return Ok(None);
+        }
+    }
+
+    // ゴミ箱から戻った Card は、クリーンアップで外されたタグの関連を本文から付け直す
+    let card_ids: std::collections::BTreeSet<String> = changes
+        .iter()
+        .filter(|(table, _, _)| table == "cards")
+        .filter_map(|(_, old_row, new_row)| {
+            let row = old_row.as_deref().or(new_row.as_deref())?;
+            serde_json::from_str::<serde_json::Value>(row).ok()?["id"].as_str().map(String::from)
+        })
+        .collect();
+    for id in &card_ids {
+        tag::resync_live_cards(&tx, "c.id = ?1", params![id])?;
+    }
+
+    tx.execute(
+        "UPDATE operations SET undone = ?1 WHERE group_id = ?2",
+        params![undo as i32, group_id],
+    )?;
+
+    let operations = {
+        let mut stmt = tx.prepare(
+            "SELECT id, group_id, label, undone, created_at FROM operations WHERE group_id = ?1 ORDER BY id",
+        )?;
+        let operations = stmt
+            .query_map(params![group_id], row_to_operation)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        operations
+    };
+
+    tx.commit()?;
+    Ok(Some(operations))
+}
+
+/// 条件に合う操作を記録から捨てる
+fn discard(conn: &Connection, condition: &str, group_id: i64) -> Result<Vec<Operation>> {
+    let tx = conn.unchecked_transaction()?;
+    let operations = {
+        let mut stmt = tx.prepare(&format!(
+            "SELECT id, group_id, label, undone, created_at FROM operations WHERE {} ORDER BY id",
+            condition
+        ))?;
+        let operations = stmt
+            .query_map(params![group_id], row_to_operation)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        operations
+    };
+    tx.execute(
+        &format!(
+            "DELETE FROM operation_changes WHERE operation_id IN (SELECT id FROM operations WHERE {})",
+            condition
+        ),
+        params![group_id],
+    )?;
+    tx.execute(&format!("DELETE FROM operations WHERE {}", condition), params![group_id])?;
+    tx.commit()?;
+    Ok(operations)
+}
+
+/// 最後の操作グループを取り消す（取り消せる操作がなければ空）
+///
+/// 操作の後に記録されていない変更（クリーンアップでの完全削除など）が入った行があれば、
+/// そのグループとそれより古い操作は二度と取り消せないので、何も変更せずに記録から捨てて `discarded` で返す。
+pub fn undo(conn: &Connection) -> Result<UndoResult> {
+    let group_id: Option<i64> = conn
+        .query_row(
+            "SELECT group_id FROM operations WHERE undone = 0 ORDER BY id DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let Some(group_id) = group_id else {
+        return Ok(UndoResult::default());
+    };
+    match apply_group(conn, group_id, true)? {
+        Some(applied) => Ok(UndoResult {
+            applied,
+            discarded: Vec::new(),
+        }),
+        None => Ok(UndoResult {
+            applied: Vec::new(),
+            discarded: discard(conn, "undone = 0 AND group_id <= ?1", group_id)?,
+        }),
+    }
+}
+
+/// 最後に取り消した操作グループをやり直す（やり直せる操作がなければ空）
+///
+/// 記録されていない変更と食い違う行があれば、redo 待ちの操作をすべて捨てて `discarded` で返す。
+pub fn redo(conn: &Connection) -> Result<UndoResult> {
+    let group_id: Option<i64> = conn
+        .query_row(
+            "SELECT group_id FROM operations WHERE undone = 1 ORDER BY id ASC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let Some(group_id) = group_id else {
+        return Ok(UndoResult::default());
+    };
+    match apply_group(conn, group_id, false)? {
+        Some(applied) => Ok(UndoResult {
+            applied,
+            discarded: Vec::new(),
+        }),
+        None => Ok(UndoResult {
+            applied: Vec::new(),
+            discarded: discard(conn, "undone = 1 AND group_id >= ?1", group_id)?,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{create_file_db, create_in_memory};
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::{card, column, deck, delete_stack, tag};
+
+    fn setup() -> (Connection, String, Vec<String>, Vec<String>) {
+        let conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Test Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+
+        let mut columns = Vec::new();
+        let mut cards = Vec::new();
+        for (name, count) in [("Todo", 4), ("Done", 2)] {
+            let col = column::create(
+                &conn,
+                NewColumn {
+                    deck_id: d.id.clone(),
+                    name: name.to_string(),
+                },
+            )
+            .unwrap();
+            for i in 0..count {
+                let c = card::create(
+                    &conn,
+                    NewCard {
+                        column_id: col.id.clone(),
+                        content: format!("{} {}", name, i),
+                    },
+                )
+                .unwrap();
+                cards.push(c.id);
+            }
+            columns.push(col.id);
+        }
+
+        (conn, d.id, columns, cards)
+    }
+
+    /// Card と Column の全行（updated_at を含む）
+    fn snapshot(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT 'card|' || id || '|' || column_id || '|' || rank || '|' || score || '|' || updated_at || '|' || IFNULL(deleted_at, '') FROM cards
+                 UNION ALL
+                 SELECT 'column|' || id || '|' || rank || '|' || updated_at || '|' || IFNULL(deleted_at, '') FROM columns
+                 ORDER BY 1",
+            )
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_undo_redo_move_to_position() {
+        let (conn, _, columns, cards) = setup();
+        let before = snapshot(&conn);
+
+        card::move_to_position(&conn, &cards[0], 3).unwrap();
+        let after = snapshot(&conn);
+        assert_ne!(before, after);
+
+        let undone = undo(&conn).unwrap().applied;
+        assert_eq!(undone.len(), 1);
+        assert_eq!(undone[0].label, "card.move_to_position");
+        assert_eq!(snapshot(&conn), before);
+        let ids: Vec<_> = card::get_by_column_id(&conn, &columns[0])
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, cards[..4]);
+
+        assert_eq!(redo(&conn).unwrap().applied.len(), 1);
+        assert_eq!(snapshot(&conn), after);
+        assert!(redo(&conn).unwrap().applied.is_empty());
+    }
+
+    #[test]
+    fn test_undo_move_to_column_and_column_reorder() {
+        let (conn, _, columns, cards) = setup();
+        let initial = snapshot(&conn);
+
+        card::move_to_column(&conn, &cards[1], &columns[1]).unwrap();
+        let moved = snapshot(&conn);
+        column::move_to_position(&conn, &columns[1], 0).unwrap();
+
+        undo(&conn).unwrap();
+        assert_eq!(snapshot(&conn), moved);
+        undo(&conn).unwrap();
+        assert_eq!(snapshot(&conn), initial);
+
+        // 作成もすべて巻き戻せる
+        while !undo(&conn).unwrap().applied.is_empty() {}
+        assert!(deck::get_all(&conn).unwrap().is_empty());
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM cards_fts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_undo_soft_delete_and_tags() {
+        let (conn, deck_id, _, cards) = setup();
+
+        card::update_content(&conn, &cards[0], "now with #tag").unwrap();
+        card::soft_delete(&conn, &cards[0]).unwrap();
+        assert_eq!(delete_stack::list_stack(&conn, &deck_id).unwrap().len(), 1);
+
+        undo(&conn).unwrap();
+        assert!(card::get_by_id(&conn, &cards[0]).unwrap().deleted_at.is_none());
+        assert!(delete_stack::list_stack(&conn, &deck_id).unwrap().is_empty());
+
+        undo(&conn).unwrap();
+        let c = card::get_by_id(&conn, &cards[0]).unwrap();
+        assert_eq!(c.content, "Todo 0");
+        assert!(tag::get_tags_by_card(&conn, &c.id).unwrap().is_empty());
+        assert!(card::history(&conn, &c.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_group() {
+        let (conn, _, columns, cards) = setup();
+        let before = snapshot(&conn);
+
+        group(&conn, || {
+            card::update_score(&conn, &cards[0], 1)?;
+            card::move_to_column(&conn, &cards[0], &columns[1])?;
+            card::soft_delete(&conn, &cards[4])
+        })
+        .unwrap();
+
+        let undone = undo(&conn).unwrap().applied;
+        let labels: Vec<_> = undone.iter().map(|o| o.label.as_str()).collect();
+        assert_eq!(labels, ["card.update_score", "card.move_to_column", "card.soft_delete"]);
+        assert!(undone.iter().all(|o| o.group_id == undone[0].id));
+        assert_eq!(snapshot(&conn), before);
+
+        // 入れ子のグループは外側にまとまる
+        begin_group(&conn).unwrap();
+        card::update_score(&conn, &cards[1], 1).unwrap();
+        group(&conn, || card::update_score(&conn, &cards[2], 1)).unwrap();
+        assert!(undo(&conn).is_err());
+        end_group(&conn).unwrap();
+        assert!(end_group(&conn).is_err());
+
+        assert_eq!(undo(&conn).unwrap().applied.len(), 2);
+        assert_eq!(card::get_by_id(&conn, &cards[1]).unwrap().score, 0);
+        assert_eq!(card::get_by_id(&conn, &cards[2]).unwrap().score, 0);
+    }
+
+    #[test]
+    fn test_new_operation_clears_redo() {
+        let (conn, _, _, cards) = setup();
+
+        card::update_score(&conn, &cards[0], 1).unwrap();
+        undo(&conn).unwrap();
+        card::update_score(&conn, &cards[1], 1).unwrap();
+
+        assert!(redo(&conn).unwrap().applied.is_empty());
+        assert_eq!(card::get_by_id(&conn, &cards[0]).unwrap().score, 0);
+
+        // 失敗した操作や何も変えない操作は記録しない
+        let count = list(&conn, 100).unwrap().len();
+        assert!(card::update_score(&conn, "nonexistent", 1).is_err());
+        tag::rename(&conn, &deck::get_all(&conn).unwrap()[0].id, "none", "other", true).ok();
+        assert_eq!(list(&conn, 100).unwrap().len(), count);
+        assert!(!list(&conn, 1).unwrap()[0].undone);
+    }
+
+    #[test]
+    fn test_failed_operation_is_rolled_back() {
+        let (conn, _, _, cards) = setup();
+        let count = list(&conn, 100).unwrap().len();
+
+        let result: Result<()> = record(&conn, "test.fail", || {
+            card::update_score(&conn, &cards[0], 1)?;
+            Err(JotDeckError::InvalidOperation("fail".to_string()))
+        });
+
+        // 途中までの変更も操作の記録も残らない
+        assert!(result.is_err());
+        assert_eq!(card::get_by_id(&conn, &cards[0]).unwrap().score, 0);
+        assert_eq!(list(&conn, 100).unwrap().len(), count);
+        assert!(state(&conn).unwrap().0.is_none());
+    }
+
+    #[test]
+    fn test_prune_old_groups() {
+        let (conn, _, _, cards) = setup();
+
+        for _ in 0..MAX_GROUPS + PRUNE_INTERVAL {
+            card::update_score(&conn, &cards[0], 1).unwrap();
+        }
+
+        let groups: i64 = conn
+            .query_row("SELECT COUNT(DISTINCT group_id) FROM operations", [], |row| row.get(0))
+            .unwrap();
+        assert!((MAX_GROUPS..MAX_GROUPS + PRUNE_INTERVAL).contains(&groups), "{}", groups);
+    }
+
+    #[test]
+    fn test_undo_conflict() {
+        let (conn, _, _, cards) = setup();
+
+        card::update_score(&conn, &cards[0], 1).unwrap();
+        // 記録されない変更が入った行は巻き戻さない
+        conn.execute("UPDATE cards SET score = 5 WHERE id = ?1", params![&cards[0]])
+            .unwrap();
+
+        let result = undo(&conn).unwrap();
+        assert!(result.applied.is_empty());
+        assert_eq!(result.discarded.last().unwrap().label, "card.update_score");
+        assert_eq!(card::get_by_id(&conn, &cards[0]).unwrap().score, 5);
+    }
+
+    #[test]
+    fn test_undo_after_row_deleted() {
+        let (conn, _, _, cards) = setup();
+        let count = list(&conn, 100).unwrap().len();
+
+        card::update_score(&conn, &cards[0], 1).unwrap();
+        card::update_score(&conn, &cards[1], 1).unwrap();
+        // 記録されない物理削除で、最新の操作の行がなくなる
+        conn.execute("DELETE FROM cards WHERE id = ?1", params![&cards[1]])
+            .unwrap();
+
+        // 食い違ったグループとそれより古い操作は捨てられ、以後の undo は失敗しない
+        let result = undo(&conn).unwrap();
+        assert!(result.applied.is_empty());
+        assert_eq!(result.discarded.len(), count + 2);
+        assert_eq!(result.discarded.last().unwrap().label, "card.update_score");
+        assert!(list(&conn, 100).unwrap().is_empty());
+        assert_eq!(card::get_by_id(&conn, &cards[0]).unwrap().score, 1);
+
+        let result = undo(&conn).unwrap();
+        assert!(result.applied.is_empty() && result.discarded.is_empty());
+
+        // 新しい操作は普通に取り消せる
+        card::update_score(&conn, &cards[2], 1).unwrap();
+        assert_eq!(undo(&conn).unwrap().applied.len(), 1);
+        assert_eq!(card::get_by_id(&conn, &cards[2]).unwrap().score, 0);
+    }
+
+    #[test]
+    fn test_redo_conflict_discards_redo() {
+        let (conn, _, _, cards) = setup();
+
+        card::update_score(&conn, &cards[0], 1).unwrap();
+        card::update_score(&conn, &cards[0], 1).unwrap();
+        undo(&conn).unwrap();
+        undo(&conn).unwrap();
+        conn.execute("UPDATE cards SET score = 5 WHERE id = ?1", params![&cards[0]])
+            .unwrap();
+
+        let result = redo(&conn).unwrap();
+        assert!(result.applied.is_empty());
+        assert_eq!(result.discarded.len(), 2);
+        assert!(redo(&conn).unwrap().discarded.is_empty());
+        assert_eq!(card::get_by_id(&conn, &cards[0]).unwrap().score, 5);
+    }
+
+    #[test]
+    fn test_persists_across_reopen() {
+        let path = std::env::temp_dir().join(format!("jot-deck-journal-{}.db", ulid::Ulid::new()));
+        let path_str = path.to_str().unwrap();
+
+        let card_id = {
+            let conn = create_file_db(path_str).unwrap();
+            let d = deck::create(
+                &conn,
+                NewDeck {
+                    name: "Test Deck".to_string(),
+                    sort_order: SortOrder::default(),
+                },
+            )
+            .unwrap();
+            let col = column::create(
+                &conn,
+                NewColumn {
+                    deck_id: d.id,
+                    name: "Todo".to_string(),
+                },
+            )
+            .unwrap();
+            let c = card::create(
+                &conn,
+                NewCard {
+                    column_id: col.id,
+                    content: "hello".to_string(),
+                },
+            )
+            .unwrap();
+            card::update_content(&conn, &c.id, "edited").unwrap();
+            c.id
+        };
+
+        let conn = create_file_db(path_str).unwrap();
+        assert_eq!(undo(&conn).unwrap().applied[0].label, "card.update_content");
+        assert_eq!(card::get_by_id(&conn, &card_id).unwrap().content, "hello");
+        redo(&conn).unwrap();
+        assert_eq!(card::get_by_id(&conn, &card_id).unwrap().content, "edited");
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,15 +1,24 @@
+pub mod backup;
 pub mod cleanup;
 pub mod db;
 pub mod error;
+pub mod export;
+pub mod journal;
+pub mod maintenance;
 pub mod models;
+pub mod pool;
+pub mod rank;
 pub mod repository;
+pub mod tag_query;
 
-pub use repository::{card, column, deck, tag};
+pub use repository::{card, column, deck, delete_stack, search, settings, tag};
 
-pub use cleanup::run_cleanup_batch;
+pub use cleanup::{run_cleanup_batch, CleanupPolicy, Retention};
 pub use db::{create_file_db, create_in_memory};
 pub use error::{JotDeckError, Result};
+pub use maintenance::{run_maintenance, MaintenanceReport, MaintenanceTask};
 pub use models::*;
+pub use pool::ConnectionPool;
 
 // Re-export rusqlite types for Tauri integration
 pub use rusqlite::Connection;
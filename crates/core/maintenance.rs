@@ -0,0 +1,205 @@
+use chrono::{DateTime, Utc};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::cleanup::{self, CleanupResult};
+use crate::db::{self, IntegrityReport};
+use crate::error::Result;
+
+/// 定期メンテナンスの作業
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    /// 削除ポリシーに従ってゴミ箱を物理削除する
+    Cleanup,
+    /// `PRAGMA optimize` で統計情報を更新する
+    Optimize,
+    /// WAL をデータベースファイルに書き戻す
+    Checkpoint,
+    /// 整合性をチェックする（修復はしない）
+    Integrity,
+}
+
+impl MaintenanceTask {
+    /// 実行する順番に並べたすべての作業
+    pub const ALL: [MaintenanceTask; 4] = [
+        MaintenanceTask::Cleanup,
+        MaintenanceTask::Optimize,
+        MaintenanceTask::Checkpoint,
+        MaintenanceTask::Integrity,
+    ];
+}
+
+/// WAL チェックポイントの結果
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CheckpointResult {
+    /// 他の接続が使用中で書き戻しきれなかった
+    pub busy: bool,
+    /// WAL のフレーム数（WAL モードでなければ -1）
+    pub log_frames: i64,
+    /// 書き戻したフレーム数（WAL モードでなければ -1）
+    pub checkpointed_frames: i64,
+}
+
+/// 失敗した作業とエラーメッセージ
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskError {
+    pub task: MaintenanceTask,
+    pub message: String,
+}
+
+/// 定期メンテナンスの結果
+#[derive(Debug, Clone, Serialize)]
+pub struct MaintenanceReport {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub cleanup: Option<CleanupResult>,
+    pub optimized: bool,
+    pub checkpoint: Option<CheckpointResult>,
+    pub integrity: Option<IntegrityReport>,
+    /// 失敗した作業（失敗しても残りの作業は続ける）
+    pub errors: Vec<TaskError>,
+}
+
+impl MaintenanceReport {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        MaintenanceReport {
+            started_at: now,
+            finished_at: now,
+            cleanup: None,
+            optimized: false,
+            checkpoint: None,
+            integrity: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty() && self.integrity.as_ref().is_none_or(IntegrityReport::is_ok)
+    }
+
+    /// 作業を 1 つ実行して結果を記録する
+    ///
+    /// 作業ごとに呼べるので、呼び出し側は作業の合間に接続のロックを手放せる。
+    pub fn run(&mut self, conn: &mut Connection, task: MaintenanceTask) {
+        let result = match task {
+            MaintenanceTask::Cleanup => cleanup::run_cleanup_batch(conn).map(|r| self.cleanup = Some(r)),
+            MaintenanceTask::Optimize => optimize(conn).map(|()| self.optimized = true),
+            MaintenanceTask::Checkpoint => checkpoint(conn).map(|r| self.checkpoint = Some(r)),
+            MaintenanceTask::Integrity => db::check_integrity(conn).map(|r| self.integrity = Some(r)),
+        };
+        if let Err(e) = result {
+            self.errors.push(TaskError {
+                task,
+                message: e.to_string(),
+            });
+        }
+        self.finished_at = Utc::now();
+    }
+}
+
+impl Default for MaintenanceReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// クエリプランナーの統計情報を必要に応じて更新する
+pub fn optimize(conn: &Connection) -> Result<()> {
+    conn.execute_batch("PRAGMA optimize")?;
+    Ok(())
+}
+
+/// WAL をデータベースファイルに書き戻す
+///
+/// PASSIVE モードなので、読み書き中の他の接続を待たない。
+pub fn checkpoint(conn: &Connection) -> Result<CheckpointResult> {
+    let result = conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |row| {
+        Ok(CheckpointResult {
+            busy: row.get::<_, i64>(0)? != 0,
+            log_frames: row.get(1)?,
+            checkpointed_frames: row.get(2)?,
+        })
+    })?;
+    Ok(result)
+}
+
+/// すべてのメンテナンス作業を続けて実行する
+pub fn run_maintenance(conn: &mut Connection) -> MaintenanceReport {
+    let mut report = MaintenanceReport::new();
+    for task in MaintenanceTask::ALL {
+        report.run(conn, task);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_in_memory;
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::{card, column, deck};
+    use chrono::Duration;
+    use rusqlite::params;
+
+    #[test]
+    fn test_run_maintenance() {
+        let mut conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "D".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "C".to_string(),
+            },
+        )
+        .unwrap();
+        let c = card::create(
+            &conn,
+            NewCard {
+                column_id: col.id.clone(),
+                content: "old".to_string(),
+            },
+        )
+        .unwrap();
+        card::soft_delete(&conn, &c.id).unwrap();
+        let old_date = (Utc::now() - Duration::days(31)).to_rfc3339();
+        conn.execute(
+            "UPDATE cards SET deleted_at = ?1 WHERE id = ?2",
+            params![&old_date, &c.id],
+        )
+        .unwrap();
+
+        let report = run_maintenance(&mut conn);
+        assert!(report.is_ok(), "{:?}", report.errors);
+        assert_eq!(report.cleanup.unwrap().deleted_cards, 1);
+        assert!(report.optimized);
+        // インメモリ DB は WAL モードではない
+        let checkpoint = report.checkpoint.unwrap();
+        assert_eq!(checkpoint.log_frames, -1);
+        assert!(report.integrity.unwrap().is_ok());
+        assert!(report.finished_at >= report.started_at);
+    }
+
+    #[test]
+    fn test_task_error_does_not_stop_others() {
+        let mut conn = create_in_memory().unwrap();
+        conn.execute("DROP TABLE settings", []).unwrap();
+
+        let report = run_maintenance(&mut conn);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].task, MaintenanceTask::Cleanup);
+        assert!(report.cleanup.is_none());
+        assert!(report.optimized);
+        assert!(report.checkpoint.is_some());
+        assert!(!report.is_ok());
+    }
+}
@@ -2,9 +2,14 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 /// カードのソート順
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+///
+/// Card の `position`（手動の並び順）はソート順に関係なく常に維持される。
+/// `Manual` 以外のソート順は表示時に `position` を無視して並べ替えるだけなので、
+/// 別のソート順で表示中に行った手動移動は `Manual` に切り替えたときに反映される。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SortOrder {
     /// 作成日時の新しい順（デフォルト）
+    #[default]
     CreatedDesc,
     /// 作成日時の古い順
     CreatedAsc,
@@ -12,12 +17,8 @@ pub enum SortOrder {
     ScoreDesc,
     /// スコアの低い順
     ScoreAsc,
-}
-
-impl Default for SortOrder {
-    fn default() -> Self {
-        Self::CreatedDesc
-    }
+    /// 手動で並べた順（`position` 順）
+    Manual,
 }
 
 impl SortOrder {
@@ -27,6 +28,7 @@ impl SortOrder {
             Self::CreatedAsc => "created_asc",
             Self::ScoreDesc => "score_desc",
             Self::ScoreAsc => "score_asc",
+            Self::Manual => "manual",
         }
     }
 
@@ -35,6 +37,7 @@ impl SortOrder {
             "created_asc" => Self::CreatedAsc,
             "score_desc" => Self::ScoreDesc,
             "score_asc" => Self::ScoreAsc,
+            "manual" => Self::Manual,
             _ => Self::CreatedDesc,
         }
     }
@@ -48,6 +51,7 @@ pub struct Deck {
     pub sort_order: SortOrder,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 /// Column - Card を縦に並べる領域
@@ -56,10 +60,16 @@ pub struct Column {
     pub id: String,
     pub deck_id: String,
     pub name: String,
+    /// Deck 内の並び順（削除されていない Column の中で 0 から。rank から求める）
     pub position: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    /// Deck 削除による連動削除かどうか
+    pub deleted_with_deck: bool,
+    /// 並び順（所属する Deck を含む）を最後に変えた日時。作成後に動かしていなければ None
+    #[serde(default)]
+    pub position_updated_at: Option<DateTime<Utc>>,
 }
 
 /// Card - テキスト入力の最小単位
@@ -69,19 +79,28 @@ pub struct Card {
     pub column_id: String,
     pub content: String,
     pub score: i32,
+    /// Column 内の並び順（削除されていない Card の中で 0 から。rank から求める）
     pub position: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
     /// Column 削除による連動削除かどうか
     pub deleted_with_column: bool,
+    /// Deck 削除による連動削除かどうか
+    pub deleted_with_deck: bool,
+    /// 並び順（所属する Column を含む）を最後に変えた日時。作成後に動かしていなければ None
+    #[serde(default)]
+    pub position_updated_at: Option<DateTime<Utc>>,
 }
 
-/// Tag - カード本文中の #word 形式
+/// Tag - カード本文中の #word 形式（`#a/b/c` のように `/` で階層化できる）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tag {
     pub id: String,
     pub name: String,
+    /// 親タグの ID（`a/b` に対する `a`）。階層を持たないタグは None
+    #[serde(default)]
+    pub parent_id: Option<String>,
 }
 
 /// Card と Tag の関連
@@ -91,6 +110,73 @@ pub struct CardTag {
     pub tag_id: String,
 }
 
+/// Card の編集履歴（上書きされる前の本文）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CardRevision {
+    pub id: i64,
+    pub card_id: String,
+    pub content: String,
+    /// この本文が上書きされた日時
+    pub created_at: DateTime<Utc>,
+}
+
+/// 操作ジャーナルに記録された操作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: i64,
+    /// まとめて undo / redo される操作のグループ（先頭の操作の ID）
+    pub group_id: i64,
+    /// 操作の種類（`card.move_to_column` など）
+    pub label: String,
+    /// undo 済みで redo を待っている
+    pub undone: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// undo / redo の結果
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UndoResult {
+    /// 巻き戻した（やり直した）操作
+    pub applied: Vec<Operation>,
+    /// 記録されていない変更と食い違ったので捨てた操作
+    pub discarded: Vec<Operation>,
+}
+
+/// 削除スタックに積まれるエンティティの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedEntityType {
+    Card,
+    Column,
+}
+
+impl DeletedEntityType {
+    pub fn to_db_value(&self) -> &'static str {
+        match self {
+            Self::Card => "card",
+            Self::Column => "column",
+        }
+    }
+
+    pub fn from_db_value(s: &str) -> Option<Self> {
+        match s {
+            "card" => Some(Self::Card),
+            "column" => Some(Self::Column),
+            _ => None,
+        }
+    }
+}
+
+/// 削除スタックのエントリ（ゴミ箱の削除順序）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteStackEntry {
+    pub id: i64,
+    pub deck_id: String,
+    pub entity_type: DeletedEntityType,
+    pub entity_id: String,
+    pub deleted_at: DateTime<Utc>,
+}
+
 // 作成用の構造体（ID や timestamp を含まない）
 
 #[derive(Debug)]
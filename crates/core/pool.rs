@@ -0,0 +1,179 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LockResult, Mutex, MutexGuard, TryLockError};
+
+use rusqlite::Connection;
+
+use crate::db::{create_file_db, open_reader};
+use crate::error::Result;
+
+/// 書き込み用の接続 1 本と読み取り用の接続をまとめたもの
+///
+/// 書き込みは 1 本の接続に直列化する。読み取りは WAL のスナップショットを読むので、
+/// 長い検索やエクスポートの最中でも書き込みを待たせない。
+pub struct ConnectionPool {
+    writer: Mutex<Connection>,
+    readers: Vec<Mutex<Connection>>,
+    next: AtomicUsize,
+}
+
+impl ConnectionPool {
+    /// ファイルデータベースを開き、読み取り用の接続を `readers` 本用意する
+    ///
+    /// `readers` が 0 なら読み取りも書き込み用の接続で行う。
+    pub fn open(path: &str, readers: usize) -> Result<Self> {
+        let writer = create_file_db(path)?;
+        let readers = (0..readers)
+            .map(|_| open_reader(path).map(Mutex::new))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ConnectionPool {
+            writer: Mutex::new(writer),
+            readers,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// 書き込み用の接続を取得する（使用中なら空くまで待つ）
+    pub fn writer(&self) -> LockResult<MutexGuard<'_, Connection>> {
+        self.writer.lock()
+    }
+
+    /// 読み取り用の接続を取得する
+    ///
+    /// 空いている接続を順に探し、すべて使用中なら 1 本が空くまで待つ。
+    pub fn reader(&self) -> LockResult<MutexGuard<'_, Connection>> {
+        if self.readers.is_empty() {
+            return self.writer();
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        for i in 0..self.readers.len() {
+            match self.readers[(start + i) % self.readers.len()].try_lock() {
+                Ok(conn) => return Ok(conn),
+                Err(TryLockError::Poisoned(e)) => return Err(e),
+                Err(TryLockError::WouldBlock) => {}
+            }
+        }
+        self.readers[start % self.readers.len()].lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::{card, column, deck, delete_stack, search, tag};
+    use crate::{backup, export, journal};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("jot-deck-{}-{}", name, ulid::Ulid::new()))
+    }
+
+    #[test]
+    fn test_reads_run_during_write() {
+        let path = temp_path("pool.db");
+        let pool = ConnectionPool::open(path.to_str().unwrap(), 2).unwrap();
+
+        let (deck_id, card_id) = {
+            let conn = pool.writer().unwrap();
+            let d = deck::create(
+                &conn,
+                NewDeck {
+                    name: "Deck".to_string(),
+                    sort_order: SortOrder::default(),
+                },
+            )
+            .unwrap();
+            let col = column::create(
+                &conn,
+                NewColumn {
+                    deck_id: d.id.clone(),
+                    name: "Col".to_string(),
+                },
+            )
+            .unwrap();
+            let c = card::create(
+                &conn,
+                NewCard {
+                    column_id: col.id,
+                    content: "#idea hello".to_string(),
+                },
+            )
+            .unwrap();
+            (d.id, c.id)
+        };
+
+        // 書き込み用の接続を握ったままでも読み取り用の接続は使える
+        let writer = pool.writer().unwrap();
+        let tx = writer.unchecked_transaction().unwrap();
+        tx.execute(
+            "UPDATE cards SET content = '#idea edited' WHERE id = ?1",
+            [&card_id],
+        )
+        .unwrap();
+
+        let first = pool.reader().unwrap();
+        let second = pool.reader().unwrap();
+        assert_eq!(card::get_by_id(&first, &card_id).unwrap().content, "#idea hello");
+        assert_eq!(deck::get_all(&second).unwrap().len(), 1);
+        drop(first);
+        drop(second);
+
+        tx.commit().unwrap();
+        drop(writer);
+        assert_eq!(
+            card::get_by_id(&pool.reader().unwrap(), &card_id).unwrap().content,
+            "#idea edited"
+        );
+
+        // 読み取りのコマンドは読み取り用の接続で動く（書き込まない）
+        let reader = pool.reader().unwrap();
+        deck::get_by_id(&reader, &deck_id).unwrap();
+        deck::get_deleted(&reader).unwrap();
+        let columns = column::get_by_deck_id(&reader, &deck_id).unwrap();
+        column::get_by_id(&reader, &columns[0].id).unwrap();
+        column::get_deleted(&reader, &deck_id).unwrap();
+        card::get_by_column_id(&reader, &columns[0].id).unwrap();
+        card::get_by_column_id_sorted(&reader, &columns[0].id, SortOrder::ScoreDesc).unwrap();
+        card::get_by_column_id_in_deck_order(&reader, &columns[0].id).unwrap();
+        assert_eq!(search::search(&reader, &deck_id, "edited", 10).unwrap().len(), 1);
+        assert_eq!(tag::get_tags_by_deck(&reader, &deck_id).unwrap().len(), 1);
+        assert_eq!(tag::get_all_live(&reader).unwrap().len(), 1);
+        tag::get_tag_tree_by_deck(&reader, &deck_id).unwrap();
+        assert_eq!(tag::get_cards_by_tag(&reader, &deck_id, "idea").unwrap(), vec![card_id.clone()]);
+        tag::get_tag_suggestions_ordered(&reader, &deck_id, "i", Default::default()).unwrap();
+        tag::query_cards(&reader, &deck_id, "idea").unwrap();
+        tag::stats(&reader, &deck_id).unwrap();
+        card::history(&reader, &card_id).unwrap();
+        card::get_deleted_by_deck(&reader, &deck_id).unwrap();
+        delete_stack::list_stack(&reader, &deck_id).unwrap();
+        journal::list(&reader, 10).unwrap();
+        assert!(crate::db::check_integrity(&reader).unwrap().is_ok());
+        export::deck_to_markdown(&reader, &deck_id, &Default::default()).unwrap();
+        let backup_path = temp_path("pool.json");
+        backup::write_backup(&reader, backup_path.to_str().unwrap()).unwrap();
+        drop(reader);
+
+        drop(pool);
+        std::fs::remove_file(&backup_path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_without_readers_uses_writer() {
+        let path = temp_path("pool.db");
+        let pool = ConnectionPool::open(path.to_str().unwrap(), 0).unwrap();
+
+        let conn = pool.reader().unwrap();
+        deck::create(
+            &conn,
+            NewDeck {
+                name: "Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        drop(conn);
+
+        drop(pool);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,267 @@
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::error::{JotDeckError, Result};
+
+/// 並び順のキー（rank）
+///
+/// Column / Card の並び順は `0-9A-Za-z` の 62 進の小数として比較できる文字列で持つ。
+/// 2 つのキーの間には必ず別のキーを作れるので、挿入・移動は対象の 1 行だけを書き換えればよい。
+/// 末尾が `0` のキーは直前との間にキーを作れないので作らない。
+const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: usize = 62;
+
+/// 末尾・先頭への追加で増減させる桁数（この桁数の範囲で約 1,400 万件まで長さが伸びない）
+const STEP_DEPTH: usize = 4;
+
+/// これより長いキーができたら兄弟の rank を振り直す
+const MAX_RANK_LEN: usize = 64;
+
+fn digit_value(c: u8) -> Option<usize> {
+    match c {
+        b'0'..=b'9' => Some((c - b'0') as usize),
+        b'A'..=b'Z' => Some((c - b'A') as usize + 10),
+        b'a'..=b'z' => Some((c - b'a') as usize + 36),
+        _ => None,
+    }
+}
+
+/// 正しい形式のキーか
+pub fn is_valid(key: &str) -> bool {
+    !key.is_empty() && !key.ends_with('0') && key.bytes().all(|c| digit_value(c).is_some())
+}
+
+/// 先頭 STEP_DEPTH 桁の値（足りない桁は 0）
+fn prefix_digits(key: &str) -> Vec<usize> {
+    (0..STEP_DEPTH)
+        .map(|i| key.as_bytes().get(i).and_then(|&c| digit_value(c)).unwrap_or(0))
+        .collect()
+}
+
+/// 桁の並びをキーにする（末尾の 0 は落とす）
+fn encode(digits: &[usize]) -> String {
+    let key: String = digits.iter().map(|&d| DIGITS[d] as char).collect();
+    key.trim_end_matches('0').to_string()
+}
+
+/// a と b の間のキー（b が None なら上限なし）。a < b であること
+fn midpoint(a: &[u8], b: Option<&[u8]>) -> String {
+    if let Some(b) = b {
+        // 共通する先頭部分はそのまま使う（a は 0 で埋めて比べる）
+        let n = (0..b.len())
+            .take_while(|&i| a.get(i).copied().unwrap_or(b'0') == b[i])
+            .count();
+        if n > 0 {
+            let rest = a.get(n..).unwrap_or(&[]);
+            return format!("{}{}", String::from_utf8_lossy(&b[..n]), midpoint(rest, Some(&b[n..])));
+        }
+    }
+
+    let da = a.first().and_then(|&c| digit_value(c)).unwrap_or(0);
+    let db = b.and_then(|b| digit_value(b[0])).unwrap_or(BASE);
+
+    if db - da > 1 {
+        (DIGITS[(da + db) / 2] as char).to_string()
+    } else if let Some(b) = b.filter(|b| b.len() > 1) {
+        // b の先頭 1 桁だけのキーは a より大きく b より小さい
+        (b[0] as char).to_string()
+    } else {
+        format!("{}{}", DIGITS[da] as char, midpoint(a.get(1..).unwrap_or(&[]), None))
+    }
+}
+
+/// a より後ろのキー（先頭 STEP_DEPTH 桁を 1 つ進める）
+fn after(a: &str) -> String {
+    let mut digits = prefix_digits(a);
+    for i in (0..STEP_DEPTH).rev() {
+        if digits[i] + 1 < BASE {
+            digits[i] += 1;
+            return encode(&digits);
+        }
+        digits[i] = 0;
+    }
+    midpoint(a.as_bytes(), None)
+}
+
+/// b より前のキー（先頭 STEP_DEPTH 桁を 1 つ戻す）
+fn before(b: &str) -> String {
+    let mut digits = prefix_digits(b);
+    for i in (0..STEP_DEPTH).rev() {
+        if digits[i] > 0 {
+            digits[i] -= 1;
+            let key = encode(&digits);
+            if !key.is_empty() {
+                return key;
+            }
+            break;
+        }
+        digits[i] = BASE - 1;
+    }
+    midpoint(&[], Some(b.as_bytes()))
+}
+
+/// a と b の間に入るキーを作る（None はそれぞれ先頭・末尾を表す）
+pub fn between(a: Option<&str>, b: Option<&str>) -> Result<String> {
+    for key in [a, b].into_iter().flatten() {
+        if !is_valid(key) {
+            return Err(JotDeckError::InvalidOperation(format!("Invalid rank: {:?}", key)));
+        }
+    }
+
+    match (a, b) {
+        (None, None) => Ok("V".to_string()),
+        (Some(a), None) => Ok(after(a)),
+        (None, Some(b)) => Ok(before(b)),
+        (Some(a), Some(b)) if a < b => Ok(midpoint(a.as_bytes(), Some(b.as_bytes()))),
+        (Some(a), Some(b)) => Err(JotDeckError::InvalidOperation(format!(
+            "Rank {:?} is not before {:?}",
+            a, b
+        ))),
+    }
+}
+
+/// 親の中で削除されていない行の `index` 番目（0 始まり）に入る rank を作る
+///
+/// 範囲外の index は末尾に丸める。`exclude_id` の行（移動する行自身）は数えない。
+/// 直後のキーは削除済みの行も含めて探すので、ゴミ箱の行と rank が重なることはない。
+pub(crate) fn for_index(
+    conn: &Connection,
+    table: &str,
+    parent_column: &str,
+    parent_id: &str,
+    index: i32,
+    exclude_id: &str,
+) -> Result<String> {
+    let prev: Option<String> = if index <= 0 {
+        None
+    } else {
+        let prev = conn
+            .query_row(
+                &format!(
+                    "SELECT rank FROM {table} WHERE {parent_column} = ?1 AND deleted_at IS NULL AND id != ?2
+                     ORDER BY rank LIMIT 1 OFFSET ?3"
+                ),
+                params![parent_id, exclude_id, index - 1],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if prev.is_none() {
+            return append(conn, table, parent_column, parent_id, exclude_id);
+        }
+        prev
+    };
+
+    let next: Option<String> = conn.query_row(
+        &format!("SELECT MIN(rank) FROM {table} WHERE {parent_column} = ?1 AND id != ?2 AND rank > ?3"),
+        params![parent_id, exclude_id, prev.as_deref().unwrap_or("")],
+        |row| row.get(0),
+    )?;
+
+    let key = between(prev.as_deref(), next.as_deref())?;
+    if key.len() > MAX_RANK_LEN {
+        rebalance(conn, table, parent_column, parent_id)?;
+        return for_index(conn, table, parent_column, parent_id, index, exclude_id);
+    }
+    Ok(key)
+}
+
+/// 親の末尾（削除済みの行も含めた最後の行の後ろ）に入る rank を作る
+pub(crate) fn append(
+    conn: &Connection,
+    table: &str,
+    parent_column: &str,
+    parent_id: &str,
+    exclude_id: &str,
+) -> Result<String> {
+    let last: Option<String> = conn.query_row(
+        &format!("SELECT MAX(rank) FROM {table} WHERE {parent_column} = ?1 AND id != ?2"),
+        params![parent_id, exclude_id],
+        |row| row.get(0),
+    )?;
+    between(last.as_deref(), None)
+}
+
+/// 親の中の行（削除済みも含む）の rank を今の並び順のまま短いキーに振り直す
+///
+/// rank が同じ行は ID 順に並べる。updated_at は変えない。
+pub(crate) fn rebalance(conn: &Connection, table: &str, parent_column: &str, parent_id: &str) -> Result<()> {
+    let ids: Vec<String> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id FROM {table} WHERE {parent_column} = ?1 ORDER BY rank, id"
+        ))?;
+        let ids = stmt
+            .query_map(params![parent_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        ids
+    };
+
+    let mut key: Option<String> = None;
+    for id in &ids {
+        let next = between(key.as_deref(), None)?;
+        conn.execute(
+            &format!("UPDATE {table} SET rank = ?1 WHERE id = ?2 AND rank != ?1"),
+            params![&next, id],
+        )?;
+        key = Some(next);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_between_orders_keys() {
+        let first = between(None, None).unwrap();
+        assert_eq!(first, "V");
+
+        let next = between(Some(&first), None).unwrap();
+        assert_eq!(next, "V001");
+        let prev = between(None, Some(&first)).unwrap();
+        assert_eq!(prev, "Uzzz");
+
+        let mid = between(Some(&first), Some(&next)).unwrap();
+        assert!(first < mid && mid < next);
+        assert!(is_valid(&mid));
+    }
+
+    #[test]
+    fn test_between_repeated_inserts_stay_ordered() {
+        // 同じ場所への挿入を繰り返しても順序が崩れない
+        let mut low = between(None, None).unwrap();
+        let high = between(Some(&low), None).unwrap();
+        for _ in 0..200 {
+            let mid = between(Some(&low), Some(&high)).unwrap();
+            assert!(low < mid && mid < high, "{} < {} < {}", low, mid, high);
+            assert!(is_valid(&mid));
+            low = mid;
+        }
+
+        let mut high = between(None, None).unwrap();
+        for _ in 0..200 {
+            let key = between(None, Some(&high)).unwrap();
+            assert!(key < high && is_valid(&key));
+            high = key;
+        }
+    }
+
+    #[test]
+    fn test_between_appends_without_growing() {
+        let mut key = between(None, None).unwrap();
+        for _ in 0..10_000 {
+            let next = between(Some(&key), None).unwrap();
+            assert!(key < next);
+            key = next;
+        }
+        assert!(key.len() <= STEP_DEPTH);
+    }
+
+    #[test]
+    fn test_between_rejects_invalid() {
+        assert!(between(Some("V0"), None).is_err());
+        assert!(between(Some(""), None).is_err());
+        assert!(between(Some("V1"), Some("V")).is_err());
+        assert!(between(Some("V"), Some("V")).is_err());
+    }
+}
@@ -1,10 +1,14 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
+use crate::db;
 use crate::error::{JotDeckError, Result};
-use crate::models::{Card, NewCard};
-use crate::repository::tag;
+use crate::journal;
+use crate::models::{Card, CardRevision, DeletedEntityType, NewCard, SortOrder};
+use crate::rank;
+use crate::repository::{column, deck, delete_stack, tag};
 
 /// RFC3339 文字列を DateTime<Utc> にパースする
 fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
@@ -26,95 +30,125 @@ fn parse_datetime_opt(s: &str) -> Option<DateTime<Utc>> {
         .ok()
 }
 
-/// 次の position を取得する
-fn get_next_position(conn: &Connection, column_id: &str) -> Result<i32> {
-    let max_pos: Option<i32> = conn.query_row(
-        "SELECT MAX(position) FROM cards WHERE column_id = ?1 AND deleted_at IS NULL",
-        params![column_id],
-        |row| row.get(0),
-    )?;
+/// Card を読み出す列（`cards c` に対して使う）
+///
+/// position は保存せず、同じ Column の削除されていない Card のうち rank が前にある枚数として求める。
+/// 削除済みの Card では復元したときに入る位置になる。
+pub(crate) const CARD_COLUMNS: &str = "c.id, c.column_id, c.content, c.score,
+    (SELECT COUNT(*) FROM cards s WHERE s.column_id = c.column_id AND s.deleted_at IS NULL AND s.rank < c.rank),
+    c.created_at, c.updated_at, c.deleted_at, c.deleted_with_column, c.deleted_with_deck, c.position_updated_at";
+
+/// Column 内の index 番目（削除されていない Card の中で数える）に入る rank を作る
+fn rank_at(conn: &Connection, column_id: &str, index: i32, exclude_id: &str) -> Result<String> {
+    rank::for_index(conn, "cards", "column_id", column_id, index, exclude_id)
+}
 
-    Ok(max_pos.unwrap_or(-1) + 1)
+/// Column の末尾に入る rank を作る
+fn rank_last(conn: &Connection, column_id: &str, exclude_id: &str) -> Result<String> {
+    rank::append(conn, "cards", "column_id", column_id, exclude_id)
+}
+
+/// Card を追加できる Column か確認する（存在し、削除されていないこと）
+fn check_column_writable(conn: &Connection, column_id: &str) -> Result<()> {
+    let column = column::get_by_id(conn, column_id)?;
+    if column.deleted_at.is_some() {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot add card to deleted column".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 /// Card を作成する
 pub fn create(conn: &Connection, new_card: NewCard) -> Result<Card> {
-    let id = Ulid::new().to_string();
-    let now = Utc::now();
-    let position = get_next_position(conn, &new_card.column_id)?;
-
-    conn.execute(
-        "INSERT INTO cards (id, column_id, content, score, position, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
-        params![
-            &id,
-            &new_card.column_id,
-            &new_card.content,
-            position,
-            now.to_rfc3339(),
-            now.to_rfc3339(),
-        ],
-    )?;
+    journal::record(conn, "card.create", || {
+        check_column_writable(conn, &new_card.column_id)?;
+
+        let id = Ulid::new().to_string();
+        let now = Utc::now();
+        let position = live_count(conn, &new_card.column_id)?;
+        let key = rank_last(conn, &new_card.column_id, "")?;
+
+        conn.execute(
+            "INSERT INTO cards (id, column_id, content, score, rank, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
+            params![
+                &id,
+                &new_card.column_id,
+                &new_card.content,
+                key,
+                now.to_rfc3339(),
+                now.to_rfc3339(),
+            ],
+        )?;
 
-    tag::sync_card_tags(conn, &id, &new_card.content)?;
+        tag::sync_card_tags(conn, &id, &new_card.content)?;
 
-    Ok(Card {
-        id,
-        column_id: new_card.column_id,
-        content: new_card.content,
-        score: 0,
-        position,
-        created_at: now,
-        updated_at: now,
-        deleted_at: None,
-        deleted_with_column: false,
+        Ok(Card {
+            id,
+            column_id: new_card.column_id,
+            content: new_card.content,
+            score: 0,
+            position,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_column: false,
+            deleted_with_deck: false,
+            position_updated_at: None,
+        })
     })
 }
 
-/// 特定の位置に Card を作成する
+/// 特定の位置に Card を作成する（position は 0〜末尾の次に丸める）
 pub fn create_at_position(conn: &Connection, new_card: NewCard, position: i32) -> Result<Card> {
-    let id = Ulid::new().to_string();
-    let now = Utc::now();
+    journal::record(conn, "card.create_at_position", || {
+        check_column_writable(conn, &new_card.column_id)?;
 
-    let tx = conn.unchecked_transaction()?;
+        let id = Ulid::new().to_string();
+        let now = Utc::now();
 
-    // 挿入位置以降の Card の position を +1 する
-    tx.execute(
-        "UPDATE cards SET position = position + 1, updated_at = ?1 WHERE column_id = ?2 AND position >= ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), &new_card.column_id, position],
-    )?;
+        let tx = db::savepoint(conn)?;
 
-    tx.execute(
-        "INSERT INTO cards (id, column_id, content, score, position, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
-        params![
-            &id,
-            &new_card.column_id,
-            &new_card.content,
-            position,
-            now.to_rfc3339(),
-            now.to_rfc3339(),
-        ],
-    )?;
+        // 範囲外の位置は先頭・末尾に丸める
+        let position = position.clamp(0, live_count(&tx, &new_card.column_id)?);
+        let key = rank_at(&tx, &new_card.column_id, position, "")?;
+
+        tx.execute(
+            "INSERT INTO cards (id, column_id, content, score, rank, created_at, updated_at) VALUES (?1, ?2, ?3, 0, ?4, ?5, ?6)",
+            params![
+                &id,
+                &new_card.column_id,
+                &new_card.content,
+                key,
+                now.to_rfc3339(),
+                now.to_rfc3339(),
+            ],
+        )?;
 
-    tag::sync_card_tags(&tx, &id, &new_card.content)?;
+        tag::sync_card_tags(&tx, &id, &new_card.content)?;
 
-    tx.commit()?;
+        tx.commit()?;
 
-    Ok(Card {
-        id,
-        column_id: new_card.column_id,
-        content: new_card.content,
-        score: 0,
-        position,
-        created_at: now,
-        updated_at: now,
-        deleted_at: None,
-        deleted_with_column: false,
+        Ok(Card {
+            id,
+            column_id: new_card.column_id,
+            content: new_card.content,
+            score: 0,
+            position,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_column: false,
+            deleted_with_deck: false,
+            position_updated_at: None,
+        })
     })
 }
 
-fn row_to_card(row: &rusqlite::Row) -> rusqlite::Result<Card> {
+pub(crate) fn row_to_card(row: &rusqlite::Row) -> rusqlite::Result<Card> {
     let deleted_at_str: Option<String> = row.get(7)?;
     let deleted_with_column: i32 = row.get(8)?;
+    let deleted_with_deck: i32 = row.get(9)?;
 
     Ok(Card {
         id: row.get(0)?,
@@ -126,13 +160,17 @@ fn row_to_card(row: &rusqlite::Row) -> rusqlite::Result<Card> {
         updated_at: parse_datetime(&row.get::<_, String>(6)?, 6)?,
         deleted_at: deleted_at_str.and_then(|s| parse_datetime_opt(&s)),
         deleted_with_column: deleted_with_column != 0,
+        deleted_with_deck: deleted_with_deck != 0,
+        position_updated_at: row
+            .get::<_, Option<String>>(10)?
+            .and_then(|s| parse_datetime_opt(&s)),
     })
 }
 
 /// ID で Card を取得する
 pub fn get_by_id(conn: &Connection, id: &str) -> Result<Card> {
     conn.query_row(
-        "SELECT id, column_id, content, score, position, created_at, updated_at, deleted_at, deleted_with_column FROM cards WHERE id = ?1",
+        &format!("SELECT {} FROM cards c WHERE c.id = ?1", CARD_COLUMNS),
         params![id],
         row_to_card,
     )
@@ -144,11 +182,32 @@ pub fn get_by_id(conn: &Connection, id: &str) -> Result<Card> {
     })
 }
 
-/// Column 内の Card 一覧を取得する（削除されていないもののみ）
+/// ソート順に対応する ORDER BY 句
+/// 同順位は手動の並び順（rank）、作成日時順では ID で決定的に並べる
+fn order_by_clause(sort_order: SortOrder) -> &'static str {
+    match sort_order {
+        SortOrder::CreatedDesc => "c.created_at DESC, c.id DESC",
+        SortOrder::CreatedAsc => "c.created_at ASC, c.id ASC",
+        SortOrder::ScoreDesc => "c.score DESC, c.rank ASC",
+        SortOrder::ScoreAsc => "c.score ASC, c.rank ASC",
+        SortOrder::Manual => "c.rank ASC",
+    }
+}
+
+/// Column 内の Card 一覧を手動の並び順で取得する（削除されていないもののみ）
 pub fn get_by_column_id(conn: &Connection, column_id: &str) -> Result<Vec<Card>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, column_id, content, score, position, created_at, updated_at, deleted_at, deleted_with_column FROM cards WHERE column_id = ?1 AND deleted_at IS NULL ORDER BY position ASC",
-    )?;
+    get_by_column_id_sorted(conn, column_id, SortOrder::Manual)
+}
+
+/// Column 内の Card 一覧を指定したソート順で取得する（削除されていないもののみ）
+pub fn get_by_column_id_sorted(conn: &Connection, column_id: &str, sort_order: SortOrder) -> Result<Vec<Card>> {
+    // 1 枚ずつ数えると枚数の 2 乗になるので、position は窓関数で振る
+    let mut stmt = conn.prepare(&format!(
+        "SELECT c.id, c.column_id, c.content, c.score, ROW_NUMBER() OVER (ORDER BY c.rank) - 1,
+                c.created_at, c.updated_at, c.deleted_at, c.deleted_with_column, c.deleted_with_deck, c.position_updated_at
+         FROM cards c WHERE c.column_id = ?1 AND c.deleted_at IS NULL ORDER BY {}",
+        order_by_clause(sort_order)
+    ))?;
 
     let cards = stmt
         .query_map(params![column_id], row_to_card)?
@@ -157,224 +216,703 @@ pub fn get_by_column_id(conn: &Connection, column_id: &str) -> Result<Vec<Card>>
     Ok(cards)
 }
 
+/// Column 内の Card 一覧を所属する Deck のソート順で取得する（削除されていないもののみ）
+pub fn get_by_column_id_in_deck_order(conn: &Connection, column_id: &str) -> Result<Vec<Card>> {
+    let sort_order: String = conn
+        .query_row(
+            "SELECT d.sort_order FROM columns col JOIN decks d ON col.deck_id = d.id WHERE col.id = ?1",
+            params![column_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                JotDeckError::NotFound(format!("Column not found: {}", column_id))
+            }
+            _ => JotDeckError::Database(e),
+        })?;
+
+    get_by_column_id_sorted(conn, column_id, SortOrder::from_db_value(&sort_order))
+}
+
+/// 連続した編集を 1 つの履歴にまとめる既定の間隔（秒）
+pub const DEFAULT_REVISION_WINDOW_SECS: i64 = 60;
+
 /// Card の内容を更新する
 pub fn update_content(conn: &Connection, id: &str, content: &str) -> Result<Card> {
-    let card = get_by_id(conn, id)?;
+    update_content_with_window(conn, id, content, Duration::seconds(DEFAULT_REVISION_WINDOW_SECS))
+}
 
-    if card.deleted_at.is_some() {
-        return Err(JotDeckError::InvalidOperation(
-            "Cannot update deleted card".to_string(),
-        ));
+/// Card の内容を更新する（履歴をまとめる間隔を指定）
+///
+/// 上書き前の本文を履歴に残す。直近の履歴が window 以内に作られていれば連続した編集とみなし、
+/// 新しい履歴は作らない（編集を始める前の本文が残る）。
+pub fn update_content_with_window(conn: &Connection, id: &str, content: &str, window: Duration) -> Result<Card> {
+    journal::record(conn, "card.update_content", || {
+        let card = get_by_id(conn, id)?;
+
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot update deleted card".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        save_revision(&tx, id, &card.content, content, now, window)?;
+
+        tx.execute(
+            "UPDATE cards SET content = ?1, updated_at = ?2 WHERE id = ?3",
+            params![content, now.to_rfc3339(), id],
+        )?;
+
+        tag::sync_card_tags(&tx, id, content)?;
+
+        tx.commit()?;
+
+        Ok(Card {
+            content: content.to_string(),
+            updated_at: now,
+            ..card
+        })
+    })
+}
+
+/// 本文を `after` に書き換える前に、上書きされる本文 `before` を履歴に残す
+///
+/// 直近の履歴が window 以内に作られていれば連続した編集とみなし、新しい履歴は作らない。
+/// 本文を直接書き換える処理（タグ名の変更やバックアップのマージ）は window を 0 にして必ず残す。
+pub(crate) fn save_revision(
+    conn: &Connection,
+    id: &str,
+    before: &str,
+    after: &str,
+    now: DateTime<Utc>,
+    window: Duration,
+) -> Result<()> {
+    if before == after {
+        return Ok(());
+    }
+
+    let recent: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM card_revisions WHERE card_id = ?1 AND created_at > ?2)",
+        params![id, (now - window).to_rfc3339()],
+        |row| row.get(0),
+    )?;
+    if !recent {
+        conn.execute(
+            "INSERT INTO card_revisions (card_id, content, created_at) VALUES (?1, ?2, ?3)",
+            params![id, before, now.to_rfc3339()],
+        )?;
     }
 
-    let now = Utc::now();
+    Ok(())
+}
 
-    conn.execute(
-        "UPDATE cards SET content = ?1, updated_at = ?2 WHERE id = ?3",
-        params![content, now.to_rfc3339(), id],
+pub(crate) fn row_to_revision(row: &rusqlite::Row) -> rusqlite::Result<CardRevision> {
+    Ok(CardRevision {
+        id: row.get(0)?,
+        card_id: row.get(1)?,
+        content: row.get(2)?,
+        created_at: parse_datetime(&row.get::<_, String>(3)?, 3)?,
+    })
+}
+
+/// Card の編集履歴を取得する（新しい順）
+pub fn history(conn: &Connection, id: &str) -> Result<Vec<CardRevision>> {
+    let _ = get_by_id(conn, id)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, card_id, content, created_at FROM card_revisions WHERE card_id = ?1 ORDER BY id DESC",
     )?;
 
-    tag::sync_card_tags(conn, id, content)?;
+    let revisions = stmt
+        .query_map(params![id], row_to_revision)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
 
-    Ok(Card {
-        content: content.to_string(),
-        updated_at: now,
-        ..card
+    Ok(revisions)
+}
+
+/// Card の本文を履歴の内容に戻す
+///
+/// 戻す直前の本文も履歴に残すので、元に戻した操作もさらに戻せる。
+pub fn revert_to(conn: &Connection, id: &str, revision_id: i64) -> Result<Card> {
+    journal::record(conn, "card.revert_to", || {
+        let revision = conn
+            .query_row(
+                "SELECT id, card_id, content, created_at FROM card_revisions WHERE id = ?1 AND card_id = ?2",
+                params![revision_id, id],
+                row_to_revision,
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => {
+                    JotDeckError::NotFound(format!("Revision not found: {}", revision_id))
+                }
+                _ => JotDeckError::Database(e),
+            })?;
+
+        update_content_with_window(conn, id, &revision.content, Duration::zero())
     })
 }
 
 /// Card のスコアを更新する
 pub fn update_score(conn: &Connection, id: &str, delta: i32) -> Result<Card> {
-    let card = get_by_id(conn, id)?;
+    journal::record(conn, "card.update_score", || {
+        let card = get_by_id(conn, id)?;
 
-    if card.deleted_at.is_some() {
-        return Err(JotDeckError::InvalidOperation(
-            "Cannot update deleted card".to_string(),
-        ));
-    }
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot update deleted card".to_string(),
+            ));
+        }
 
-    let now = Utc::now();
-    let new_score = card.score + delta;
+        let now = Utc::now();
+        let new_score = card.score + delta;
 
-    conn.execute(
-        "UPDATE cards SET score = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_score, now.to_rfc3339(), id],
-    )?;
+        conn.execute(
+            "UPDATE cards SET score = ?1, updated_at = ?2 WHERE id = ?3",
+            params![new_score, now.to_rfc3339(), id],
+        )?;
 
-    Ok(Card {
-        score: new_score,
-        updated_at: now,
-        ..card
+        Ok(Card {
+            score: new_score,
+            updated_at: now,
+            ..card
+        })
     })
 }
 
-/// Card を別の Column に移動する
+/// Card を別の Column に移動する（末尾に追加）
 pub fn move_to_column(conn: &Connection, id: &str, new_column_id: &str) -> Result<Card> {
-    let card = get_by_id(conn, id)?;
+    journal::record(conn, "card.move_to_column", || {
+        move_to(conn, id, new_column_id, i32::MAX)
+    })
+}
 
-    if card.deleted_at.is_some() {
+/// Card を Column 内で移動する（並び替え）
+pub fn move_to_position(conn: &Connection, id: &str, new_position: i32) -> Result<Card> {
+    journal::record(conn, "card.move_to_position", || {
+        let card = get_by_id(conn, id)?;
+        move_to(conn, id, &card.column_id, new_position)
+    })
+}
+
+/// Card の複製を新しい ID で挿入する（本文・スコアをコピーし、タグを同期する）
+pub(crate) fn insert_copy(
+    conn: &Connection,
+    source: &Card,
+    column_id: &str,
+    key: &str,
+    now: DateTime<Utc>,
+) -> Result<Card> {
+    let id = Ulid::new().to_string();
+
+    conn.execute(
+        "INSERT INTO cards (id, column_id, content, score, rank, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            &id,
+            column_id,
+            &source.content,
+            source.score,
+            key,
+            now.to_rfc3339(),
+            now.to_rfc3339(),
+        ],
+    )?;
+
+    tag::sync_card_tags(conn, &id, &source.content)?;
+
+    get_by_id(conn, &id)
+}
+
+/// Card を複製する
+///
+/// 同じ Column なら元の Card の直後に、別の Column（他の Deck でもよい）なら末尾に作る。
+pub fn duplicate(conn: &Connection, id: &str, column_id: &str) -> Result<Card> {
+    journal::record(conn, "card.duplicate", || {
+        let card = get_by_id(conn, id)?;
+
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot duplicate deleted card".to_string(),
+            ));
+        }
+
+        let target = column::get_by_id(conn, column_id)?;
+        if target.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot duplicate card into deleted column".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        let key = if card.column_id == column_id {
+            rank_at(&tx, column_id, card.position + 1, "")?
+        } else {
+            rank_last(&tx, column_id, "")?
+        };
+
+        let copy = insert_copy(&tx, &card, column_id, &key, now)?;
+
+        tx.commit()?;
+
+        Ok(copy)
+    })
+}
+
+/// Card の移動先の Column が同じ Deck の削除されていない Column か確認する
+fn check_move_target(conn: &Connection, card: &Card, column_id: &str) -> Result<()> {
+    let target = column::get_by_id(conn, column_id)?;
+    if target.deleted_at.is_some() {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot move card to deleted column".to_string(),
+        ));
+    }
+    if target.deck_id != column::get_by_id(conn, &card.column_id)?.deck_id {
         return Err(JotDeckError::InvalidOperation(
-            "Cannot move deleted card".to_string(),
+            "Cannot move card to a column in another deck".to_string(),
         ));
     }
+    Ok(())
+}
 
-    let now = Utc::now();
-    let old_column_id = &card.column_id;
+/// Card を指定した Column の指定した位置に移動する
+///
+/// 移動先の Column は同じ Deck の削除されていない Column であること。
+/// position は移動先の範囲（末尾の次まで）に丸める。書き換えるのは移動する Card の行だけ。
+pub fn move_to(conn: &Connection, id: &str, column_id: &str, position: i32) -> Result<Card> {
+    journal::record(conn, "card.move_to", || {
+        let card = get_by_id(conn, id)?;
+
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot move deleted card".to_string(),
+            ));
+        }
 
-    let tx = conn.unchecked_transaction()?;
+        check_move_target(conn, &card, column_id)?;
 
-    // 元の Column 内の position を詰める
-    tx.execute(
-        "UPDATE cards SET position = position - 1, updated_at = ?1 WHERE column_id = ?2 AND position > ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), old_column_id, card.position],
-    )?;
+        let same_column = card.column_id == column_id;
+        let now = Utc::now();
 
-    // 新しい Column での position を取得（トランザクション内で実行）
-    let new_position: i32 = {
-        let max_pos: Option<i32> = tx.query_row(
-            "SELECT MAX(position) FROM cards WHERE column_id = ?1 AND deleted_at IS NULL",
-            params![new_column_id],
+        let tx = db::savepoint(conn)?;
+
+        // 移動する Card を除いた枚数が挿入できる位置の上限
+        let others: i32 = tx.query_row(
+            "SELECT COUNT(*) FROM cards WHERE column_id = ?1 AND deleted_at IS NULL AND id != ?2",
+            params![column_id, id],
             |row| row.get(0),
         )?;
-        max_pos.unwrap_or(-1) + 1
-    };
+        let new_position = position.clamp(0, others);
 
-    tx.execute(
-        "UPDATE cards SET column_id = ?1, position = ?2, updated_at = ?3 WHERE id = ?4",
-        params![new_column_id, new_position, now.to_rfc3339(), id],
-    )?;
+        // 同じ位置への移動では何も書き換えない
+        if same_column && new_position == card.position {
+            return Ok(card);
+        }
 
-    tx.commit()?;
+        // 並び替えは本文の編集ではないので updated_at は変えない
+        let key = rank_at(&tx, column_id, new_position, id)?;
+        tx.execute(
+            "UPDATE cards SET column_id = ?1, rank = ?2, position_updated_at = ?3 WHERE id = ?4",
+            params![column_id, key, now.to_rfc3339(), id],
+        )?;
 
-    Ok(Card {
-        column_id: new_column_id.to_string(),
-        position: new_position,
-        updated_at: now,
-        ..card
+        tx.commit()?;
+
+        Ok(Card {
+            column_id: column_id.to_string(),
+            position: new_position,
+            position_updated_at: Some(now),
+            ..card
+        })
     })
 }
 
-/// Card を Column 内で移動する（並び替え）
-pub fn move_to_position(conn: &Connection, id: &str, new_position: i32) -> Result<Card> {
-    let card = get_by_id(conn, id)?;
+/// Card を論理削除する
+pub fn soft_delete(conn: &Connection, id: &str) -> Result<()> {
+    journal::record(conn, "card.soft_delete", || {
+        let card = get_by_id(conn, id)?;
 
-    if card.deleted_at.is_some() {
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Card is already deleted".to_string(),
+            ));
+        }
+
+        let deck_id = column::get_by_id(conn, &card.column_id)?.deck_id;
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
+
+        // rank はそのまま残すので、復元すると元の並びに戻る
+        tx.execute(
+            "UPDATE cards SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        delete_stack::push(&tx, &deck_id, DeletedEntityType::Card, id, now)?;
+
+        tx.commit()?;
+
+        Ok(())
+    })
+}
+
+/// 単体で復元できる Card か確認する（削除済みであることは呼び出し側で確認する）
+fn check_restorable(conn: &Connection, card: &Card) -> Result<()> {
+    // 連動削除された Card は Column の復元時に復元されるので、単体では復元できない
+    if card.deleted_with_column {
         return Err(JotDeckError::InvalidOperation(
-            "Cannot move deleted card".to_string(),
+            "Cannot restore card that was deleted with column. Restore the column instead.".to_string(),
         ));
     }
 
-    let old_position = card.position;
-    let now = Utc::now();
+    // Deck ごと削除された Card は Deck の復元時に復元される
+    if card.deleted_with_deck {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot restore card that was deleted with deck. Restore the deck instead.".to_string(),
+        ));
+    }
 
-    let tx = conn.unchecked_transaction()?;
+    let deck = deck::get_by_id(conn, &column::get_by_id(conn, &card.column_id)?.deck_id)?;
+    if deck.deleted_at.is_some() {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot restore card in deleted deck. Restore the deck first.".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Card を復元する（削除前の並びの位置に戻る）
+pub fn restore(conn: &Connection, id: &str) -> Result<Card> {
+    journal::record(conn, "card.restore", || {
+        let card = get_by_id(conn, id)?;
+
+        if card.deleted_at.is_none() {
+            return Err(JotDeckError::InvalidOperation(
+                "Card is not deleted".to_string(),
+            ));
+        }
+
+        check_restorable(conn, &card)?;
+
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
 
-    if new_position > old_position {
-        // 下に移動: old_position < x <= new_position の Card を -1
-        tx.execute(
-            "UPDATE cards SET position = position - 1, updated_at = ?1 WHERE column_id = ?2 AND position > ?3 AND position <= ?4 AND deleted_at IS NULL",
-            params![now.to_rfc3339(), &card.column_id, old_position, new_position],
-        )?;
-    } else if new_position < old_position {
-        // 上に移動: new_position <= x < old_position の Card を +1
         tx.execute(
-            "UPDATE cards SET position = position + 1, updated_at = ?1 WHERE column_id = ?2 AND position >= ?3 AND position < ?4 AND deleted_at IS NULL",
-            params![now.to_rfc3339(), &card.column_id, new_position, old_position],
+            "UPDATE cards SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), id],
         )?;
+        tag::resync_live_cards(&tx, "c.id = ?1", params![id])?;
+
+        delete_stack::remove(&tx, DeletedEntityType::Card, id)?;
+
+        tx.commit()?;
+
+        Ok(Card {
+            deleted_at: None,
+            updated_at: now,
+            ..card
+        })
+    })
+}
+
+/// 複数の Card を取得する（同じ ID が重複していればエラー）
+fn get_many(conn: &Connection, ids: &[&str]) -> Result<Vec<Card>> {
+    let mut cards: Vec<Card> = Vec::with_capacity(ids.len());
+    for id in ids {
+        if cards.iter().any(|c| c.id == *id) {
+            return Err(JotDeckError::InvalidOperation(format!(
+                "Card is listed more than once: {}",
+                id
+            )));
+        }
+        cards.push(get_by_id(conn, id)?);
     }
+    Ok(cards)
+}
 
-    tx.execute(
-        "UPDATE cards SET position = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_position, now.to_rfc3339(), id],
+/// Column 内の削除されていない Card の枚数
+fn live_count(conn: &Connection, column_id: &str) -> Result<i32> {
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM cards WHERE column_id = ?1 AND deleted_at IS NULL",
+        params![column_id],
+        |row| row.get(0),
     )?;
+    Ok(count)
+}
 
-    tx.commit()?;
+/// 複数の Card を同じ Deck の別の Column の末尾に移動する（`ids` の順に並ぶ）
+pub fn move_many_to_column(conn: &Connection, ids: &[&str], new_column_id: &str) -> Result<Vec<Card>> {
+    journal::record(conn, "card.move_many_to_column", || {
+        let cards = get_many(conn, ids)?;
+        if cards.iter().any(|c| c.deleted_at.is_some()) {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot move deleted card".to_string(),
+            ));
+        }
+        for c in &cards {
+            check_move_target(conn, c, new_column_id)?;
+        }
 
-    Ok(Card {
-        position: new_position,
-        updated_at: now,
-        ..card
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        for c in &cards {
+            let key = rank_last(&tx, new_column_id, &c.id)?;
+            tx.execute(
+                "UPDATE cards SET column_id = ?1, rank = ?2, position_updated_at = ?3 WHERE id = ?4",
+                params![new_column_id, key, now.to_rfc3339(), &c.id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        ids.iter().map(|id| get_by_id(conn, id)).collect()
     })
 }
 
-/// Card を論理削除する
-pub fn soft_delete(conn: &Connection, id: &str) -> Result<()> {
-    let card = get_by_id(conn, id)?;
+/// 複数の Card を論理削除する（削除スタックには `ids` の順に積む）
+pub fn soft_delete_many(conn: &Connection, ids: &[&str]) -> Result<Vec<Card>> {
+    journal::record(conn, "card.soft_delete_many", || {
+        let cards = get_many(conn, ids)?;
+        if cards.iter().any(|c| c.deleted_at.is_some()) {
+            return Err(JotDeckError::InvalidOperation(
+                "Card is already deleted".to_string(),
+            ));
+        }
 
-    if card.deleted_at.is_some() {
+        let mut deck_ids = Vec::with_capacity(cards.len());
+        for c in &cards {
+            deck_ids.push(column::get_by_id(conn, &c.column_id)?.deck_id);
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        for (c, deck_id) in cards.iter().zip(&deck_ids) {
+            tx.execute(
+                "UPDATE cards SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+                params![now.to_rfc3339(), &c.id],
+            )?;
+            delete_stack::push(&tx, deck_id, DeletedEntityType::Card, &c.id, now)?;
+        }
+
+        tx.commit()?;
+
+        ids.iter().map(|id| get_by_id(conn, id)).collect()
+    })
+}
+
+/// 複数の Card のスコアをまとめて更新する
+pub fn update_score_many(conn: &Connection, ids: &[&str], delta: i32) -> Result<Vec<Card>> {
+    journal::record(conn, "card.update_score_many", || {
+        let cards = get_many(conn, ids)?;
+        if cards.iter().any(|c| c.deleted_at.is_some()) {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot update deleted card".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        for c in &cards {
+            tx.execute(
+                "UPDATE cards SET score = score + ?1, updated_at = ?2 WHERE id = ?3",
+                params![delta, now.to_rfc3339(), &c.id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        ids.iter().map(|id| get_by_id(conn, id)).collect()
+    })
+}
+
+/// 複数の Card を復元する（それぞれ削除前の並びの位置に戻る）
+pub fn restore_many(conn: &Connection, ids: &[&str]) -> Result<Vec<Card>> {
+    journal::record(conn, "card.restore_many", || {
+        let cards = get_many(conn, ids)?;
+        for c in &cards {
+            if c.deleted_at.is_none() {
+                return Err(JotDeckError::InvalidOperation(
+                    "Card is not deleted".to_string(),
+                ));
+            }
+            check_restorable(conn, c)?;
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        for c in &cards {
+            tx.execute(
+                "UPDATE cards SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2",
+                params![now.to_rfc3339(), &c.id],
+            )?;
+            tag::resync_live_cards(&tx, "c.id = ?1", params![&c.id])?;
+            delete_stack::remove(&tx, DeletedEntityType::Card, &c.id)?;
+        }
+
+        tx.commit()?;
+
+        ids.iter().map(|id| get_by_id(conn, id)).collect()
+    })
+}
+
+/// Card をまとめるときのスコアの決め方
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScoreMerge {
+    /// 合計（デフォルト）
+    #[default]
+    Sum,
+    /// 最大値
+    Max,
+}
+
+/// 複数の Card を 1 枚にまとめる
+///
+/// 本文を `ids` の順に `separator` で連結した Card を先頭の Card の位置に作り、元の Card は論理削除する。
+/// まとめて 1 つの操作として記録されるので、`journal::undo` 1 回で元に戻る。
+/// 元の Card は削除スタックに積まない（1 枚ずつ戻すとまとめた Card と本文が重複するため）。
+pub fn merge(conn: &Connection, ids: &[&str], separator: &str, score: ScoreMerge) -> Result<Card> {
+    if ids.len() < 2 {
         return Err(JotDeckError::InvalidOperation(
-            "Card is already deleted".to_string(),
+            "At least two cards are required to merge".to_string(),
         ));
     }
 
-    let now = Utc::now();
+    let mut sources = Vec::with_capacity(ids.len());
+    let mut deck_id: Option<String> = None;
+    for id in ids {
+        if sources.iter().any(|c: &Card| c.id == *id) {
+            return Err(JotDeckError::InvalidOperation(format!(
+                "Card is listed more than once: {}",
+                id
+            )));
+        }
 
-    let tx = conn.unchecked_transaction()?;
+        let card = get_by_id(conn, id)?;
+        if card.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot merge deleted card".to_string(),
+            ));
+        }
 
-    tx.execute(
-        "UPDATE cards SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
-        params![now.to_rfc3339(), id],
-    )?;
+        let card_deck_id = column::get_by_id(conn, &card.column_id)?.deck_id;
+        match &deck_id {
+            Some(d) if *d != card_deck_id => {
+                return Err(JotDeckError::InvalidOperation(
+                    "Cannot merge cards from different decks".to_string(),
+                ));
+            }
+            Some(_) => {}
+            None => deck_id = Some(card_deck_id),
+        }
 
-    // position を詰める
-    tx.execute(
-        "UPDATE cards SET position = position - 1, updated_at = ?1 WHERE column_id = ?2 AND position > ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), &card.column_id, card.position],
-    )?;
+        sources.push(card);
+    }
 
-    tx.commit()?;
+    let content = sources
+        .iter()
+        .map(|c| c.content.as_str())
+        .collect::<Vec<_>>()
+        .join(separator);
+    let total = match score {
+        ScoreMerge::Sum => sources.iter().map(|c| c.score).sum(),
+        ScoreMerge::Max => sources.iter().map(|c| c.score).max().unwrap_or(0),
+    };
 
-    Ok(())
+    journal::record(conn, "card.merge", || {
+        let tx = db::savepoint(conn)?;
+
+        let first = &sources[0];
+        let merged = create_at_position(
+            &tx,
+            NewCard {
+                column_id: first.column_id.clone(),
+                content,
+            },
+            first.position,
+        )?;
+        if total != 0 {
+            update_score(&tx, &merged.id, total)?;
+        }
+
+        let now = Utc::now();
+        for c in &sources {
+            tx.execute(
+                "UPDATE cards SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+                params![now.to_rfc3339(), &c.id],
+            )?;
+        }
+
+        tx.commit()?;
+
+        get_by_id(conn, &merged.id)
+    })
 }
 
-/// Card を復元する（元の位置に挿入）
-pub fn restore(conn: &Connection, id: &str) -> Result<Card> {
+/// Card を本文の `at` 文字目で 2 枚に分ける
+///
+/// 前半は元の Card に残し（スコアも元の Card のまま）、後半は直後の位置に新しい Card として作る。
+/// 分け目の前後の空白は取り除く。どちらかが空になる位置では分けられない。
+pub fn split(conn: &Connection, id: &str, at: usize) -> Result<(Card, Card)> {
     let card = get_by_id(conn, id)?;
 
-    if card.deleted_at.is_none() {
+    if card.deleted_at.is_some() {
         return Err(JotDeckError::InvalidOperation(
-            "Card is not deleted".to_string(),
+            "Cannot split deleted card".to_string(),
         ));
     }
 
-    // 連動削除された Card は Column の復元時に復元されるので、単体では復元できない
-    if card.deleted_with_column {
-        return Err(JotDeckError::InvalidOperation(
-            "Cannot restore card that was deleted with column. Restore the column instead.".to_string(),
-        ));
+    let offset = card
+        .content
+        .char_indices()
+        .nth(at)
+        .map(|(i, _)| i)
+        .unwrap_or(card.content.len());
+    let head = card.content[..offset].trim_end();
+    let tail = card.content[offset..].trim_start();
+
+    if head.is_empty() || tail.is_empty() {
+        return Err(JotDeckError::InvalidOperation(format!(
+            "Cannot split card at {}: both parts must have content",
+            at
+        )));
     }
 
-    let now = Utc::now();
-    let restore_position = card.position;
-
-    let tx = conn.unchecked_transaction()?;
+    journal::record(conn, "card.split", || {
+        let tx = db::savepoint(conn)?;
 
-    // 復元位置以降の Card の position を +1 する
-    tx.execute(
-        "UPDATE cards SET position = position + 1, updated_at = ?1 WHERE column_id = ?2 AND position >= ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), &card.column_id, restore_position],
-    )?;
-
-    tx.execute(
-        "UPDATE cards SET deleted_at = NULL, position = ?1, updated_at = ?2 WHERE id = ?3",
-        params![restore_position, now.to_rfc3339(), id],
-    )?;
+        let first = update_content(&tx, id, head)?;
+        let second = create_at_position(
+            &tx,
+            NewCard {
+                column_id: first.column_id.clone(),
+                content: tail.to_string(),
+            },
+            first.position + 1,
+        )?;
 
-    tx.commit()?;
+        tx.commit()?;
 
-    Ok(Card {
-        deleted_at: None,
-        position: restore_position,
-        updated_at: now,
-        ..card
+        Ok((first, second))
     })
 }
 
 /// 削除済みの Card 一覧を取得する（ゴミ箱表示用）
 pub fn get_deleted(conn: &Connection, column_id: &str) -> Result<Vec<Card>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, column_id, content, score, position, created_at, updated_at, deleted_at, deleted_with_column FROM cards WHERE column_id = ?1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM cards c WHERE c.column_id = ?1 AND c.deleted_at IS NOT NULL ORDER BY c.deleted_at DESC",
+        CARD_COLUMNS
+    ))?;
 
     let cards = stmt
         .query_map(params![column_id], row_to_card)?
@@ -385,13 +923,14 @@ pub fn get_deleted(conn: &Connection, column_id: &str) -> Result<Vec<Card>> {
 
 /// Deck 全体の削除済み Card 一覧を取得する
 pub fn get_deleted_by_deck(conn: &Connection, deck_id: &str) -> Result<Vec<Card>> {
-    let mut stmt = conn.prepare(
-        "SELECT c.id, c.column_id, c.content, c.score, c.position, c.created_at, c.updated_at, c.deleted_at, c.deleted_with_column
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}
          FROM cards c
          JOIN columns col ON c.column_id = col.id
          WHERE col.deck_id = ?1 AND c.deleted_at IS NOT NULL
          ORDER BY c.deleted_at DESC",
-    )?;
+        CARD_COLUMNS
+    ))?;
 
     let cards = stmt
         .query_map(params![deck_id], row_to_card)?
@@ -404,8 +943,7 @@ pub fn get_deleted_by_deck(conn: &Connection, deck_id: &str) -> Result<Vec<Card>
 mod tests {
     use super::*;
     use crate::db::create_in_memory;
-    use crate::models::{NewColumn, NewDeck, SortOrder};
-    use crate::repository::{column, deck};
+    use crate::models::{NewColumn, NewDeck};
 
     fn setup() -> (Connection, String, String) {
         let conn = create_in_memory().unwrap();
@@ -621,4 +1159,727 @@ mod tests {
         assert_eq!(cards2.len(), 1);
         assert_eq!(cards2[0].content, "Test");
     }
+
+    /// 作成日時とスコアを指定した Card を作る（A, B, C の順に作成）
+    fn setup_sortable(conn: &Connection, column_id: &str) {
+        for (content, created_at, score) in [
+            ("A", "2024-01-01T00:00:00+00:00", 2),
+            ("B", "2024-01-02T00:00:00+00:00", 5),
+            ("C", "2024-01-03T00:00:00+00:00", -1),
+        ] {
+            let c = create(
+                conn,
+                NewCard {
+                    column_id: column_id.to_string(),
+                    content: content.to_string(),
+                },
+            )
+            .unwrap();
+            conn.execute(
+                "UPDATE cards SET created_at = ?1, score = ?2 WHERE id = ?3",
+                params![created_at, score, &c.id],
+            )
+            .unwrap();
+        }
+    }
+
+    fn contents(cards: &[Card]) -> Vec<&str> {
+        cards.iter().map(|c| c.content.as_str()).collect()
+    }
+
+    #[test]
+    fn test_sort_created_desc() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::CreatedDesc).unwrap();
+        assert_eq!(contents(&cards), vec!["C", "B", "A"]);
+    }
+
+    #[test]
+    fn test_sort_created_asc() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::CreatedAsc).unwrap();
+        assert_eq!(contents(&cards), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_sort_score_desc() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::ScoreDesc).unwrap();
+        assert_eq!(contents(&cards), vec!["B", "A", "C"]);
+    }
+
+    #[test]
+    fn test_sort_score_asc() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::ScoreAsc).unwrap();
+        assert_eq!(contents(&cards), vec!["C", "A", "B"]);
+    }
+
+    #[test]
+    fn test_sort_score_ties_follow_manual_order() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+        conn.execute("UPDATE cards SET score = 0", []).unwrap();
+
+        // (A, B, C) -> (C, A, B)
+        let c = get_by_column_id(&conn, &column_id).unwrap().pop().unwrap();
+        move_to_position(&conn, &c.id, 0).unwrap();
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::ScoreDesc).unwrap();
+        assert_eq!(contents(&cards), vec!["C", "A", "B"]);
+    }
+
+    #[test]
+    fn test_sort_manual() {
+        let (conn, _, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        // 手動移動は他のソート順には影響せず、Manual でのみ反映される
+        let a = get_by_column_id(&conn, &column_id).unwrap().remove(0);
+        move_to_position(&conn, &a.id, 2).unwrap();
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::Manual).unwrap();
+        assert_eq!(contents(&cards), vec!["B", "C", "A"]);
+
+        let cards = get_by_column_id_sorted(&conn, &column_id, SortOrder::CreatedAsc).unwrap();
+        assert_eq!(contents(&cards), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn test_get_by_column_id_in_deck_order() {
+        let (conn, deck_id, column_id) = setup();
+        setup_sortable(&conn, &column_id);
+
+        // 既定は CreatedDesc
+        let cards = get_by_column_id_in_deck_order(&conn, &column_id).unwrap();
+        assert_eq!(contents(&cards), vec!["C", "B", "A"]);
+
+        deck::update(&conn, &deck_id, None, Some(SortOrder::ScoreDesc)).unwrap();
+        let cards = get_by_column_id_in_deck_order(&conn, &column_id).unwrap();
+        assert_eq!(contents(&cards), vec!["B", "A", "C"]);
+
+        let result = get_by_column_id_in_deck_order(&conn, "nonexistent");
+        assert!(matches!(result, Err(JotDeckError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_revision_history() {
+        let (conn, _, column_id) = setup();
+        let card = create(
+            &conn,
+            NewCard {
+                column_id,
+                content: "v1".to_string(),
+            },
+        )
+        .unwrap();
+
+        // 間隔内の連続した編集は 1 つの履歴にまとまる（編集前の本文が残る）
+        update_content(&conn, &card.id, "v2").unwrap();
+        update_content(&conn, &card.id, "v3").unwrap();
+        let revisions = history(&conn, &card.id).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0].content, "v1");
+
+        // 間隔 0 なら毎回残る。本文が変わらない更新は残さない
+        update_content_with_window(&conn, &card.id, "v4", Duration::zero()).unwrap();
+        update_content_with_window(&conn, &card.id, "v4", Duration::zero()).unwrap();
+        let contents: Vec<_> = history(&conn, &card.id)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.content)
+            .collect();
+        assert_eq!(contents, vec!["v3", "v1"]);
+
+        assert!(matches!(history(&conn, "nonexistent"), Err(JotDeckError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_revert_to() {
+        let (conn, _, column_id) = setup();
+        let card = create(
+            &conn,
+            NewCard {
+                column_id,
+                content: "important #notes".to_string(),
+            },
+        )
+        .unwrap();
+        update_content(&conn, &card.id, "").unwrap();
+
+        let revision = history(&conn, &card.id).unwrap().remove(0);
+        let reverted = revert_to(&conn, &card.id, revision.id).unwrap();
+        assert_eq!(reverted.content, "important #notes");
+        assert_eq!(get_by_id(&conn, &card.id).unwrap().content, "important #notes");
+        assert_eq!(tag::get_tags_by_card(&conn, &card.id).unwrap().len(), 1);
+
+        // 戻す前の本文も履歴に残る
+        let revisions = history(&conn, &card.id).unwrap();
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0].content, "");
+
+        let other = create(
+            &conn,
+            NewCard {
+                column_id: card.column_id.clone(),
+                content: "other".to_string(),
+            },
+        )
+        .unwrap();
+        let result = revert_to(&conn, &other.id, revision.id);
+        assert!(matches!(result, Err(JotDeckError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_merge_cards() {
+        let (conn, deck_id, column_id) = setup();
+        let mut ids = Vec::new();
+        for (content, score) in [("A", 0), ("B #idea", 2), ("C", 0), ("D #todo", 3)] {
+            let c = create(
+                &conn,
+                NewCard {
+                    column_id: column_id.clone(),
+                    content: content.to_string(),
+                },
+            )
+            .unwrap();
+            update_score(&conn, &c.id, score).unwrap();
+            ids.push(c.id);
+        }
+
+        let merged = merge(&conn, &[&ids[1], &ids[3]], "\n\n", ScoreMerge::Sum).unwrap();
+        assert_eq!(merged.content, "B #idea\n\nD #todo");
+        assert_eq!(merged.score, 5);
+        assert_eq!(merged.position, 1);
+
+        let live: Vec<_> = get_by_column_id(&conn, &column_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.content, c.position))
+            .collect();
+        assert_eq!(
+            live,
+            vec![
+                ("A".to_string(), 0),
+                ("B #idea\n\nD #todo".to_string(), 1),
+                ("C".to_string(), 2)
+            ]
+        );
+        let tags: Vec<_> = tag::get_tags_by_card(&conn, &merged.id)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(tags, vec!["idea", "todo"]);
+        assert_eq!(get_deleted(&conn, &column_id).unwrap().len(), 2);
+        assert!(delete_stack::list_stack(&conn, &deck_id).unwrap().is_empty());
+
+        // 1 回の undo で元に戻る
+        crate::journal::undo(&conn).unwrap();
+        let live: Vec<_> = get_by_column_id(&conn, &column_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(live, ids);
+        assert!(get_deleted(&conn, &column_id).unwrap().is_empty());
+
+        let merged = merge(&conn, &[&ids[3], &ids[1]], " ", ScoreMerge::Max).unwrap();
+        assert_eq!(merged.content, "D #todo B #idea");
+        assert_eq!(merged.score, 3);
+    }
+
+    #[test]
+    fn test_merge_rejects_invalid_input() {
+        let (conn, _, column_id) = setup();
+        let a = create(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "A".to_string(),
+            },
+        )
+        .unwrap();
+        let b = create(
+            &conn,
+            NewCard {
+                column_id,
+                content: "B".to_string(),
+            },
+        )
+        .unwrap();
+
+        let other_deck = deck::create(
+            &conn,
+            NewDeck {
+                name: "Other".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let other_column = column::create(
+            &conn,
+            NewColumn {
+                deck_id: other_deck.id,
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        let c = create(
+            &conn,
+            NewCard {
+                column_id: other_column.id,
+                content: "C".to_string(),
+            },
+        )
+        .unwrap();
+
+        for ids in [vec![a.id.as_str()], vec![&a.id, &a.id], vec![&a.id, &c.id]] {
+            let result = merge(&conn, &ids, "\n", ScoreMerge::Sum);
+            assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        }
+
+        soft_delete(&conn, &b.id).unwrap();
+        let result = merge(&conn, &[&a.id, &b.id], "\n", ScoreMerge::Sum);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        assert!(matches!(
+            merge(&conn, &[&a.id, "nonexistent"], "\n", ScoreMerge::Sum),
+            Err(JotDeckError::NotFound(_))
+        ));
+        assert!(get_by_id(&conn, &a.id).unwrap().deleted_at.is_none());
+    }
+
+    #[test]
+    fn test_merge_rolls_back_on_failure() {
+        let (conn, _, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A", "B"]);
+        conn.execute_batch(&format!(
+            "CREATE TEMP TRIGGER fail_delete BEFORE UPDATE OF deleted_at ON cards WHEN OLD.id = '{}'
+             BEGIN SELECT RAISE(ABORT, 'fail'); END;",
+            ids[1]
+        ))
+        .unwrap();
+
+        assert!(merge(&conn, &[&ids[0], &ids[1]], "\n", ScoreMerge::Sum).is_err());
+        let live: Vec<_> = get_by_column_id(&conn, &column_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(live, ids);
+        assert!(crate::journal::list(&conn, 10).unwrap().iter().all(|op| op.label != "card.merge"));
+    }
+
+    #[test]
+    fn test_split_card() {
+        let (conn, _, column_id) = setup();
+        let mut ids = Vec::new();
+        for content in ["first", "アイデア #a\n次の話 #b", "last"] {
+            let c = create(
+                &conn,
+                NewCard {
+                    column_id: column_id.clone(),
+                    content: content.to_string(),
+                },
+            )
+            .unwrap();
+            ids.push(c.id);
+        }
+        update_score(&conn, &ids[1], 2).unwrap();
+
+        let (head, tail) = split(&conn, &ids[1], 7).unwrap();
+        assert_eq!(head.content, "アイデア #a");
+        assert_eq!(head.score, 2);
+        assert_eq!(tail.content, "次の話 #b");
+        assert_eq!(tail.score, 0);
+        assert_eq!(tail.position, 2);
+
+        let live: Vec<_> = get_by_column_id(&conn, &column_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.content)
+            .collect();
+        assert_eq!(live, vec!["first", "アイデア #a", "次の話 #b", "last"]);
+        assert_eq!(tag::get_tags_by_card(&conn, &head.id).unwrap()[0].name, "a");
+        assert_eq!(tag::get_tags_by_card(&conn, &tail.id).unwrap()[0].name, "b");
+
+        for at in [0, 5, 100] {
+            let result = split(&conn, &ids[0], at);
+            assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))), "at {}", at);
+        }
+    }
+
+    /// Column に本文だけの Card を順に作る
+    fn create_cards(conn: &Connection, column_id: &str, contents: &[&str]) -> Vec<String> {
+        contents
+            .iter()
+            .map(|content| {
+                create(
+                    conn,
+                    NewCard {
+                        column_id: column_id.to_string(),
+                        content: content.to_string(),
+                    },
+                )
+                .unwrap()
+                .id
+            })
+            .collect()
+    }
+
+    fn positions(conn: &Connection, column_id: &str) -> Vec<(String, i32)> {
+        get_by_column_id(conn, column_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.content, c.position))
+            .collect()
+    }
+
+    #[test]
+    fn test_move_many_to_column() {
+        let (conn, deck_id, column_id) = setup();
+        let other = column::create(
+            &conn,
+            NewColumn {
+                deck_id,
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C", "D", "E"]);
+        create_cards(&conn, &other.id, &["X"]);
+
+        let moved = move_many_to_column(&conn, &[&ids[3], &ids[1]], &other.id).unwrap();
+        assert_eq!(moved[0].content, "D");
+        assert_eq!(moved[0].position, 1);
+        assert_eq!(moved[1].position, 2);
+
+        assert_eq!(
+            positions(&conn, &column_id),
+            vec![("A".into(), 0), ("C".into(), 1), ("E".into(), 2)]
+        );
+        assert_eq!(
+            positions(&conn, &other.id),
+            vec![("X".into(), 0), ("D".into(), 1), ("B".into(), 2)]
+        );
+
+        // 1 件でも不正なら何も変更しない
+        let result = move_many_to_column(&conn, &[&ids[0], "nonexistent"], &other.id);
+        assert!(matches!(result, Err(JotDeckError::NotFound(_))));
+        let result = move_many_to_column(&conn, &[&ids[0], &ids[0]], &other.id);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        assert_eq!(positions(&conn, &column_id).len(), 3);
+        assert!(move_many_to_column(&conn, &[], &other.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_soft_delete_and_restore_many() {
+        let (conn, deck_id, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C", "D", "E"]);
+
+        let deleted = soft_delete_many(&conn, &[&ids[3], &ids[1], &ids[0]]).unwrap();
+        assert!(deleted.iter().all(|c| c.deleted_at.is_some()));
+        assert_eq!(positions(&conn, &column_id), vec![("C".into(), 0), ("E".into(), 1)]);
+        assert_eq!(delete_stack::list_stack(&conn, &deck_id).unwrap().len(), 3);
+
+        let result = soft_delete_many(&conn, &[&ids[2], &ids[1]]);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        assert!(get_by_id(&conn, &ids[2]).unwrap().deleted_at.is_none());
+
+        let restored = restore_many(&conn, &[&ids[3], &ids[0], &ids[1]]).unwrap();
+        assert!(restored.iter().all(|c| c.deleted_at.is_none()));
+        assert_eq!(
+            positions(&conn, &column_id),
+            vec![
+                ("A".into(), 0),
+                ("B".into(), 1),
+                ("C".into(), 2),
+                ("D".into(), 3),
+                ("E".into(), 4)
+            ]
+        );
+        assert!(delete_stack::list_stack(&conn, &deck_id).unwrap().is_empty());
+
+        let result = restore_many(&conn, &[&ids[0]]);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_update_score_many() {
+        let (conn, _, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C"]);
+        update_score(&conn, &ids[0], 2).unwrap();
+
+        let updated = update_score_many(&conn, &[&ids[0], &ids[2]], 3).unwrap();
+        let scores: Vec<_> = updated.iter().map(|c| c.score).collect();
+        assert_eq!(scores, vec![5, 3]);
+        assert_eq!(get_by_id(&conn, &ids[1]).unwrap().score, 0);
+
+        soft_delete(&conn, &ids[1]).unwrap();
+        let result = update_score_many(&conn, &[&ids[0], &ids[1]], 1);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        assert_eq!(get_by_id(&conn, &ids[0]).unwrap().score, 5);
+    }
+
+    #[test]
+    fn test_move_to() {
+        let (conn, deck_id, column_id) = setup();
+        let other = column::create(
+            &conn,
+            NewColumn {
+                deck_id,
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C"]);
+        create_cards(&conn, &other.id, &["X", "Y"]);
+
+        let moved = move_to(&conn, &ids[0], &other.id, 1).unwrap();
+        assert_eq!(moved.column_id, other.id);
+        assert_eq!(moved.position, 1);
+        assert_eq!(positions(&conn, &column_id), vec![("B".into(), 0), ("C".into(), 1)]);
+        assert_eq!(
+            positions(&conn, &other.id),
+            vec![("X".into(), 0), ("A".into(), 1), ("Y".into(), 2)]
+        );
+
+        // 範囲外の position は先頭・末尾に丸める
+        assert_eq!(move_to(&conn, &ids[1], &other.id, 99).unwrap().position, 3);
+        assert_eq!(move_to(&conn, &ids[2], &other.id, -1).unwrap().position, 0);
+        assert_eq!(
+            positions(&conn, &other.id),
+            vec![("C".into(), 0), ("X".into(), 1), ("A".into(), 2), ("Y".into(), 3), ("B".into(), 4)]
+        );
+        assert!(positions(&conn, &column_id).is_empty());
+
+        // 同じ Column 内の移動
+        move_to(&conn, &ids[2], &other.id, 2).unwrap();
+        assert_eq!(
+            positions(&conn, &other.id),
+            vec![("X".into(), 0), ("A".into(), 1), ("C".into(), 2), ("Y".into(), 3), ("B".into(), 4)]
+        );
+    }
+
+    #[test]
+    fn test_reorder_writes_only_target_row() {
+        let (conn, _, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C", "D", "E"]);
+
+        let rows = |conn: &Connection| -> Vec<(String, String, String)> {
+            conn.prepare("SELECT id, rank, updated_at FROM cards ORDER BY id")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap()
+        };
+        let changed = |before: &[(String, String, String)], after: &[(String, String, String)]| -> Vec<String> {
+            before
+                .iter()
+                .zip(after)
+                .filter(|(b, a)| b != a)
+                .map(|(b, _)| b.0.clone())
+                .collect()
+        };
+
+        // 兄弟の rank も updated_at も書き換えない
+        let before = rows(&conn);
+        move_to_position(&conn, &ids[4], 0).unwrap();
+        let after = rows(&conn);
+        assert_eq!(changed(&before, &after), vec![ids[4].clone()]);
+
+        soft_delete(&conn, &ids[1]).unwrap();
+        assert_eq!(changed(&after, &rows(&conn)), vec![ids[1].clone()]);
+        assert_eq!(
+            positions(&conn, &column_id),
+            vec![("E".into(), 0), ("A".into(), 1), ("C".into(), 2), ("D".into(), 3)]
+        );
+        assert_eq!(get_by_id(&conn, &ids[1]).unwrap().position, 2);
+
+        // 削除中に並びが変わっても、削除前に隣にあった Card の間に戻る
+        move_to_position(&conn, &ids[0], 3).unwrap();
+        let before = rows(&conn);
+        restore(&conn, &ids[1]).unwrap();
+        assert_eq!(changed(&before, &rows(&conn)), vec![ids[1].clone()]);
+        assert_eq!(
+            positions(&conn, &column_id),
+            vec![("E".into(), 0), ("B".into(), 1), ("C".into(), 2), ("D".into(), 3), ("A".into(), 4)]
+        );
+    }
+
+    #[test]
+    fn test_reorder_keeps_updated_at() {
+        let (conn, deck_id, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A", "B", "C"]);
+        let other = column::create(
+            &conn,
+            NewColumn {
+                deck_id,
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        let original: Vec<Card> = ids.iter().map(|id| get_by_id(&conn, id).unwrap()).collect();
+        assert!(original.iter().all(|c| c.position_updated_at.is_none()));
+
+        // 並び替えでは updated_at は変えず、position_updated_at だけを記録する
+        let moved = move_to_position(&conn, &ids[2], 0).unwrap();
+        assert_eq!(moved.updated_at, original[2].updated_at);
+        assert!(moved.position_updated_at.is_some());
+        let stored = get_by_id(&conn, &ids[2]).unwrap();
+        assert_eq!(stored.updated_at, original[2].updated_at);
+        assert_eq!(stored.position_updated_at, moved.position_updated_at);
+
+        // 同じ位置への移動は何も書き換えない
+        let same = move_to_position(&conn, &ids[2], 0).unwrap();
+        assert_eq!(same.position_updated_at, moved.position_updated_at);
+
+        // 前への挿入・削除・復元でも隣の Card は変わらない
+        create_at_position(
+            &conn,
+            NewCard {
+                column_id: column_id.clone(),
+                content: "Front".to_string(),
+            },
+            0,
+        )
+        .unwrap();
+        soft_delete(&conn, &ids[1]).unwrap();
+        restore(&conn, &ids[1]).unwrap();
+        let a = get_by_id(&conn, &ids[0]).unwrap();
+        assert_eq!(a.updated_at, original[0].updated_at);
+        assert!(a.position_updated_at.is_none());
+
+        // 別の Column への移動も並び替えとして扱う
+        let moved = move_many_to_column(&conn, &[&ids[0]], &other.id).unwrap();
+        assert_eq!(moved[0].updated_at, original[0].updated_at);
+        assert!(moved[0].position_updated_at.is_some());
+        let moved = move_to(&conn, &ids[0], &column_id, 0).unwrap();
+        assert_eq!(moved.updated_at, original[0].updated_at);
+    }
+
+    #[test]
+    fn test_move_to_validates_target() {
+        let (conn, deck_id, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A"]);
+
+        let other_deck = deck::create(
+            &conn,
+            NewDeck {
+                name: "Other".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let foreign = column::create(
+            &conn,
+            NewColumn {
+                deck_id: other_deck.id,
+                name: "Foreign".to_string(),
+            },
+        )
+        .unwrap();
+        let trashed = column::create(
+            &conn,
+            NewColumn {
+                deck_id,
+                name: "Trashed".to_string(),
+            },
+        )
+        .unwrap();
+        column::soft_delete(&conn, &trashed.id).unwrap();
+
+        for target in [&foreign.id, &trashed.id] {
+            assert!(matches!(
+                move_to(&conn, &ids[0], target, 0),
+                Err(JotDeckError::InvalidOperation(_))
+            ));
+            assert!(matches!(
+                move_to_column(&conn, &ids[0], target),
+                Err(JotDeckError::InvalidOperation(_))
+            ));
+            assert!(matches!(
+                move_many_to_column(&conn, &[&ids[0]], target),
+                Err(JotDeckError::InvalidOperation(_))
+            ));
+        }
+        assert!(matches!(
+            move_to(&conn, &ids[0], "nonexistent", 0),
+            Err(JotDeckError::NotFound(_))
+        ));
+        assert_eq!(get_by_id(&conn, &ids[0]).unwrap().column_id, column_id);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let (conn, deck_id, column_id) = setup();
+        let ids = create_cards(&conn, &column_id, &["A #tag", "B"]);
+        update_score(&conn, &ids[0], 4).unwrap();
+
+        let copy = duplicate(&conn, &ids[0], &column_id).unwrap();
+        assert_ne!(copy.id, ids[0]);
+        assert_eq!(copy.score, 4);
+        assert_eq!(copy.position, 1);
+        assert_eq!(
+            positions(&conn, &column_id),
+            vec![("A #tag".into(), 0), ("A #tag".into(), 1), ("B".into(), 2)]
+        );
+        assert_eq!(tag::get_tags_by_card(&conn, &copy.id).unwrap()[0].name, "tag");
+
+        let other = column::create(
+            &conn,
+            NewColumn {
+                deck_id,
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        create_cards(&conn, &other.id, &["X"]);
+        let copy = duplicate(&conn, &ids[1], &other.id).unwrap();
+        assert_eq!(copy.position, 1);
+        assert_eq!(positions(&conn, &column_id).len(), 3);
+
+        soft_delete(&conn, &ids[1]).unwrap();
+        assert!(matches!(
+            duplicate(&conn, &ids[1], &other.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_validates_column() {
+        let (conn, _, column_id) = setup();
+        create_cards(&conn, &column_id, &["A", "B"]);
+
+        let new_card = |column_id: &str| NewCard {
+            column_id: column_id.to_string(),
+            content: "x".to_string(),
+        };
+        assert!(matches!(create(&conn, new_card("nonexistent")), Err(JotDeckError::NotFound(_))));
+        assert!(matches!(
+            create_at_position(&conn, new_card("nonexistent"), 0),
+            Err(JotDeckError::NotFound(_))
+        ));
+
+        // 範囲外の position は丸める
+        assert_eq!(create_at_position(&conn, new_card(&column_id), 10).unwrap().position, 2);
+        assert_eq!(create_at_position(&conn, new_card(&column_id), -3).unwrap().position, 0);
+        assert!(crate::db::check_integrity(&conn).unwrap().is_ok());
+
+        column::soft_delete(&conn, &column_id).unwrap();
+        assert!(matches!(create(&conn, new_card(&column_id)), Err(JotDeckError::InvalidOperation(_))));
+        assert!(matches!(
+            create_at_position(&conn, new_card(&column_id), 0),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+    }
 }
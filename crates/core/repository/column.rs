@@ -2,8 +2,12 @@ use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection};
 use ulid::Ulid;
 
+use crate::db;
 use crate::error::{JotDeckError, Result};
-use crate::models::{Column, NewColumn};
+use crate::journal;
+use crate::models::{Column, DeletedEntityType, NewColumn};
+use crate::rank;
+use crate::repository::{card, deck, delete_stack, tag};
 
 /// RFC3339 文字列を DateTime<Utc> にパースする
 fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
@@ -51,98 +55,137 @@ fn generate_column_name(conn: &Connection, deck_id: &str) -> Result<String> {
     Ok(format!("{}-col", prefix))
 }
 
-/// 次の position を取得する
-fn get_next_position(conn: &Connection, deck_id: &str) -> Result<i32> {
-    let max_pos: Option<i32> = conn.query_row(
-        "SELECT MAX(position) FROM columns WHERE deck_id = ?1 AND deleted_at IS NULL",
+/// Column を読み出す列（`columns c` に対して使う）
+///
+/// position は保存せず、同じ Deck の削除されていない Column のうち rank が前にある数として求める。
+pub(crate) const COLUMN_COLUMNS: &str = "c.id, c.deck_id, c.name,
+    (SELECT COUNT(*) FROM columns s WHERE s.deck_id = c.deck_id AND s.deleted_at IS NULL AND s.rank < c.rank),
+    c.created_at, c.updated_at, c.deleted_at, c.deleted_with_deck, c.position_updated_at";
+
+/// Deck 内の削除されていない Column の数
+fn live_count(conn: &Connection, deck_id: &str) -> Result<i32> {
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM columns WHERE deck_id = ?1 AND deleted_at IS NULL",
         params![deck_id],
         |row| row.get(0),
     )?;
+    Ok(count)
+}
+
+/// Deck 内の index 番目（削除されていない Column の中で数える）に入る rank を作る
+fn rank_at(conn: &Connection, deck_id: &str, index: i32, exclude_id: &str) -> Result<String> {
+    rank::for_index(conn, "columns", "deck_id", deck_id, index, exclude_id)
+}
+
+/// Deck の末尾に入る rank を作る
+fn rank_last(conn: &Connection, deck_id: &str, exclude_id: &str) -> Result<String> {
+    rank::append(conn, "columns", "deck_id", deck_id, exclude_id)
+}
 
-    Ok(max_pos.unwrap_or(-1) + 1)
+/// Column を追加できる Deck か確認する（存在し、削除されていないこと）
+fn check_deck_writable(conn: &Connection, deck_id: &str) -> Result<()> {
+    let deck = deck::get_by_id(conn, deck_id)?;
+    if deck.deleted_at.is_some() {
+        return Err(JotDeckError::InvalidOperation(
+            "Cannot add column to deleted deck".to_string(),
+        ));
+    }
+    Ok(())
 }
 
 /// Column を作成する
 pub fn create(conn: &Connection, new_column: NewColumn) -> Result<Column> {
-    let id = Ulid::new().to_string();
-    let now = Utc::now();
-    let position = get_next_position(conn, &new_column.deck_id)?;
-
-    let name = if new_column.name.is_empty() {
-        generate_column_name(conn, &new_column.deck_id)?
-    } else {
-        new_column.name
-    };
-
-    conn.execute(
-        "INSERT INTO columns (id, deck_id, name, position, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            &id,
-            &new_column.deck_id,
-            &name,
-            position,
-            now.to_rfc3339(),
-            now.to_rfc3339(),
-        ],
-    )?;
+    journal::record(conn, "column.create", || {
+        check_deck_writable(conn, &new_column.deck_id)?;
+
+        let id = Ulid::new().to_string();
+        let now = Utc::now();
+        let position = live_count(conn, &new_column.deck_id)?;
+        let key = rank_last(conn, &new_column.deck_id, "")?;
+
+        let name = if new_column.name.is_empty() {
+            generate_column_name(conn, &new_column.deck_id)?
+        } else {
+            new_column.name
+        };
+
+        conn.execute(
+            "INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                &id,
+                &new_column.deck_id,
+                &name,
+                key,
+                now.to_rfc3339(),
+                now.to_rfc3339(),
+            ],
+        )?;
 
-    Ok(Column {
-        id,
-        deck_id: new_column.deck_id,
-        name,
-        position,
-        created_at: now,
-        updated_at: now,
-        deleted_at: None,
+        Ok(Column {
+            id,
+            deck_id: new_column.deck_id,
+            name,
+            position,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_deck: false,
+            position_updated_at: None,
+        })
     })
 }
 
-/// 特定の位置に Column を作成する
+/// 特定の位置に Column を作成する（position は 0〜末尾の次に丸める）
 pub fn create_at_position(conn: &Connection, new_column: NewColumn, position: i32) -> Result<Column> {
-    let id = Ulid::new().to_string();
-    let now = Utc::now();
+    journal::record(conn, "column.create_at_position", || {
+        check_deck_writable(conn, &new_column.deck_id)?;
 
-    let name = if new_column.name.is_empty() {
-        generate_column_name(conn, &new_column.deck_id)?
-    } else {
-        new_column.name
-    };
+        let id = Ulid::new().to_string();
+        let now = Utc::now();
 
-    let tx = conn.unchecked_transaction()?;
+        let name = if new_column.name.is_empty() {
+            generate_column_name(conn, &new_column.deck_id)?
+        } else {
+            new_column.name
+        };
 
-    // 挿入位置以降の Column の position を +1 する
-    tx.execute(
-        "UPDATE columns SET position = position + 1, updated_at = ?1 WHERE deck_id = ?2 AND position >= ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), &new_column.deck_id, position],
-    )?;
+        let tx = db::savepoint(conn)?;
 
-    tx.execute(
-        "INSERT INTO columns (id, deck_id, name, position, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-        params![
-            &id,
-            &new_column.deck_id,
-            &name,
-            position,
-            now.to_rfc3339(),
-            now.to_rfc3339(),
-        ],
-    )?;
+        // 範囲外の位置は先頭・末尾に丸める
+        let position = position.clamp(0, live_count(&tx, &new_column.deck_id)?);
+        let key = rank_at(&tx, &new_column.deck_id, position, "")?;
 
-    tx.commit()?;
+        tx.execute(
+            "INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                &id,
+                &new_column.deck_id,
+                &name,
+                key,
+                now.to_rfc3339(),
+                now.to_rfc3339(),
+            ],
+        )?;
 
-    Ok(Column {
-        id,
-        deck_id: new_column.deck_id,
-        name,
-        position,
-        created_at: now,
-        updated_at: now,
-        deleted_at: None,
+        tx.commit()?;
+
+        Ok(Column {
+            id,
+            deck_id: new_column.deck_id,
+            name,
+            position,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_deck: false,
+            position_updated_at: None,
+        })
     })
 }
 
-fn row_to_column(row: &rusqlite::Row) -> rusqlite::Result<Column> {
+pub(crate) fn row_to_column(row: &rusqlite::Row) -> rusqlite::Result<Column> {
     let deleted_at_str: Option<String> = row.get(6)?;
+    let deleted_with_deck: i32 = row.get(7)?;
 
     Ok(Column {
         id: row.get(0)?,
@@ -152,13 +195,17 @@ fn row_to_column(row: &rusqlite::Row) -> rusqlite::Result<Column> {
         created_at: parse_datetime(&row.get::<_, String>(4)?, 4)?,
         updated_at: parse_datetime(&row.get::<_, String>(5)?, 5)?,
         deleted_at: deleted_at_str.and_then(|s| parse_datetime_opt(&s)),
+        deleted_with_deck: deleted_with_deck != 0,
+        position_updated_at: row
+            .get::<_, Option<String>>(8)?
+            .and_then(|s| parse_datetime_opt(&s)),
     })
 }
 
 /// ID で Column を取得する
 pub fn get_by_id(conn: &Connection, id: &str) -> Result<Column> {
     conn.query_row(
-        "SELECT id, deck_id, name, position, created_at, updated_at, deleted_at FROM columns WHERE id = ?1",
+        &format!("SELECT {} FROM columns c WHERE c.id = ?1", COLUMN_COLUMNS),
         params![id],
         row_to_column,
     )
@@ -172,9 +219,10 @@ pub fn get_by_id(conn: &Connection, id: &str) -> Result<Column> {
 
 /// Deck 内の Column 一覧を取得する（削除されていないもののみ）
 pub fn get_by_deck_id(conn: &Connection, deck_id: &str) -> Result<Vec<Column>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, deck_id, name, position, created_at, updated_at, deleted_at FROM columns WHERE deck_id = ?1 AND deleted_at IS NULL ORDER BY position ASC",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM columns c WHERE c.deck_id = ?1 AND c.deleted_at IS NULL ORDER BY c.rank ASC",
+        COLUMN_COLUMNS
+    ))?;
 
     let columns = stmt
         .query_map(params![deck_id], row_to_column)?
@@ -185,155 +233,287 @@ pub fn get_by_deck_id(conn: &Connection, deck_id: &str) -> Result<Vec<Column>> {
 
 /// Column を更新する
 pub fn update(conn: &Connection, id: &str, name: Option<&str>) -> Result<Column> {
-    let column = get_by_id(conn, id)?;
+    journal::record(conn, "column.update", || {
+        let column = get_by_id(conn, id)?;
 
-    if column.deleted_at.is_some() {
-        return Err(JotDeckError::InvalidOperation(
-            "Cannot update deleted column".to_string(),
-        ));
-    }
+        if column.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot update deleted column".to_string(),
+            ));
+        }
 
-    let now = Utc::now();
-    let new_name = name.unwrap_or(&column.name);
+        let now = Utc::now();
+        let new_name = name.unwrap_or(&column.name);
 
-    conn.execute(
-        "UPDATE columns SET name = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_name, now.to_rfc3339(), id],
-    )?;
+        conn.execute(
+            "UPDATE columns SET name = ?1, updated_at = ?2 WHERE id = ?3",
+            params![new_name, now.to_rfc3339(), id],
+        )?;
 
-    Ok(Column {
-        id: column.id,
-        deck_id: column.deck_id,
-        name: new_name.to_string(),
-        position: column.position,
-        created_at: column.created_at,
-        updated_at: now,
-        deleted_at: None,
+        Ok(Column {
+            id: column.id,
+            deck_id: column.deck_id,
+            name: new_name.to_string(),
+            position: column.position,
+            created_at: column.created_at,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_deck: false,
+            position_updated_at: column.position_updated_at,
+        })
     })
 }
 
 /// Column を移動する（並び替え）
 pub fn move_to_position(conn: &Connection, id: &str, new_position: i32) -> Result<Column> {
-    let column = get_by_id(conn, id)?;
+    journal::record(conn, "column.move_to_position", || {
+        let column = get_by_id(conn, id)?;
 
-    if column.deleted_at.is_some() {
-        return Err(JotDeckError::InvalidOperation(
-            "Cannot move deleted column".to_string(),
-        ));
-    }
+        if column.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot move deleted column".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
 
-    let old_position = column.position;
-    let now = Utc::now();
+        // 移動する Column を除いた数が挿入できる位置の上限
+        let new_position = new_position.clamp(0, live_count(&tx, &column.deck_id)? - 1);
 
-    let tx = conn.unchecked_transaction()?;
+        // 同じ位置への移動では何も書き換えない
+        if new_position == column.position {
+            return Ok(column);
+        }
 
-    if new_position > old_position {
-        // 下に移動: old_position < x <= new_position の Column を -1
+        // 書き換えるのは移動する Column の行だけ。並び替えでは updated_at は変えない
+        let key = rank_at(&tx, &column.deck_id, new_position, id)?;
         tx.execute(
-            "UPDATE columns SET position = position - 1, updated_at = ?1 WHERE deck_id = ?2 AND position > ?3 AND position <= ?4 AND deleted_at IS NULL",
-            params![now.to_rfc3339(), &column.deck_id, old_position, new_position],
+            "UPDATE columns SET rank = ?1, position_updated_at = ?2 WHERE id = ?3",
+            params![key, now.to_rfc3339(), id],
         )?;
-    } else if new_position < old_position {
-        // 上に移動: new_position <= x < old_position の Column を +1
+
+        tx.commit()?;
+
+        Ok(Column {
+            position: new_position,
+            position_updated_at: Some(now),
+            ..column
+        })
+    })
+}
+
+/// Column を別の Deck の末尾に移動する（Card ごと移動する）
+pub fn move_to_deck(conn: &Connection, id: &str, deck_id: &str) -> Result<Column> {
+    journal::record(conn, "column.move_to_deck", || {
+        let column = get_by_id(conn, id)?;
+
+        if column.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot move deleted column".to_string(),
+            ));
+        }
+
+        if column.deck_id == deck_id {
+            return Err(JotDeckError::InvalidOperation(
+                "Column is already in this deck".to_string(),
+            ));
+        }
+
+        let target = deck::get_by_id(conn, deck_id)?;
+        if target.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot move column to deleted deck".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+        let tx = db::savepoint(conn)?;
+
+        let position = live_count(&tx, deck_id)?;
+        let key = rank_last(&tx, deck_id, id)?;
         tx.execute(
-            "UPDATE columns SET position = position + 1, updated_at = ?1 WHERE deck_id = ?2 AND position >= ?3 AND position < ?4 AND deleted_at IS NULL",
-            params![now.to_rfc3339(), &column.deck_id, new_position, old_position],
+            "UPDATE columns SET deck_id = ?1, rank = ?2, position_updated_at = ?3 WHERE id = ?4",
+            params![deck_id, key, now.to_rfc3339(), id],
         )?;
-    }
 
-    tx.execute(
-        "UPDATE columns SET position = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_position, now.to_rfc3339(), id],
-    )?;
+        // ゴミ箱の Card は移動先の Deck の削除スタックから復元できるようにする
+        tx.execute(
+            "UPDATE delete_stack SET deck_id = ?1
+             WHERE entity_type = ?2 AND entity_id IN (SELECT id FROM cards WHERE column_id = ?3)",
+            params![deck_id, DeletedEntityType::Card.to_db_value(), id],
+        )?;
 
-    tx.commit()?;
+        tx.commit()?;
 
-    Ok(Column {
-        position: new_position,
-        updated_at: now,
-        ..column
+        Ok(Column {
+            deck_id: deck_id.to_string(),
+            position,
+            position_updated_at: Some(now),
+            ..column
+        })
+    })
+}
+
+/// Column を削除されていない Card ごと複製する
+///
+/// 同じ Deck なら元の Column の直後に、別の Deck なら末尾に作る。Card は新しい ID で同じ順に並ぶ。
+pub fn duplicate(conn: &Connection, id: &str, deck_id: &str) -> Result<Column> {
+    journal::record(conn, "column.duplicate", || {
+        let column = get_by_id(conn, id)?;
+
+        if column.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot duplicate deleted column".to_string(),
+            ));
+        }
+
+        let target = deck::get_by_id(conn, deck_id)?;
+        if target.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot duplicate column into deleted deck".to_string(),
+            ));
+        }
+
+        let cards = card::get_by_column_id(conn, id)?;
+        let new_id = Ulid::new().to_string();
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
+
+        let (position, key) = if column.deck_id == deck_id {
+            (column.position + 1, rank_at(&tx, deck_id, column.position + 1, "")?)
+        } else {
+            (live_count(&tx, deck_id)?, rank_last(&tx, deck_id, "")?)
+        };
+
+        tx.execute(
+            "INSERT INTO columns (id, deck_id, name, rank, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![&new_id, deck_id, &column.name, key, now.to_rfc3339(), now.to_rfc3339()],
+        )?;
+
+        let mut card_key: Option<String> = None;
+        for c in &cards {
+            let next = rank::between(card_key.as_deref(), None)?;
+            card::insert_copy(&tx, c, &new_id, &next, now)?;
+            card_key = Some(next);
+        }
+
+        tx.commit()?;
+
+        Ok(Column {
+            id: new_id,
+            deck_id: deck_id.to_string(),
+            name: column.name,
+            position,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            deleted_with_deck: false,
+            position_updated_at: None,
+        })
     })
 }
 
 /// Column を論理削除する（所属する Card も連動して論理削除）
 pub fn soft_delete(conn: &Connection, id: &str) -> Result<()> {
-    let column = get_by_id(conn, id)?;
+    journal::record(conn, "column.soft_delete", || {
+        let column = get_by_id(conn, id)?;
 
-    if column.deleted_at.is_some() {
-        return Err(JotDeckError::InvalidOperation(
-            "Column is already deleted".to_string(),
-        ));
-    }
+        if column.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Column is already deleted".to_string(),
+            ));
+        }
 
-    let now = Utc::now();
+        let now = Utc::now();
 
-    let tx = conn.unchecked_transaction()?;
+        let tx = db::savepoint(conn)?;
 
-    // 所属する Card を連動削除
-    tx.execute(
-        "UPDATE cards SET deleted_at = ?1, deleted_with_column = 1, updated_at = ?1 WHERE column_id = ?2 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), id],
-    )?;
+        // 所属する Card を連動削除
+        tx.execute(
+            "UPDATE cards SET deleted_at = ?1, deleted_with_column = 1, updated_at = ?1 WHERE column_id = ?2 AND deleted_at IS NULL",
+            params![now.to_rfc3339(), id],
+        )?;
 
-    // Column を論理削除
-    tx.execute(
-        "UPDATE columns SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
-        params![now.to_rfc3339(), id],
-    )?;
+        // Column を論理削除
+        tx.execute(
+            "UPDATE columns SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), id],
+        )?;
 
-    // position を詰める
-    tx.execute(
-        "UPDATE columns SET position = position - 1, updated_at = ?1 WHERE deck_id = ?2 AND position > ?3 AND deleted_at IS NULL",
-        params![now.to_rfc3339(), &column.deck_id, column.position],
-    )?;
+        delete_stack::push(&tx, &column.deck_id, DeletedEntityType::Column, id, now)?;
 
-    tx.commit()?;
+        tx.commit()?;
 
-    Ok(())
+        Ok(())
+    })
 }
 
 /// Column を復元する（連動削除された Card も復元）
 pub fn restore(conn: &Connection, id: &str) -> Result<Column> {
-    let column = get_by_id(conn, id)?;
+    journal::record(conn, "column.restore", || {
+        let column = get_by_id(conn, id)?;
 
-    if column.deleted_at.is_none() {
-        return Err(JotDeckError::InvalidOperation(
-            "Column is not deleted".to_string(),
-        ));
-    }
+        if column.deleted_at.is_none() {
+            return Err(JotDeckError::InvalidOperation(
+                "Column is not deleted".to_string(),
+            ));
+        }
 
-    let now = Utc::now();
-    let new_position = get_next_position(conn, &column.deck_id)?;
+        // Deck ごと削除された Column は Deck の復元時に復元される
+        if column.deleted_with_deck {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot restore column that was deleted with deck. Restore the deck instead.".to_string(),
+            ));
+        }
 
-    let tx = conn.unchecked_transaction()?;
+        if deck::get_by_id(conn, &column.deck_id)?.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot restore column in deleted deck. Restore the deck first.".to_string(),
+            ));
+        }
 
-    // 連動削除された Card を復元
-    tx.execute(
-        "UPDATE cards SET deleted_at = NULL, deleted_with_column = 0, updated_at = ?1 WHERE column_id = ?2 AND deleted_with_column = 1",
-        params![now.to_rfc3339(), id],
-    )?;
+        let now = Utc::now();
+        let new_position = live_count(conn, &column.deck_id)?;
+        let key = rank_last(conn, &column.deck_id, id)?;
 
-    // Column を復元
-    tx.execute(
-        "UPDATE columns SET deleted_at = NULL, position = ?1, updated_at = ?2 WHERE id = ?3",
-        params![new_position, now.to_rfc3339(), id],
-    )?;
+        let tx = db::savepoint(conn)?;
 
-    tx.commit()?;
+        // 連動削除された Card を復元
+        tx.execute(
+            "UPDATE cards SET deleted_at = NULL, deleted_with_column = 0, updated_at = ?1 WHERE column_id = ?2 AND deleted_with_column = 1",
+            params![now.to_rfc3339(), id],
+        )?;
+        tag::resync_live_cards(&tx, "c.column_id = ?1", params![id])?;
 
-    Ok(Column {
-        deleted_at: None,
-        position: new_position,
-        updated_at: now,
-        ..column
+        // Column を復元
+        tx.execute(
+            "UPDATE columns SET deleted_at = NULL, rank = ?1, updated_at = ?2, position_updated_at = ?2 WHERE id = ?3",
+            params![key, now.to_rfc3339(), id],
+        )?;
+
+        delete_stack::remove(&tx, DeletedEntityType::Column, id)?;
+
+        tx.commit()?;
+
+        Ok(Column {
+            deleted_at: None,
+            position: new_position,
+            updated_at: now,
+            position_updated_at: Some(now),
+            ..column
+        })
     })
 }
 
 /// 削除済みの Column 一覧を取得する（ゴミ箱表示用）
 pub fn get_deleted(conn: &Connection, deck_id: &str) -> Result<Vec<Column>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, deck_id, name, position, created_at, updated_at, deleted_at FROM columns WHERE deck_id = ?1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC",
-    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM columns c WHERE c.deck_id = ?1 AND c.deleted_at IS NOT NULL ORDER BY c.deleted_at DESC",
+        COLUMN_COLUMNS
+    ))?;
 
     let columns = stmt
         .query_map(params![deck_id], row_to_column)?
@@ -346,8 +526,8 @@ pub fn get_deleted(conn: &Connection, deck_id: &str) -> Result<Vec<Column>> {
 mod tests {
     use super::*;
     use crate::db::create_in_memory;
-    use crate::models::{NewDeck, SortOrder};
-    use crate::repository::deck;
+    use crate::models::{NewCard, NewDeck, SortOrder};
+    use crate::repository::tag;
 
     fn setup() -> (Connection, String) {
         let conn = create_in_memory().unwrap();
@@ -479,6 +659,41 @@ mod tests {
         assert_eq!(columns[2].name, "A");
     }
 
+    #[test]
+    fn test_reorder_keeps_updated_at() {
+        let (conn, deck_id) = setup();
+        let a = create_with_cards(&conn, &deck_id, "A", &[]);
+        let b = create_with_cards(&conn, &deck_id, "B", &[]);
+        let c = create_with_cards(&conn, &deck_id, "C", &[]);
+
+        // 並び替えでは updated_at は変えず、position_updated_at だけを記録する
+        let moved = move_to_position(&conn, &c.id, 0).unwrap();
+        assert_eq!(moved.updated_at, c.updated_at);
+        assert!(moved.position_updated_at.is_some());
+
+        // 前への挿入・削除・復元でも隣の Column は変わらない
+        create_at_position(
+            &conn,
+            NewColumn {
+                deck_id: deck_id.clone(),
+                name: "Front".to_string(),
+            },
+            0,
+        )
+        .unwrap();
+        soft_delete(&conn, &b.id).unwrap();
+        restore(&conn, &b.id).unwrap();
+        let after = get_by_id(&conn, &a.id).unwrap();
+        assert_eq!(after.updated_at, a.updated_at);
+        assert!(after.position_updated_at.is_none());
+
+        // 名前の変更は並び順の変更日時を残す
+        let renamed = update(&conn, &c.id, Some("C2")).unwrap();
+        assert_ne!(renamed.updated_at, c.updated_at);
+        assert_eq!(renamed.position_updated_at, moved.position_updated_at);
+        assert_eq!(get_by_id(&conn, &c.id).unwrap().position_updated_at, moved.position_updated_at);
+    }
+
     #[test]
     fn test_to_alphabetic() {
         // Single letter: a-z (0-25)
@@ -501,4 +716,115 @@ mod tests {
         // Four letters (18278+)
         assert_eq!(to_alphabetic(18278), "aaaa");
     }
+
+    /// Column と本文だけの Card を作る
+    fn create_with_cards(conn: &Connection, deck_id: &str, name: &str, contents: &[&str]) -> Column {
+        let column = create(
+            conn,
+            NewColumn {
+                deck_id: deck_id.to_string(),
+                name: name.to_string(),
+            },
+        )
+        .unwrap();
+        for content in contents {
+            card::create(
+                conn,
+                NewCard {
+                    column_id: column.id.clone(),
+                    content: content.to_string(),
+                },
+            )
+            .unwrap();
+        }
+        column
+    }
+
+    fn names(conn: &Connection, deck_id: &str) -> Vec<(String, i32)> {
+        get_by_deck_id(conn, deck_id)
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.name, c.position))
+            .collect()
+    }
+
+    #[test]
+    fn test_move_to_deck() {
+        let (conn, deck_id) = setup();
+        let other = deck::create(
+            &conn,
+            NewDeck {
+                name: "Other".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        create_with_cards(&conn, &deck_id, "A", &[]);
+        let b = create_with_cards(&conn, &deck_id, "B", &["one", "two"]);
+        create_with_cards(&conn, &deck_id, "C", &[]);
+        create_with_cards(&conn, &other.id, "X", &[]);
+        let trashed = card::get_by_column_id(&conn, &b.id).unwrap().remove(1);
+        card::soft_delete(&conn, &trashed.id).unwrap();
+
+        let moved = move_to_deck(&conn, &b.id, &other.id).unwrap();
+        assert_eq!(moved.deck_id, other.id);
+        assert_eq!(moved.position, 1);
+        assert_eq!(names(&conn, &deck_id), vec![("A".into(), 0), ("C".into(), 1)]);
+        assert_eq!(names(&conn, &other.id), vec![("X".into(), 0), ("B".into(), 1)]);
+
+        // Card は Column ごと移動し、ゴミ箱の Card は移動先の Deck から戻せる
+        assert_eq!(card::get_by_column_id(&conn, &b.id).unwrap().len(), 1);
+        assert!(delete_stack::list_stack(&conn, &deck_id).unwrap().is_empty());
+        assert_eq!(delete_stack::undo_last(&conn, &other.id).unwrap().unwrap().entity_id, trashed.id);
+
+        assert!(matches!(
+            move_to_deck(&conn, &b.id, &other.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+        assert!(matches!(
+            move_to_deck(&conn, &b.id, "nonexistent"),
+            Err(JotDeckError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let (conn, deck_id) = setup();
+        let a = create_with_cards(&conn, &deck_id, "A", &["first #idea", "second", "third"]);
+        create_with_cards(&conn, &deck_id, "B", &[]);
+        let deleted = card::get_by_column_id(&conn, &a.id).unwrap().remove(1);
+        card::soft_delete(&conn, &deleted.id).unwrap();
+        let first = card::get_by_column_id(&conn, &a.id).unwrap().remove(0);
+        card::update_score(&conn, &first.id, 2).unwrap();
+
+        // 同じ Deck では直後に入る
+        let copy = duplicate(&conn, &a.id, &deck_id).unwrap();
+        assert_ne!(copy.id, a.id);
+        assert_eq!(
+            names(&conn, &deck_id),
+            vec![("A".into(), 0), ("A".into(), 1), ("B".into(), 2)]
+        );
+
+        let cards = card::get_by_column_id(&conn, &copy.id).unwrap();
+        let contents: Vec<_> = cards.iter().map(|c| (c.content.as_str(), c.position, c.score)).collect();
+        assert_eq!(contents, vec![("first #idea", 0, 2), ("third", 1, 0)]);
+        assert_ne!(cards[0].id, first.id);
+        assert_eq!(tag::get_tags_by_card(&conn, &cards[0].id).unwrap()[0].name, "idea");
+        assert_eq!(card::get_by_column_id(&conn, &a.id).unwrap().len(), 2);
+
+        // 別の Deck では末尾に入る
+        let other = deck::create(
+            &conn,
+            NewDeck {
+                name: "Other".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        create_with_cards(&conn, &other.id, "X", &[]);
+        let copy = duplicate(&conn, &a.id, &other.id).unwrap();
+        assert_eq!(copy.position, 1);
+        assert_eq!(card::get_by_column_id(&conn, &copy.id).unwrap().len(), 2);
+        assert_eq!(tag::get_tags_by_deck(&conn, &other.id).unwrap().len(), 1);
+    }
 }
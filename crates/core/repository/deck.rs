@@ -2,8 +2,11 @@ use chrono::{DateTime, Utc};
 use rusqlite::{params, Connection};
 use ulid::Ulid;
 
+use crate::db;
 use crate::error::{JotDeckError, Result};
+use crate::journal;
 use crate::models::{Deck, NewDeck, SortOrder};
+use crate::repository::tag;
 
 /// RFC3339 文字列を DateTime<Utc> にパースする
 fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
@@ -20,43 +23,58 @@ fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
 
 /// Deck を作成する
 pub fn create(conn: &Connection, new_deck: NewDeck) -> Result<Deck> {
-    let id = Ulid::new().to_string();
-    let now = Utc::now();
-
-    conn.execute(
-        "INSERT INTO decks (id, name, sort_order, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![
-            &id,
-            &new_deck.name,
-            new_deck.sort_order.to_db_value(),
-            now.to_rfc3339(),
-            now.to_rfc3339(),
-        ],
-    )?;
+    journal::record(conn, "deck.create", || {
+        let id = Ulid::new().to_string();
+        let now = Utc::now();
+
+        conn.execute(
+            "INSERT INTO decks (id, name, sort_order, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                &id,
+                &new_deck.name,
+                new_deck.sort_order.to_db_value(),
+                now.to_rfc3339(),
+                now.to_rfc3339(),
+            ],
+        )?;
+
+        Ok(Deck {
+            id,
+            name: new_deck.name,
+            sort_order: new_deck.sort_order,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        })
+    })
+}
+
+/// RFC3339 文字列を Option<DateTime<Utc>> にパースする（deleted_at 用）
+fn parse_datetime_opt(s: &str) -> Option<DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+}
+
+pub(crate) fn row_to_deck(row: &rusqlite::Row) -> rusqlite::Result<Deck> {
+    let deleted_at_str: Option<String> = row.get(5)?;
 
     Ok(Deck {
-        id,
-        name: new_deck.name,
-        sort_order: new_deck.sort_order,
-        created_at: now,
-        updated_at: now,
+        id: row.get(0)?,
+        name: row.get(1)?,
+        sort_order: SortOrder::from_db_value(&row.get::<_, String>(2)?),
+        created_at: parse_datetime(&row.get::<_, String>(3)?, 3)?,
+        updated_at: parse_datetime(&row.get::<_, String>(4)?, 4)?,
+        deleted_at: deleted_at_str.and_then(|s| parse_datetime_opt(&s)),
     })
 }
 
 /// ID で Deck を取得する
 pub fn get_by_id(conn: &Connection, id: &str) -> Result<Deck> {
     conn.query_row(
-        "SELECT id, name, sort_order, created_at, updated_at FROM decks WHERE id = ?1",
+        "SELECT id, name, sort_order, created_at, updated_at, deleted_at FROM decks WHERE id = ?1",
         params![id],
-        |row| {
-            Ok(Deck {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                sort_order: SortOrder::from_db_value(&row.get::<_, String>(2)?),
-                created_at: parse_datetime(&row.get::<_, String>(3)?, 3)?,
-                updated_at: parse_datetime(&row.get::<_, String>(4)?, 4)?,
-            })
-        },
+        row_to_deck,
     )
     .map_err(|e| match e {
         rusqlite::Error::QueryReturnedNoRows => {
@@ -66,21 +84,14 @@ pub fn get_by_id(conn: &Connection, id: &str) -> Result<Deck> {
     })
 }
 
-/// すべての Deck を取得する
+/// すべての Deck を取得する（削除されていないもののみ）
 pub fn get_all(conn: &Connection) -> Result<Vec<Deck>> {
-    let mut stmt =
-        conn.prepare("SELECT id, name, sort_order, created_at, updated_at FROM decks ORDER BY created_at DESC")?;
+    let mut stmt = conn.prepare(
+        "SELECT id, name, sort_order, created_at, updated_at, deleted_at FROM decks WHERE deleted_at IS NULL ORDER BY created_at DESC",
+    )?;
 
     let decks = stmt
-        .query_map([], |row| {
-            Ok(Deck {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                sort_order: SortOrder::from_db_value(&row.get::<_, String>(2)?),
-                created_at: parse_datetime(&row.get::<_, String>(3)?, 3)?,
-                updated_at: parse_datetime(&row.get::<_, String>(4)?, 4)?,
-            })
-        })?
+        .query_map([], row_to_deck)?
         .collect::<rusqlite::Result<Vec<_>>>()?;
 
     Ok(decks)
@@ -88,61 +99,197 @@ pub fn get_all(conn: &Connection) -> Result<Vec<Deck>> {
 
 /// Deck を更新する
 pub fn update(conn: &Connection, id: &str, name: Option<&str>, sort_order: Option<SortOrder>) -> Result<Deck> {
-    let deck = get_by_id(conn, id)?;
-    let now = Utc::now();
+    journal::record(conn, "deck.update", || {
+        let deck = get_by_id(conn, id)?;
 
-    let new_name = name.unwrap_or(&deck.name);
-    let new_sort_order = sort_order.unwrap_or(deck.sort_order);
+        if deck.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Cannot update deleted deck".to_string(),
+            ));
+        }
 
-    conn.execute(
-        "UPDATE decks SET name = ?1, sort_order = ?2, updated_at = ?3 WHERE id = ?4",
-        params![new_name, new_sort_order.to_db_value(), now.to_rfc3339(), id],
-    )?;
+        let now = Utc::now();
 
-    Ok(Deck {
-        id: deck.id,
-        name: new_name.to_string(),
-        sort_order: new_sort_order,
-        created_at: deck.created_at,
-        updated_at: now,
+        let new_name = name.unwrap_or(&deck.name);
+        let new_sort_order = sort_order.unwrap_or(deck.sort_order);
+
+        conn.execute(
+            "UPDATE decks SET name = ?1, sort_order = ?2, updated_at = ?3 WHERE id = ?4",
+            params![new_name, new_sort_order.to_db_value(), now.to_rfc3339(), id],
+        )?;
+
+        Ok(Deck {
+            id: deck.id,
+            name: new_name.to_string(),
+            sort_order: new_sort_order,
+            created_at: deck.created_at,
+            updated_at: now,
+            deleted_at: None,
+        })
+    })
+}
+
+/// Deck を論理削除する（所属する Column と Card も連動して論理削除）
+pub fn soft_delete(conn: &Connection, id: &str) -> Result<()> {
+    journal::record(conn, "deck.soft_delete", || {
+        let deck = get_by_id(conn, id)?;
+
+        if deck.deleted_at.is_some() {
+            return Err(JotDeckError::InvalidOperation(
+                "Deck is already deleted".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
+
+        // 表示中の Column に属する Card を連動削除
+        tx.execute(
+            "UPDATE cards SET deleted_at = ?1, deleted_with_deck = 1, updated_at = ?1
+             WHERE deleted_at IS NULL AND column_id IN (SELECT id FROM columns WHERE deck_id = ?2 AND deleted_at IS NULL)",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        // 表示中の Column を連動削除（position はそのまま残す）
+        tx.execute(
+            "UPDATE columns SET deleted_at = ?1, deleted_with_deck = 1, updated_at = ?1 WHERE deck_id = ?2 AND deleted_at IS NULL",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        // Deck を論理削除
+        tx.execute(
+            "UPDATE decks SET deleted_at = ?1, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        tx.commit()?;
+
+        Ok(())
+    })
+}
+
+/// Deck を復元する（連動削除された Column と Card も復元）
+pub fn restore(conn: &Connection, id: &str) -> Result<Deck> {
+    journal::record(conn, "deck.restore", || {
+        let deck = get_by_id(conn, id)?;
+
+        if deck.deleted_at.is_none() {
+            return Err(JotDeckError::InvalidOperation(
+                "Deck is not deleted".to_string(),
+            ));
+        }
+
+        let now = Utc::now();
+
+        let tx = db::savepoint(conn)?;
+
+        // 連動削除された Card を復元
+        tx.execute(
+            "UPDATE cards SET deleted_at = NULL, deleted_with_deck = 0, updated_at = ?1
+             WHERE deleted_with_deck = 1 AND column_id IN (SELECT id FROM columns WHERE deck_id = ?2)",
+            params![now.to_rfc3339(), id],
+        )?;
+        tag::resync_live_cards(
+            &tx,
+            "c.column_id IN (SELECT id FROM columns WHERE deck_id = ?1)",
+            params![id],
+        )?;
+
+        // 連動削除された Column を復元
+        tx.execute(
+            "UPDATE columns SET deleted_at = NULL, deleted_with_deck = 0, updated_at = ?1 WHERE deck_id = ?2 AND deleted_with_deck = 1",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        // Deck を復元
+        tx.execute(
+            "UPDATE decks SET deleted_at = NULL, updated_at = ?1 WHERE id = ?2",
+            params![now.to_rfc3339(), id],
+        )?;
+
+        tx.commit()?;
+
+        Ok(Deck {
+            deleted_at: None,
+            updated_at: now,
+            ..deck
+        })
     })
 }
 
+/// 削除済みの Deck 一覧を取得する（ゴミ箱表示用）
+pub fn get_deleted(conn: &Connection) -> Result<Vec<Deck>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, sort_order, created_at, updated_at, deleted_at FROM decks WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC",
+    )?;
+
+    let decks = stmt
+        .query_map([], row_to_deck)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(decks)
+}
+
 /// Deck を削除する（物理削除）
-/// 注意: 関連する Column と Card も削除される
+/// 注意: 関連する Column と Card も削除される。通常の削除操作には soft_delete を使うこと
+///
+/// 取り消せない操作なので記録しない。削除する行に触れた操作も記録から消し、削除した内容を残さない。
 pub fn delete(conn: &Connection, id: &str) -> Result<()> {
     // まず Deck が存在するか確認
     let _ = get_by_id(conn, id)?;
 
-    let tx = conn.unchecked_transaction()?;
+    journal::unrecorded(conn, || {
+        let tx = db::savepoint(conn)?;
 
-    // 関連する card_tags を削除
-    tx.execute(
-        "DELETE FROM card_tags WHERE card_id IN (SELECT id FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1))",
-        params![id],
-    )?;
+        // 削除する Deck / Column / Card に触れた操作を記録から消す
+        journal::forget(
+            &tx,
+            "SELECT ?1
+             UNION ALL SELECT id FROM columns WHERE deck_id = ?1
+             UNION ALL SELECT id FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1)",
+            params![id],
+        )?;
 
-    // 関連する Card を削除
-    tx.execute(
-        "DELETE FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1)",
-        params![id],
-    )?;
+        // 関連する card_tags を削除
+        tx.execute(
+            "DELETE FROM card_tags WHERE card_id IN (SELECT id FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1))",
+            params![id],
+        )?;
+
+        // 関連する Card の編集履歴を削除
+        tx.execute(
+            "DELETE FROM card_revisions WHERE card_id IN (SELECT id FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1))",
+            params![id],
+        )?;
+
+        // 関連する Card を削除
+        tx.execute(
+            "DELETE FROM cards WHERE column_id IN (SELECT id FROM columns WHERE deck_id = ?1)",
+            params![id],
+        )?;
 
-    // 関連する Column を削除
-    tx.execute("DELETE FROM columns WHERE deck_id = ?1", params![id])?;
+        // 関連する Column を削除
+        tx.execute("DELETE FROM columns WHERE deck_id = ?1", params![id])?;
 
-    // Deck を削除
-    tx.execute("DELETE FROM decks WHERE id = ?1", params![id])?;
+        // 削除スタックを削除
+        tx.execute("DELETE FROM delete_stack WHERE deck_id = ?1", params![id])?;
 
-    tx.commit()?;
+        // Deck を削除
+        tx.execute("DELETE FROM decks WHERE id = ?1", params![id])?;
 
-    Ok(())
+        tx.commit()?;
+
+        Ok(())
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::db::create_in_memory;
+    use crate::models::{NewCard, NewColumn};
+    use crate::repository::{card, column};
 
     #[test]
     fn test_create_deck() {
@@ -198,6 +345,9 @@ mod tests {
 
         assert_eq!(updated.name, "Updated Deck");
         assert_eq!(updated.sort_order, SortOrder::ScoreDesc);
+
+        let updated = update(&conn, &deck.id, None, Some(SortOrder::Manual)).unwrap();
+        assert_eq!(get_by_id(&conn, &updated.id).unwrap().sort_order, SortOrder::Manual);
     }
 
     #[test]
@@ -216,6 +366,62 @@ mod tests {
         assert!(matches!(result, Err(JotDeckError::NotFound(_))));
     }
 
+    #[test]
+    fn test_delete_deck_leaves_no_journal() {
+        let conn = create_in_memory().unwrap();
+        let kept = create(
+            &conn,
+            NewDeck {
+                name: "Kept".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let deck = create(
+            &conn,
+            NewDeck {
+                name: "Deleted".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: deck.id.clone(),
+                name: "Col".to_string(),
+            },
+        )
+        .unwrap();
+        let c = card::create(
+            &conn,
+            NewCard {
+                column_id: col.id,
+                content: "secret text".to_string(),
+            },
+        )
+        .unwrap();
+        card::update_content(&conn, &c.id, "secret text v2").unwrap();
+        update(&conn, &kept.id, Some("Renamed"), None).unwrap();
+
+        delete(&conn, &deck.id).unwrap();
+
+        // 削除した内容は操作の記録にも残らない
+        let leftovers: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM operation_changes WHERE old_row LIKE '%secret%' OR new_row LIKE '%secret%'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(leftovers, 0);
+
+        // 残った操作は取り消せる
+        let result = journal::undo(&conn).unwrap();
+        assert_eq!(result.applied[0].label, "deck.update");
+        assert_eq!(get_by_id(&conn, &kept.id).unwrap().name, "Kept");
+    }
+
     #[test]
     fn test_get_all_decks() {
         let conn = create_in_memory().unwrap();
@@ -241,4 +447,135 @@ mod tests {
         let decks = get_all(&conn).unwrap();
         assert_eq!(decks.len(), 2);
     }
+
+    #[test]
+    fn test_soft_delete_and_restore() {
+        use crate::models::{NewCard, NewColumn};
+        use crate::repository::{card, column};
+
+        let conn = create_in_memory().unwrap();
+        let deck = create(
+            &conn,
+            NewDeck {
+                name: "My Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col1 = column::create(
+            &conn,
+            NewColumn {
+                deck_id: deck.id.clone(),
+                name: "A".to_string(),
+            },
+        )
+        .unwrap();
+        let col2 = column::create(
+            &conn,
+            NewColumn {
+                deck_id: deck.id.clone(),
+                name: "B".to_string(),
+            },
+        )
+        .unwrap();
+        let card1 = card::create(
+            &conn,
+            NewCard {
+                column_id: col1.id.clone(),
+                content: "live".to_string(),
+            },
+        )
+        .unwrap();
+        let card2 = card::create(
+            &conn,
+            NewCard {
+                column_id: col1.id.clone(),
+                content: "trashed before".to_string(),
+            },
+        )
+        .unwrap();
+        card::soft_delete(&conn, &card2.id).unwrap();
+        column::soft_delete(&conn, &col2.id).unwrap();
+
+        soft_delete(&conn, &deck.id).unwrap();
+
+        assert!(get_by_id(&conn, &deck.id).unwrap().deleted_at.is_some());
+        assert!(get_all(&conn).unwrap().is_empty());
+        assert_eq!(get_deleted(&conn).unwrap().len(), 1);
+        assert!(column::get_by_deck_id(&conn, &deck.id).unwrap().is_empty());
+
+        let c1 = card::get_by_id(&conn, &card1.id).unwrap();
+        assert!(c1.deleted_at.is_some());
+        assert!(c1.deleted_with_deck);
+        assert!(!c1.deleted_with_column);
+
+        // 連動削除された Column / Card は単体では復元できない
+        assert!(matches!(
+            column::restore(&conn, &col1.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+        assert!(matches!(
+            card::restore(&conn, &card1.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+        // 削除済み Deck 内の項目も Deck を復元するまで復元できない
+        assert!(matches!(
+            card::restore(&conn, &card2.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+        assert!(matches!(
+            column::restore(&conn, &col2.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+        assert!(matches!(
+            update(&conn, &deck.id, Some("Renamed"), None),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+
+        let restored = restore(&conn, &deck.id).unwrap();
+        assert!(restored.deleted_at.is_none());
+        assert_eq!(get_all(&conn).unwrap().len(), 1);
+
+        // 連動削除された分だけが元の position のまま復元される
+        let columns = column::get_by_deck_id(&conn, &deck.id).unwrap();
+        assert_eq!(columns.len(), 1);
+        assert_eq!(columns[0].id, col1.id);
+        assert_eq!(columns[0].position, 0);
+
+        let cards = card::get_by_column_id(&conn, &col1.id).unwrap();
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].id, card1.id);
+        assert!(!cards[0].deleted_with_deck);
+
+        // 個別に削除していたものは引き続きゴミ箱にある
+        assert!(card::get_by_id(&conn, &card2.id).unwrap().deleted_at.is_some());
+        assert!(column::get_by_id(&conn, &col2.id).unwrap().deleted_at.is_some());
+        column::restore(&conn, &col2.id).unwrap();
+        card::restore(&conn, &card2.id).unwrap();
+    }
+
+    #[test]
+    fn test_soft_delete_twice() {
+        let conn = create_in_memory().unwrap();
+        let deck = create(
+            &conn,
+            NewDeck {
+                name: "My Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+
+        soft_delete(&conn, &deck.id).unwrap();
+        assert!(matches!(
+            soft_delete(&conn, &deck.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+
+        restore(&conn, &deck.id).unwrap();
+        assert!(matches!(
+            restore(&conn, &deck.id),
+            Err(JotDeckError::InvalidOperation(_))
+        ));
+    }
 }
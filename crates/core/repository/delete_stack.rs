@@ -0,0 +1,377 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::error::{JotDeckError, Result};
+use crate::models::{DeleteStackEntry, DeletedEntityType};
+use crate::repository::{card, column};
+
+/// RFC3339 文字列を DateTime<Utc> にパースする
+fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(
+                col_idx,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            )
+        })
+}
+
+pub(crate) fn row_to_entry(row: &rusqlite::Row) -> rusqlite::Result<DeleteStackEntry> {
+    let entity_type: String = row.get(2)?;
+
+    Ok(DeleteStackEntry {
+        id: row.get(0)?,
+        deck_id: row.get(1)?,
+        entity_type: DeletedEntityType::from_db_value(&entity_type).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                2,
+                rusqlite::types::Type::Text,
+                format!("Unknown entity type: {}", entity_type).into(),
+            )
+        })?,
+        entity_id: row.get(3)?,
+        deleted_at: parse_datetime(&row.get::<_, String>(4)?, 4)?,
+    })
+}
+
+/// 削除スタックに積む
+pub fn push(
+    conn: &Connection,
+    deck_id: &str,
+    entity_type: DeletedEntityType,
+    entity_id: &str,
+    deleted_at: DateTime<Utc>,
+) -> Result<DeleteStackEntry> {
+    conn.execute(
+        "INSERT INTO delete_stack (deck_id, entity_type, entity_id, deleted_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            deck_id,
+            entity_type.to_db_value(),
+            entity_id,
+            deleted_at.to_rfc3339(),
+        ],
+    )?;
+
+    Ok(DeleteStackEntry {
+        id: conn.last_insert_rowid(),
+        deck_id: deck_id.to_string(),
+        entity_type,
+        entity_id: entity_id.to_string(),
+        deleted_at,
+    })
+}
+
+/// エンティティのエントリを削除スタックから取り除く（復元時）
+pub fn remove(conn: &Connection, entity_type: DeletedEntityType, entity_id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM delete_stack WHERE entity_type = ?1 AND entity_id = ?2",
+        params![entity_type.to_db_value(), entity_id],
+    )?;
+    Ok(())
+}
+
+/// ID でエントリを取得する
+pub fn get_by_id(conn: &Connection, id: i64) -> Result<DeleteStackEntry> {
+    conn.query_row(
+        "SELECT id, deck_id, entity_type, entity_id, deleted_at FROM delete_stack WHERE id = ?1",
+        params![id],
+        row_to_entry,
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::QueryReturnedNoRows => {
+            JotDeckError::NotFound(format!("Delete stack entry not found: {}", id))
+        }
+        _ => JotDeckError::Database(e),
+    })
+}
+
+/// Deck の削除スタックを取得する（新しい順、ゴミ箱 UI 用）
+pub fn list_stack(conn: &Connection, deck_id: &str) -> Result<Vec<DeleteStackEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, deck_id, entity_type, entity_id, deleted_at FROM delete_stack WHERE deck_id = ?1 ORDER BY id DESC",
+    )?;
+
+    let entries = stmt
+        .query_map(params![deck_id], row_to_entry)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(entries)
+}
+
+/// Deck の削除スタックの先頭（直近の削除）を取得する
+pub fn peek(conn: &Connection, deck_id: &str) -> Result<Option<DeleteStackEntry>> {
+    let entry = conn
+        .query_row(
+            "SELECT id, deck_id, entity_type, entity_id, deleted_at FROM delete_stack WHERE deck_id = ?1 ORDER BY id DESC LIMIT 1",
+            params![deck_id],
+            row_to_entry,
+        )
+        .optional()?;
+
+    Ok(entry)
+}
+
+/// エントリが指す Card / Column を復元する（エントリは restore 側で取り除かれる）
+fn restore_entity(conn: &Connection, entry: &DeleteStackEntry) -> Result<()> {
+    match entry.entity_type {
+        DeletedEntityType::Card => card::restore(conn, &entry.entity_id).map(|_| ()),
+        DeletedEntityType::Column => column::restore(conn, &entry.entity_id).map(|_| ()),
+    }
+}
+
+/// 直近の削除を取り消す
+/// 復元したエントリを返す。復元できるエントリがない場合は None
+///
+/// 実体が物理削除されたエントリは取り除き、今は復元できないエントリ（別の経路で復元済み、
+/// 親の Column や Deck ごと削除されたものなど）は残したまま次のエントリに進む。
+pub fn undo_last(conn: &Connection, deck_id: &str) -> Result<Option<DeleteStackEntry>> {
+    for entry in list_stack(conn, deck_id)? {
+        match restore_entity(conn, &entry) {
+            Ok(()) => return Ok(Some(entry)),
+            Err(JotDeckError::NotFound(_)) => {
+                remove(conn, entry.entity_type, &entry.entity_id)?;
+            }
+            Err(JotDeckError::InvalidOperation(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(None)
+}
+
+/// 指定したエントリを復元する（ゴミ箱 UI から任意の項目を選択した場合）
+pub fn restore_entry(conn: &Connection, id: i64) -> Result<DeleteStackEntry> {
+    let entry = get_by_id(conn, id)?;
+    restore_entity(conn, &entry)?;
+    Ok(entry)
+}
+
+/// 実体が物理削除された、または既に復元済みのエントリを取り除く
+pub fn prune(conn: &Connection) -> Result<usize> {
+    let deleted = conn.execute(
+        "DELETE FROM delete_stack
+         WHERE (entity_type = 'card' AND NOT EXISTS (
+                    SELECT 1 FROM cards c WHERE c.id = delete_stack.entity_id AND c.deleted_at IS NOT NULL))
+            OR (entity_type = 'column' AND NOT EXISTS (
+                    SELECT 1 FROM columns col WHERE col.id = delete_stack.entity_id AND col.deleted_at IS NOT NULL))",
+        [],
+    )?;
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{create_file_db, create_in_memory};
+    use crate::models::{Card, NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::deck;
+
+    fn setup() -> (Connection, String, String) {
+        let conn = create_in_memory().unwrap();
+        let (deck_id, column_id) = create_deck(&conn);
+        (conn, deck_id, column_id)
+    }
+
+    fn create_deck(conn: &Connection) -> (String, String) {
+        let d = deck::create(
+            conn,
+            NewDeck {
+                name: "Test Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Test Column".to_string(),
+            },
+        )
+        .unwrap();
+        (d.id, col.id)
+    }
+
+    fn add_card(conn: &Connection, column_id: &str, content: &str) -> Card {
+        card::create(
+            conn,
+            NewCard {
+                column_id: column_id.to_string(),
+                content: content.to_string(),
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_soft_delete_pushes_entry() {
+        let (conn, deck_id, column_id) = setup();
+        let c = add_card(&conn, &column_id, "A");
+
+        card::soft_delete(&conn, &c.id).unwrap();
+        column::soft_delete(&conn, &column_id).unwrap();
+
+        let stack = list_stack(&conn, &deck_id).unwrap();
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack[0].entity_type, DeletedEntityType::Column);
+        assert_eq!(stack[0].entity_id, column_id);
+        assert_eq!(stack[1].entity_type, DeletedEntityType::Card);
+        assert_eq!(stack[1].entity_id, c.id);
+    }
+
+    #[test]
+    fn test_column_delete_does_not_push_cascaded_cards() {
+        let (conn, deck_id, column_id) = setup();
+        add_card(&conn, &column_id, "A");
+        add_card(&conn, &column_id, "B");
+
+        column::soft_delete(&conn, &column_id).unwrap();
+
+        let stack = list_stack(&conn, &deck_id).unwrap();
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack[0].entity_type, DeletedEntityType::Column);
+    }
+
+    #[test]
+    fn test_undo_last_in_lifo_order() {
+        let (conn, deck_id, column_id) = setup();
+        let a = add_card(&conn, &column_id, "A");
+        let b = add_card(&conn, &column_id, "B");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+        column::soft_delete(&conn, &column_id).unwrap();
+
+        let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+        assert_eq!(undone.entity_id, column_id);
+        assert!(column::get_by_id(&conn, &column_id).unwrap().deleted_at.is_none());
+
+        let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+        assert_eq!(undone.entity_id, b.id);
+
+        let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+        assert_eq!(undone.entity_id, a.id);
+
+        assert!(undo_last(&conn, &deck_id).unwrap().is_none());
+
+        let cards = card::get_by_column_id(&conn, &column_id).unwrap();
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].content, "A");
+        assert_eq!(cards[1].content, "B");
+    }
+
+    #[test]
+    fn test_stack_is_scoped_to_deck() {
+        let conn = create_in_memory().unwrap();
+        let (deck1, col1) = create_deck(&conn);
+        let (deck2, col2) = create_deck(&conn);
+        let a = add_card(&conn, &col1, "A");
+        let b = add_card(&conn, &col2, "B");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+
+        let undone = undo_last(&conn, &deck1).unwrap().unwrap();
+        assert_eq!(undone.entity_id, a.id);
+        assert!(undo_last(&conn, &deck1).unwrap().is_none());
+        assert_eq!(list_stack(&conn, &deck2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_direct_restore_removes_entry() {
+        let (conn, deck_id, column_id) = setup();
+        let a = add_card(&conn, &column_id, "A");
+        let b = add_card(&conn, &column_id, "B");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+
+        // ゴミ箱 UI から古い方を選んで復元
+        let entry = list_stack(&conn, &deck_id).unwrap().pop().unwrap();
+        let restored = restore_entry(&conn, entry.id).unwrap();
+        assert_eq!(restored.entity_id, a.id);
+
+        // Card を直接復元してもエントリが取り除かれる
+        card::restore(&conn, &b.id).unwrap();
+        assert!(list_stack(&conn, &deck_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo_skips_purged_entries() {
+        let (conn, deck_id, column_id) = setup();
+        let a = add_card(&conn, &column_id, "A");
+        let b = add_card(&conn, &column_id, "B");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+        conn.execute("DELETE FROM cards WHERE id = ?1", params![&b.id]).unwrap();
+
+        let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+        assert_eq!(undone.entity_id, a.id);
+        assert!(list_stack(&conn, &deck_id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_undo_skips_unrestorable_entries() {
+        let (conn, deck_id, column_id) = setup();
+        let a = add_card(&conn, &column_id, "A");
+        let b = add_card(&conn, &column_id, "B");
+        let c = add_card(&conn, &column_id, "C");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+        card::soft_delete(&conn, &c.id).unwrap();
+        // 削除スタックを通さずに復元された Card と、Column ごと削除された扱いの Card
+        conn.execute("UPDATE cards SET deleted_at = NULL WHERE id = ?1", params![&c.id]).unwrap();
+        conn.execute("UPDATE cards SET deleted_with_column = 1 WHERE id = ?1", params![&b.id]).unwrap();
+
+        let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+        assert_eq!(undone.entity_id, a.id);
+        assert!(undo_last(&conn, &deck_id).unwrap().is_none());
+
+        // 復元できなかったエントリは残り、prune で実体のないものが取り除かれる
+        assert_eq!(list_stack(&conn, &deck_id).unwrap().len(), 2);
+        assert_eq!(prune(&conn).unwrap(), 1);
+        assert_eq!(list_stack(&conn, &deck_id).unwrap()[0].entity_id, b.id);
+    }
+
+    #[test]
+    fn test_prune() {
+        let (conn, deck_id, column_id) = setup();
+        let a = add_card(&conn, &column_id, "A");
+        let b = add_card(&conn, &column_id, "B");
+
+        card::soft_delete(&conn, &a.id).unwrap();
+        card::soft_delete(&conn, &b.id).unwrap();
+        conn.execute("DELETE FROM cards WHERE id = ?1", params![&a.id]).unwrap();
+
+        assert_eq!(prune(&conn).unwrap(), 1);
+        let stack = list_stack(&conn, &deck_id).unwrap();
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack[0].entity_id, b.id);
+    }
+
+    #[test]
+    fn test_stack_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("jot-deck-stack-{}.db", ulid::Ulid::new()));
+        let path_str = path.to_str().unwrap();
+
+        let (deck_id, card_id) = {
+            let conn = create_file_db(path_str).unwrap();
+            let (deck_id, column_id) = create_deck(&conn);
+            let c = add_card(&conn, &column_id, "A");
+            card::soft_delete(&conn, &c.id).unwrap();
+            (deck_id, c.id)
+        };
+
+        {
+            let conn = create_file_db(path_str).unwrap();
+            let undone = undo_last(&conn, &deck_id).unwrap().unwrap();
+            assert_eq!(undone.entity_id, card_id);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,7 @@
 pub mod card;
 pub mod column;
 pub mod deck;
+pub mod delete_stack;
+pub mod search;
+pub mod settings;
 pub mod tag;
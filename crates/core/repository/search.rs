@@ -0,0 +1,333 @@
+use rusqlite::{params_from_iter, types::Value, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::models::Card;
+use crate::repository::card::{row_to_card, CARD_COLUMNS};
+
+/// スニペット中の一致箇所の開始マーカー（STX）
+pub const HIGHLIGHT_START: &str = "\u{2}";
+
+/// スニペット中の一致箇所の終了マーカー（ETX）
+pub const HIGHLIGHT_END: &str = "\u{3}";
+
+/// trigram トークナイザで索引を引ける最小文字数
+const TRIGRAM_MIN_CHARS: usize = 3;
+
+/// スニペットに含める最大トークン数
+const SNIPPET_TOKENS: i32 = 32;
+
+/// 検索結果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResult {
+    pub card: Card,
+    /// 一致箇所を HIGHLIGHT_START / HIGHLIGHT_END で囲んだ抜粋
+    pub snippet: String,
+    /// bm25 スコア（小さいほど関連度が高い）。索引を使えない短い語だけの検索では 0
+    pub rank: f64,
+}
+
+/// FTS5 のフレーズとしてクォートする
+fn to_fts_phrase(term: &str) -> String {
+    format!("\"{}\"", term.replace('"', "\"\""))
+}
+
+/// LIKE パターンとしてエスケープする（エスケープ文字は `\`）
+fn to_like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// 本文中の一致箇所をマーカーで囲む（LIKE と同じく ASCII のみ大文字小文字を区別しない）
+fn highlight(content: &str, terms: &[&str]) -> String {
+    let haystack = content.to_ascii_lowercase();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for term in terms {
+        let needle = term.to_ascii_lowercase();
+        if needle.is_empty() {
+            continue;
+        }
+        ranges.extend(
+            haystack
+                .match_indices(&needle)
+                .map(|(start, m)| (start, start + m.len())),
+        );
+    }
+
+    ranges.sort_unstable();
+
+    let mut result = String::with_capacity(content.len());
+    let mut cursor = 0;
+    for (start, end) in ranges {
+        // 重なった一致は先行する一致に含める
+        if start < cursor {
+            continue;
+        }
+        result.push_str(&content[cursor..start]);
+        result.push_str(HIGHLIGHT_START);
+        result.push_str(&content[start..end]);
+        result.push_str(HIGHLIGHT_END);
+        cursor = end;
+    }
+    result.push_str(&content[cursor..]);
+
+    result
+}
+
+/// Deck 内の Card を全文検索する（削除されていないもののみ）
+///
+/// クエリは空白区切りの AND 検索。3 文字以上の語は FTS5 の trigram 索引で検索して bm25 順に並べ、
+/// 索引を引けない 1〜2 文字の語（「会議」など）は本文の部分一致で絞り込む。
+pub fn search(conn: &Connection, deck_id: &str, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+    let terms: Vec<&str> = query.split_whitespace().collect();
+    if terms.is_empty() || limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let (long_terms, short_terms): (Vec<&str>, Vec<&str>) = terms
+        .iter()
+        .partition(|t| t.chars().count() >= TRIGRAM_MIN_CHARS);
+
+    let mut values: Vec<Value> = vec![Value::Text(deck_id.to_string())];
+    let mut sql = if long_terms.is_empty() {
+        format!(
+            "SELECT {}, NULL, 0.0
+             FROM cards c
+             JOIN columns col ON c.column_id = col.id
+             WHERE col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL",
+            CARD_COLUMNS
+        )
+    } else {
+        let match_expr = long_terms
+            .iter()
+            .map(|t| to_fts_phrase(t))
+            .collect::<Vec<_>>()
+            .join(" ");
+        values.push(Value::Text(match_expr));
+        values.push(Value::Text(HIGHLIGHT_START.to_string()));
+        values.push(Value::Text(HIGHLIGHT_END.to_string()));
+
+        format!(
+            "SELECT {},
+                    snippet(cards_fts, 0, ?3, ?4, '…', {}), bm25(cards_fts)
+             FROM cards_fts
+             JOIN cards c ON c.rowid = cards_fts.rowid
+             JOIN columns col ON c.column_id = col.id
+             WHERE cards_fts MATCH ?2 AND col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL",
+            CARD_COLUMNS, SNIPPET_TOKENS
+        )
+    };
+
+    for term in &short_terms {
+        values.push(Value::Text(to_like_pattern(term)));
+        sql.push_str(&format!(" AND c.content LIKE ?{} ESCAPE '\\'", values.len()));
+    }
+
+    if long_terms.is_empty() {
+        sql.push_str(" ORDER BY c.updated_at DESC");
+    } else {
+        sql.push_str(" ORDER BY bm25(cards_fts)");
+    }
+    values.push(Value::Integer(limit as i64));
+    sql.push_str(&format!(" LIMIT ?{}", values.len()));
+
+    let mut stmt = conn.prepare(&sql)?;
+    let results = stmt
+        .query_map(params_from_iter(values.iter()), |row| {
+            let card = row_to_card(row)?;
+            let snippet: Option<String> = row.get(11)?;
+            let rank: f64 = row.get(12)?;
+            Ok(SearchResult {
+                snippet: snippet.unwrap_or_else(|| highlight(&card.content, &short_terms)),
+                card,
+                rank,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_in_memory;
+    use crate::models::{NewCard, NewColumn, NewDeck, SortOrder};
+    use crate::repository::{card, column, deck};
+
+    fn setup() -> (Connection, String, String) {
+        let conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Test Deck".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Test Column".to_string(),
+            },
+        )
+        .unwrap();
+        (conn, d.id, col.id)
+    }
+
+    fn add_card(conn: &Connection, column_id: &str, content: &str) -> Card {
+        card::create(
+            conn,
+            NewCard {
+                column_id: column_id.to_string(),
+                content: content.to_string(),
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_search_basic() {
+        let (conn, deck_id, column_id) = setup();
+        let c = add_card(&conn, &column_id, "Write the release notes");
+        add_card(&conn, &column_id, "Buy groceries");
+
+        let results = search(&conn, &deck_id, "release", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].card.id, c.id);
+        assert!(results[0]
+            .snippet
+            .contains(&format!("{}release{}", HIGHLIGHT_START, HIGHLIGHT_END)));
+    }
+
+    #[test]
+    fn test_search_ranking() {
+        let (conn, deck_id, column_id) = setup();
+        let weak = add_card(&conn, &column_id, "rust is a language used for many different kinds of things");
+        let strong = add_card(&conn, &column_id, "rust rust rust");
+
+        let results = search(&conn, &deck_id, "rust", 10).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].card.id, strong.id);
+        assert_eq!(results[1].card.id, weak.id);
+        assert!(results[0].rank <= results[1].rank);
+    }
+
+    #[test]
+    fn test_search_japanese() {
+        let (conn, deck_id, column_id) = setup();
+        let c1 = add_card(&conn, &column_id, "新しいアイデアを考える");
+        let c2 = add_card(&conn, &column_id, "明日の会議の準備");
+
+        let results = search(&conn, &deck_id, "アイデア", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].card.id, c1.id);
+
+        // 2 文字の語は部分一致にフォールバックする
+        let results = search(&conn, &deck_id, "会議", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].card.id, c2.id);
+        assert_eq!(
+            results[0].snippet,
+            format!("明日の{}会議{}の準備", HIGHLIGHT_START, HIGHLIGHT_END)
+        );
+    }
+
+    #[test]
+    fn test_search_multiple_terms() {
+        let (conn, deck_id, column_id) = setup();
+        let both = add_card(&conn, &column_id, "meeting notes for the UI review");
+        add_card(&conn, &column_id, "meeting with the bank");
+
+        let results = search(&conn, &deck_id, "meeting UI", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].card.id, both.id);
+    }
+
+    #[test]
+    fn test_search_skips_deleted() {
+        let (conn, deck_id, column_id) = setup();
+        let c = add_card(&conn, &column_id, "deleted idea");
+
+        card::soft_delete(&conn, &c.id).unwrap();
+        assert!(search(&conn, &deck_id, "idea", 10).unwrap().is_empty());
+
+        card::restore(&conn, &c.id).unwrap();
+        assert_eq!(search(&conn, &deck_id, "idea", 10).unwrap().len(), 1);
+
+        column::soft_delete(&conn, &column_id).unwrap();
+        assert!(search(&conn, &deck_id, "idea", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_follows_updates() {
+        let (conn, deck_id, column_id) = setup();
+        let c = add_card(&conn, &column_id, "draft");
+
+        card::update_content(&conn, &c.id, "final version").unwrap();
+
+        assert!(search(&conn, &deck_id, "draft", 10).unwrap().is_empty());
+        assert_eq!(search(&conn, &deck_id, "final", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_search_scoped_to_deck() {
+        let (conn, deck_id, column_id) = setup();
+        add_card(&conn, &column_id, "shared keyword");
+
+        let other = deck::create(
+            &conn,
+            NewDeck {
+                name: "Other".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(search(&conn, &deck_id, "keyword", 10).unwrap().len(), 1);
+        assert!(search(&conn, &other.id, "keyword", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_escapes_query() {
+        let (conn, deck_id, column_id) = setup();
+        add_card(&conn, &column_id, "say \"hello\" AND goodbye");
+        add_card(&conn, &column_id, "100% done");
+
+        // FTS5 の構文として解釈されない
+        assert_eq!(search(&conn, &deck_id, "\"hello\"", 10).unwrap().len(), 1);
+        assert_eq!(search(&conn, &deck_id, "AND", 10).unwrap().len(), 1);
+        // LIKE のワイルドカードとして解釈されない
+        assert_eq!(search(&conn, &deck_id, "0%", 10).unwrap().len(), 1);
+        assert!(search(&conn, &deck_id, "_", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_limit_and_empty_query() {
+        let (conn, deck_id, column_id) = setup();
+        for _ in 0..5 {
+            add_card(&conn, &column_id, "repeat");
+        }
+
+        assert_eq!(search(&conn, &deck_id, "repeat", 3).unwrap().len(), 3);
+        assert!(search(&conn, &deck_id, "   ", 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("Go go GO", &["go"]),
+            format!(
+                "{s}Go{e} {s}go{e} {s}GO{e}",
+                s = HIGHLIGHT_START,
+                e = HIGHLIGHT_END
+            )
+        );
+        assert_eq!(highlight("abc", &["x"]), "abc");
+    }
+}
@@ -0,0 +1,60 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// 設定値を取得する（未設定なら None）
+///
+/// 値は JSON で保存する。設定はユーザーの編集ではないのでジャーナルには記録しない。
+pub fn get<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match value {
+        Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+        None => Ok(None),
+    }
+}
+
+/// 設定値を保存する（既にあれば上書き）
+pub fn set<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<()> {
+    conn.execute(
+        "INSERT INTO settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+/// 設定値を削除する（既定値に戻す）
+pub fn remove(conn: &Connection, key: &str) -> Result<()> {
+    conn.execute("DELETE FROM settings WHERE key = ?1", params![key])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::create_in_memory;
+
+    #[test]
+    fn test_get_set_remove() {
+        let conn = create_in_memory().unwrap();
+        assert_eq!(get::<Vec<i32>>(&conn, "numbers").unwrap(), None);
+
+        set(&conn, "numbers", &vec![1, 2]).unwrap();
+        set(&conn, "numbers", &vec![3]).unwrap();
+        assert_eq!(get::<Vec<i32>>(&conn, "numbers").unwrap(), Some(vec![3]));
+
+        // 型が合わない値はエラーになる
+        assert!(get::<String>(&conn, "numbers").is_err());
+
+        remove(&conn, "numbers").unwrap();
+        assert_eq!(get::<Vec<i32>>(&conn, "numbers").unwrap(), None);
+    }
+}
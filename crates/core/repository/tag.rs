@@ -1,14 +1,34 @@
+use chrono::{DateTime, Duration, Utc};
 use regex::Regex;
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Params};
+use serde::{Deserialize, Serialize};
 use ulid::Ulid;
 
-use crate::error::Result;
-use crate::models::Tag;
+use crate::db;
+use crate::error::{JotDeckError, Result};
+use crate::journal;
+use crate::models::{Card, Tag};
+use crate::repository::card::{self, row_to_card, CARD_COLUMNS};
+use crate::tag_query;
+
+/// RFC3339 文字列を DateTime<Utc> にパースする
+fn parse_datetime(s: &str, col_idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    chrono::DateTime::parse_from_rfc3339(s)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(
+                col_idx,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            )
+        })
+}
 
 /// タグ抽出のための正規表現
-/// パターン: # + 英数字・アンダースコア・日本語（ひらがな・カタカナ・漢字）
+/// パターン: # + 英数字・アンダースコア・日本語（ひらがな・カタカナ・漢字）を `/` で区切ったもの
+/// 末尾の `/` はタグに含めない。packages/app/src/lib/types.ts の TAG_PATTERN と同期すること
 fn get_tag_regex() -> Regex {
-    Regex::new(r"#([\w\u3040-\u309f\u30a0-\u30ff\u4e00-\u9faf]+)").unwrap()
+    Regex::new(r"#((?:[\w\u3040-\u309f\u30a0-\u30ff\u4e00-\u9faf]+/)*[\w\u3040-\u309f\u30a0-\u30ff\u4e00-\u9faf]+)").unwrap()
 }
 
 /// テキストからタグを抽出する
@@ -19,19 +39,28 @@ pub fn extract_tags(content: &str) -> Vec<String> {
         .collect()
 }
 
+pub(crate) fn row_to_tag(row: &rusqlite::Row) -> rusqlite::Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        parent_id: row.get(2)?,
+    })
+}
+
+/// 親タグの名前（`a/b/c` に対する `a/b`）
+pub fn parent_name(name: &str) -> Option<&str> {
+    name.rsplit_once('/').map(|(parent, _)| parent)
+}
+
 /// タグを取得または作成する
+/// `a/b/c` のような階層タグは、存在しない祖先タグ（`a`, `a/b`）も作成する
 pub fn get_or_create(conn: &Connection, name: &str) -> Result<Tag> {
     // まず既存のタグを検索
     let existing: Option<Tag> = conn
         .query_row(
-            "SELECT id, name FROM tags WHERE name = ?1",
+            "SELECT id, name, parent_id FROM tags WHERE name = ?1",
             params![name],
-            |row| {
-                Ok(Tag {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            },
+            row_to_tag,
         )
         .ok();
 
@@ -39,16 +68,22 @@ pub fn get_or_create(conn: &Connection, name: &str) -> Result<Tag> {
         return Ok(tag);
     }
 
+    let parent_id = match parent_name(name) {
+        Some(parent) => Some(get_or_create(conn, parent)?.id),
+        None => None,
+    };
+
     // 存在しなければ作成
     let id = Ulid::new().to_string();
     conn.execute(
-        "INSERT INTO tags (id, name) VALUES (?1, ?2)",
-        params![&id, name],
+        "INSERT INTO tags (id, name, parent_id) VALUES (?1, ?2, ?3)",
+        params![&id, name, &parent_id],
     )?;
 
     Ok(Tag {
         id,
         name: name.to_string(),
+        parent_id,
     })
 }
 
@@ -115,16 +150,11 @@ pub fn sync_card_tags(conn: &Connection, card_id: &str, content: &str) -> Result
 /// Card に関連付けられたタグを取得する
 pub fn get_tags_by_card(conn: &Connection, card_id: &str) -> Result<Vec<Tag>> {
     let mut stmt = conn.prepare(
-        "SELECT t.id, t.name FROM tags t JOIN card_tags ct ON t.id = ct.tag_id WHERE ct.card_id = ?1 ORDER BY t.name",
+        "SELECT t.id, t.name, t.parent_id FROM tags t JOIN card_tags ct ON t.id = ct.tag_id WHERE ct.card_id = ?1 ORDER BY t.name",
     )?;
 
     let tags = stmt
-        .query_map(params![card_id], |row| {
-            Ok(Tag {
-                id: row.get(0)?,
-                name: row.get(1)?,
-            })
-        })?
+        .query_map(params![card_id], row_to_tag)?
         .collect::<rusqlite::Result<Vec<_>>>()?;
 
     Ok(tags)
@@ -133,7 +163,7 @@ pub fn get_tags_by_card(conn: &Connection, card_id: &str) -> Result<Vec<Tag>> {
 /// Deck 内で使用されているタグを取得する
 pub fn get_tags_by_deck(conn: &Connection, deck_id: &str) -> Result<Vec<Tag>> {
     let mut stmt = conn.prepare(
-        "SELECT DISTINCT t.id, t.name
+        "SELECT DISTINCT t.id, t.name, t.parent_id
          FROM tags t
          JOIN card_tags ct ON t.id = ct.tag_id
          JOIN cards c ON ct.card_id = c.id
@@ -143,27 +173,144 @@ pub fn get_tags_by_deck(conn: &Connection, deck_id: &str) -> Result<Vec<Tag>> {
     )?;
 
     let tags = stmt
-        .query_map(params![deck_id], |row| {
-            Ok(Tag {
-                id: row.get(0)?,
-                name: row.get(1)?,
-            })
-        })?
+        .query_map(params![deck_id], row_to_tag)?
         .collect::<rusqlite::Result<Vec<_>>>()?;
 
     Ok(tags)
 }
 
-/// タグ名で Card を検索する
-pub fn get_cards_by_tag(conn: &Connection, deck_id: &str, tag_name: &str) -> Result<Vec<String>> {
+/// 削除されていない Card で使われているタグをすべて取得する（Deck をまたぐ）
+///
+/// `tag_usage` の使用数から引くので、ゴミ箱の Card にしか付いていないタグは含まない。
+/// 使われているタグの祖先は、直接使われていなくても含める。
+pub fn get_all_live(conn: &Connection) -> Result<Vec<Tag>> {
     let mut stmt = conn.prepare(
-        "SELECT c.id
+        "WITH RECURSIVE live(id) AS (
+             SELECT tag_id FROM tag_usage
+             UNION
+             SELECT t.parent_id FROM tags t JOIN live ON t.id = live.id WHERE t.parent_id IS NOT NULL
+         )
+         SELECT t.id, t.name, t.parent_id FROM tags t WHERE t.id IN (SELECT id FROM live) ORDER BY t.name",
+    )?;
+
+    let tags = stmt
+        .query_map([], row_to_tag)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(tags)
+}
+
+/// タグを使っている削除されていない Card の数（Deck をまたぐ。子孫タグは数えない）
+pub fn live_card_count(conn: &Connection, tag_id: &str) -> Result<i64> {
+    let count = conn
+        .query_row(
+            "SELECT live_cards FROM tag_usage WHERE tag_id = ?1",
+            params![tag_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(count.unwrap_or(0))
+}
+
+/// タグごとの削除されていない Card の数（`tag_usage` にあるべき内容）
+pub(crate) const LIVE_USAGE_SQL: &str = "SELECT ct.tag_id, COUNT(*) FROM card_tags ct
+     JOIN cards c ON c.id = ct.card_id
+     WHERE c.deleted_at IS NULL
+     GROUP BY ct.tag_id";
+
+/// `tag_usage` を数え直す
+pub(crate) fn rebuild_usage(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM tag_usage", [])?;
+    conn.execute(
+        &format!("INSERT INTO tag_usage (tag_id, live_cards) {}", LIVE_USAGE_SQL),
+        [],
+    )?;
+    Ok(())
+}
+
+/// 削除されていない Card のタグを本文から付け直す（`condition` は `cards c` に対する条件）
+///
+/// ゴミ箱の Card にしか使われていないタグは、クリーンアップで関連ごと削除されることがある。
+/// 復元した Card のタグはこれで元に戻す。関連が揃っていれば何も書き換えない。
+pub(crate) fn resync_live_cards(conn: &Connection, condition: &str, params: impl Params) -> Result<()> {
+    let cards = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT c.id, c.content FROM cards c WHERE c.deleted_at IS NULL AND {}",
+            condition
+        ))?;
+        let cards = stmt
+            .query_map(params, |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        cards
+    };
+
+    for (id, content) in &cards {
+        sync_card_tags(conn, id, content)?;
+    }
+    Ok(())
+}
+
+/// タグの階層構造
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagNode {
+    pub tag: Tag,
+    /// 子タグ（名前順）
+    pub children: Vec<TagNode>,
+}
+
+/// Deck 内で使用されているタグを階層構造で取得する
+///
+/// 使われているタグの祖先は、直接使われていなくても含める（`a/b` だけが使われていれば `a` → `a/b`）。
+pub fn get_tag_tree_by_deck(conn: &Connection, deck_id: &str) -> Result<Vec<TagNode>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE tree(id) AS (
+             SELECT ct.tag_id
+             FROM card_tags ct
+             JOIN cards c ON ct.card_id = c.id
+             JOIN columns col ON c.column_id = col.id
+             WHERE col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL
+             UNION
+             SELECT t.parent_id FROM tags t JOIN tree ON t.id = tree.id WHERE t.parent_id IS NOT NULL
+         )
+         SELECT t.id, t.name, t.parent_id FROM tags t WHERE t.id IN (SELECT id FROM tree) ORDER BY t.name",
+    )?;
+
+    let tags = stmt
+        .query_map(params![deck_id], row_to_tag)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    fn build(parent_id: Option<&str>, tags: &[Tag]) -> Vec<TagNode> {
+        tags.iter()
+            .filter(|t| t.parent_id.as_deref() == parent_id)
+            .map(|t| TagNode {
+                tag: t.clone(),
+                children: build(Some(&t.id), tags),
+            })
+            .collect()
+    }
+
+    Ok(build(None, &tags))
+}
+
+/// `t.name` がタグ自身かその子孫（`a` に対する `a/b`, `a/b/c`）であることを表す SQL の条件式
+pub(crate) fn tag_or_descendant_sql(placeholder: &str) -> String {
+    format!(
+        "(t.name = {p} OR substr(t.name, 1, length({p}) + 1) = {p} || '/')",
+        p = placeholder
+    )
+}
+
+/// タグ名で Card を検索する（子孫タグを含む: `project` で `project/alpha` も一致する）
+pub fn get_cards_by_tag(conn: &Connection, deck_id: &str, tag_name: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT c.id
          FROM cards c
          JOIN card_tags ct ON c.id = ct.card_id
          JOIN tags t ON ct.tag_id = t.id
          JOIN columns col ON c.column_id = col.id
-         WHERE col.deck_id = ?1 AND t.name = ?2 AND c.deleted_at IS NULL AND col.deleted_at IS NULL",
-    )?;
+         WHERE col.deck_id = ?1 AND {} AND c.deleted_at IS NULL AND col.deleted_at IS NULL",
+        tag_or_descendant_sql("?2")
+    ))?;
 
     let card_ids = stmt
         .query_map(params![deck_id, tag_name], |row| row.get(0))?
@@ -172,30 +319,301 @@ pub fn get_cards_by_tag(conn: &Connection, deck_id: &str, tag_name: &str) -> Res
     Ok(card_ids)
 }
 
-/// タグ名の補完候補を取得する
+/// タグの論理式（`idea & !done | urgent` など）に一致する Card を取得する（削除されていないもののみ）
+///
+/// 式の書式は [`tag_query::parse`] を参照。Column の並び順、Column 内は Card の並び順に並べる。
+pub fn query_cards(conn: &Connection, deck_id: &str, expr: &str) -> Result<Vec<Card>> {
+    let expr = tag_query::parse(expr)?;
+
+    let mut values = vec![Value::Text(deck_id.to_string())];
+    let condition = expr.to_sql(&mut values);
+    let sql = format!(
+        "SELECT {}
+         FROM cards c
+         JOIN columns col ON c.column_id = col.id
+         WHERE col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL AND {}
+         ORDER BY col.rank ASC, c.rank ASC",
+        CARD_COLUMNS, condition
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let cards = stmt
+        .query_map(params_from_iter(values.iter()), row_to_card)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(cards)
+}
+
+/// タグ補完候補の並び順
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionOrder {
+    /// 名前順（デフォルト）
+    #[default]
+    Alphabetical,
+    /// 使用している Card の多い順
+    Frequency,
+}
+
+/// タグ名の補完候補を取得する（名前順）
 pub fn get_tag_suggestions(conn: &Connection, deck_id: &str, prefix: &str) -> Result<Vec<Tag>> {
+    get_tag_suggestions_ordered(conn, deck_id, prefix, SuggestionOrder::Alphabetical)
+}
+
+/// タグ名の補完候補を指定した順で取得する
+pub fn get_tag_suggestions_ordered(
+    conn: &Connection,
+    deck_id: &str,
+    prefix: &str,
+    order: SuggestionOrder,
+) -> Result<Vec<Tag>> {
     let pattern = format!("{}%", prefix);
-    let mut stmt = conn.prepare(
-        "SELECT DISTINCT t.id, t.name
+    let order_by = match order {
+        SuggestionOrder::Alphabetical => "t.name",
+        SuggestionOrder::Frequency => "COUNT(DISTINCT c.id) DESC, t.name",
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT t.id, t.name, t.parent_id
          FROM tags t
          JOIN card_tags ct ON t.id = ct.tag_id
          JOIN cards c ON ct.card_id = c.id
          JOIN columns col ON c.column_id = col.id
          WHERE col.deck_id = ?1 AND t.name LIKE ?2 AND c.deleted_at IS NULL AND col.deleted_at IS NULL
-         ORDER BY t.name
+         GROUP BY t.id
+         ORDER BY {}
          LIMIT 10",
+        order_by
+    ))?;
+
+    let tags = stmt
+        .query_map(params![deck_id, &pattern], row_to_tag)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(tags)
+}
+
+/// タグの使用状況
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagUsage {
+    pub tag: Tag,
+    /// タグを含む Card の数
+    pub card_count: i64,
+    /// タグを含む Card のスコアの合計
+    pub total_score: i64,
+    /// タグを含む Card のうち最も古い作成日時
+    pub first_used_at: DateTime<Utc>,
+    /// タグを含む Card のうち最も新しい更新日時
+    pub last_used_at: DateTime<Utc>,
+}
+
+/// 同じ Card に付いているタグの組（tag_a < tag_b の名前順）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagCooccurrence {
+    pub tag_a: String,
+    pub tag_b: String,
+    /// 両方のタグを含む Card の数
+    pub count: i64,
+}
+
+/// Deck のタグ統計
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagStats {
+    /// 使用している Card の多い順
+    pub tags: Vec<TagUsage>,
+    /// 共起行列の 0 でない要素（多い順）
+    pub cooccurrences: Vec<TagCooccurrence>,
+}
+
+/// Deck 内のタグの使用状況と共起を集計する（削除されていない Card のみ）
+pub fn stats(conn: &Connection, deck_id: &str) -> Result<TagStats> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.name, t.parent_id, COUNT(DISTINCT c.id), SUM(c.score), MIN(c.created_at), MAX(c.updated_at)
+         FROM tags t
+         JOIN card_tags ct ON t.id = ct.tag_id
+         JOIN cards c ON ct.card_id = c.id
+         JOIN columns col ON c.column_id = col.id
+         WHERE col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL
+         GROUP BY t.id
+         ORDER BY COUNT(DISTINCT c.id) DESC, t.name",
     )?;
 
     let tags = stmt
-        .query_map(params![deck_id, &pattern], |row| {
-            Ok(Tag {
-                id: row.get(0)?,
-                name: row.get(1)?,
+        .query_map(params![deck_id], |row| {
+            Ok(TagUsage {
+                tag: row_to_tag(row)?,
+                card_count: row.get(3)?,
+                total_score: row.get(4)?,
+                first_used_at: parse_datetime(&row.get::<_, String>(5)?, 5)?,
+                last_used_at: parse_datetime(&row.get::<_, String>(6)?, 6)?,
             })
         })?
         .collect::<rusqlite::Result<Vec<_>>>()?;
 
-    Ok(tags)
+    let mut stmt = conn.prepare(
+        "SELECT ta.name, tb.name, COUNT(*)
+         FROM card_tags a
+         JOIN card_tags b ON a.card_id = b.card_id
+         JOIN tags ta ON a.tag_id = ta.id
+         JOIN tags tb ON b.tag_id = tb.id
+         JOIN cards c ON a.card_id = c.id
+         JOIN columns col ON c.column_id = col.id
+         WHERE col.deck_id = ?1 AND c.deleted_at IS NULL AND col.deleted_at IS NULL AND ta.name < tb.name
+         GROUP BY ta.name, tb.name
+         ORDER BY COUNT(*) DESC, ta.name, tb.name",
+    )?;
+
+    let cooccurrences = stmt
+        .query_map(params![deck_id], |row| {
+            Ok(TagCooccurrence {
+                tag_a: row.get(0)?,
+                tag_b: row.get(1)?,
+                count: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(TagStats { tags, cooccurrences })
+}
+
+/// タグの書き換えで変更される（された）Card
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagRewrite {
+    pub card_id: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// 本文中の `#from` を `#into` に置き換える
+///
+/// 子孫タグは階層ごと移す（`#from/x` は `#into/x`）。`#from` で始まるだけの別のタグには触れない。
+fn replace_tag(content: &str, from: &str, into: &str) -> String {
+    let re = get_tag_regex();
+    let mut result = String::with_capacity(content.len());
+    let mut cursor = 0;
+
+    for cap in re.captures_iter(content) {
+        let name = &cap[1];
+        let rest = match name.strip_prefix(from) {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+            _ => continue,
+        };
+        let m = cap.get(0).unwrap();
+        result.push_str(&content[cursor..m.start()]);
+        result.push('#');
+        result.push_str(into);
+        result.push_str(rest);
+        cursor = m.end();
+    }
+    result.push_str(&content[cursor..]);
+
+    result
+}
+
+/// タグ名として使える文字列か（`#name` から name がそのまま抽出できるか）
+fn is_valid_name(name: &str) -> bool {
+    extract_tags(&format!("#{}", name)) == [name]
+}
+
+/// Deck 内で `#from` を含む Card を `#into` に書き換える
+///
+/// ゴミ箱の Card も対象にする（復元したときに古いタグが戻らないように）。
+/// dry_run のときは変更内容を返すだけで書き込まない。
+fn rewrite_tag(conn: &Connection, deck_id: &str, from: &str, into: &str, dry_run: bool) -> Result<Vec<TagRewrite>> {
+    if from == into {
+        return Err(JotDeckError::InvalidOperation(
+            "Source and target tags are the same".to_string(),
+        ));
+    }
+    if !is_valid_name(into) {
+        return Err(JotDeckError::InvalidOperation(format!(
+            "Invalid tag name: {}",
+            into
+        )));
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT c.id, c.content
+         FROM cards c
+         JOIN card_tags ct ON c.id = ct.card_id
+         JOIN tags t ON ct.tag_id = t.id
+         JOIN columns col ON c.column_id = col.id
+         WHERE col.deck_id = ?1 AND {}
+         ORDER BY c.id",
+        tag_or_descendant_sql("?2")
+    ))?;
+    let targets = stmt
+        .query_map(params![deck_id, from], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    if targets.is_empty() {
+        return Err(JotDeckError::NotFound(format!("Tag not found in deck: {}", from)));
+    }
+
+    let rewrites: Vec<TagRewrite> = targets
+        .into_iter()
+        .map(|(card_id, before)| TagRewrite {
+            after: replace_tag(&before, from, into),
+            card_id,
+            before,
+        })
+        .collect();
+
+    if dry_run {
+        return Ok(rewrites);
+    }
+
+    let tx = db::savepoint(conn)?;
+    let now = Utc::now();
+
+    for rewrite in &rewrites {
+        card::save_revision(&tx, &rewrite.card_id, &rewrite.before, &rewrite.after, now, Duration::zero())?;
+        tx.execute(
+            "UPDATE cards SET content = ?1, updated_at = ?2 WHERE id = ?3",
+            params![&rewrite.after, now.to_rfc3339(), &rewrite.card_id],
+        )?;
+        sync_card_tags(&tx, &rewrite.card_id, &rewrite.after)?;
+    }
+
+    tx.commit()?;
+
+    Ok(rewrites)
+}
+
+/// Deck 内のタグ名を変更する
+///
+/// 変更先のタグが Deck 内で（ゴミ箱の Card も含めて）既に使われている場合はエラー（merge を使う）。
+/// 変更先の子孫タグだけが使われている場合は変更できる。
+pub fn rename(conn: &Connection, deck_id: &str, old: &str, new: &str, dry_run: bool) -> Result<Vec<TagRewrite>> {
+    journal::record(conn, "tag.rename", || {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(
+                 SELECT 1
+                 FROM card_tags ct
+                 JOIN tags t ON ct.tag_id = t.id
+                 JOIN cards c ON ct.card_id = c.id
+                 JOIN columns col ON c.column_id = col.id
+                 WHERE col.deck_id = ?1 AND t.name = ?2)",
+            params![deck_id, new],
+            |row| row.get(0),
+        )?;
+        if exists {
+            return Err(JotDeckError::InvalidOperation(format!(
+                "Tag already exists in deck: {} (use merge instead)",
+                new
+            )));
+        }
+
+        rewrite_tag(conn, deck_id, old, new, dry_run)
+    })
+}
+
+/// Deck 内のタグ `from` を `into` に統合する
+pub fn merge(conn: &Connection, deck_id: &str, from: &str, into: &str, dry_run: bool) -> Result<Vec<TagRewrite>> {
+    journal::record(conn, "tag.merge", || {
+        rewrite_tag(conn, deck_id, from, into, dry_run)
+    })
 }
 
 #[cfg(test)]
@@ -345,4 +763,351 @@ mod tests {
         assert!(names.contains(&"project".to_string()));
         assert!(names.contains(&"proposal".to_string()));
     }
+
+    fn setup_tagged(contents: &[&str]) -> (Connection, String, Vec<String>) {
+        let conn = create_in_memory().unwrap();
+        let d = deck::create(
+            &conn,
+            NewDeck {
+                name: "Test".to_string(),
+                sort_order: SortOrder::default(),
+            },
+        )
+        .unwrap();
+        let col = column::create(
+            &conn,
+            NewColumn {
+                deck_id: d.id.clone(),
+                name: "Col".to_string(),
+            },
+        )
+        .unwrap();
+        let ids = contents
+            .iter()
+            .map(|content| {
+                card::create(
+                    &conn,
+                    NewCard {
+                        column_id: col.id.clone(),
+                        content: content.to_string(),
+                    },
+                )
+                .unwrap()
+                .id
+            })
+            .collect();
+        (conn, d.id, ids)
+    }
+
+    #[test]
+    fn test_replace_tag() {
+        assert_eq!(replace_tag("#todo and #todolist #todo", "todo", "task"), "#task and #todolist #task");
+        assert_eq!(replace_tag("no tags", "todo", "task"), "no tags");
+        assert_eq!(replace_tag("#メモ です", "メモ", "ノート"), "#ノート です");
+    }
+
+    #[test]
+    fn test_rename() {
+        let (conn, deck_id, ids) = setup_tagged(&["write #todo", "#todolist stays", "#todo #urgent"]);
+        let before = card::get_by_id(&conn, &ids[0]).unwrap();
+
+        // dry-run は何も書き換えない
+        let preview = rename(&conn, &deck_id, "todo", "task", true).unwrap();
+        assert_eq!(preview.len(), 2);
+        assert_eq!(preview[0].after, "write #task");
+        assert_eq!(card::get_by_id(&conn, &ids[0]).unwrap().content, "write #todo");
+
+        let rewrites = rename(&conn, &deck_id, "todo", "task", false).unwrap();
+        assert_eq!(rewrites.len(), 2);
+
+        let after = card::get_by_id(&conn, &ids[0]).unwrap();
+        assert_eq!(after.content, "write #task");
+        assert!(after.updated_at >= before.updated_at);
+        assert_eq!(card::get_by_id(&conn, &ids[1]).unwrap().content, "#todolist stays");
+        assert_eq!(card::get_by_id(&conn, &ids[2]).unwrap().content, "#task #urgent");
+
+        assert!(get_cards_by_tag(&conn, &deck_id, "todo").unwrap().is_empty());
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "task").unwrap().len(), 2);
+
+        // 書き換える前の本文は履歴に残る
+        let history = card::history(&conn, &ids[0]).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content, "write #todo");
+        assert!(card::history(&conn, &ids[1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let (conn, deck_id, _) = setup_tagged(&["#todo", "#task"]);
+
+        let result = rename(&conn, &deck_id, "todo", "task", false);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        let result = rename(&conn, &deck_id, "todo", "bad name", false);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        let result = rename(&conn, &deck_id, "missing", "other", false);
+        assert!(matches!(result, Err(JotDeckError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_rename_conflict_check() {
+        let (conn, deck_id, ids) = setup_tagged(&["#a", "#b/x", "#c", "#d"]);
+
+        // 子孫タグだけが使われている名前には変更できる
+        rename(&conn, &deck_id, "a", "b", false).unwrap();
+        assert_eq!(card::get_by_id(&conn, &ids[0]).unwrap().content, "#b");
+
+        // ゴミ箱の Card に付いている名前には変更できない
+        card::soft_delete(&conn, &ids[3]).unwrap();
+        let result = rename(&conn, &deck_id, "c", "d", false);
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+        assert_eq!(card::get_by_id(&conn, &ids[2]).unwrap().content, "#c");
+    }
+
+    #[test]
+    fn test_merge() {
+        let (conn, deck_id, ids) = setup_tagged(&["#todo one", "#task two", "#todo #task both"]);
+        card::soft_delete(&conn, &ids[0]).unwrap();
+
+        let rewrites = merge(&conn, &deck_id, "todo", "task", false).unwrap();
+        assert_eq!(rewrites.len(), 2);
+
+        // ゴミ箱の Card も書き換わる
+        assert_eq!(card::get_by_id(&conn, &ids[0]).unwrap().content, "#task one");
+        assert_eq!(card::get_by_id(&conn, &ids[2]).unwrap().content, "#task #task both");
+
+        let tags = get_tags_by_card(&conn, &ids[2]).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "task");
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "task").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_query_cards() {
+        let (conn, deck_id, ids) = setup_tagged(&["#idea", "#idea #done", "#urgent #done", "nothing"]);
+
+        let contents = |expr: &str| -> Vec<String> {
+            query_cards(&conn, &deck_id, expr)
+                .unwrap()
+                .into_iter()
+                .map(|c| c.content)
+                .collect()
+        };
+
+        assert_eq!(contents("idea"), vec!["#idea", "#idea #done"]);
+        assert_eq!(contents("idea & !done"), vec!["#idea"]);
+        assert_eq!(contents("idea & !done | urgent"), vec!["#idea", "#urgent #done"]);
+        assert_eq!(contents("!(idea | urgent)"), vec!["nothing"]);
+        assert!(contents("missing").is_empty());
+
+        card::soft_delete(&conn, &ids[0]).unwrap();
+        assert_eq!(contents("idea"), vec!["#idea #done"]);
+
+        let result = query_cards(&conn, &deck_id, "idea &");
+        assert!(matches!(result, Err(JotDeckError::InvalidOperation(_))));
+    }
+
+    #[test]
+    fn test_suggestions_by_frequency() {
+        let (conn, deck_id, _) = setup_tagged(&["#project", "#proposal", "#proposal", "#personal"]);
+
+        let names = |order| -> Vec<String> {
+            get_tag_suggestions_ordered(&conn, &deck_id, "p", order)
+                .unwrap()
+                .into_iter()
+                .map(|t| t.name)
+                .collect()
+        };
+
+        assert_eq!(names(SuggestionOrder::Alphabetical), vec!["personal", "project", "proposal"]);
+        assert_eq!(names(SuggestionOrder::Frequency), vec!["proposal", "personal", "project"]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let (conn, deck_id, ids) = setup_tagged(&["#idea #urgent", "#idea", "#idea #urgent #done", "#done"]);
+        card::update_score(&conn, &ids[0], 3).unwrap();
+        card::update_score(&conn, &ids[1], 2).unwrap();
+        card::soft_delete(&conn, &ids[3]).unwrap();
+
+        let stats = stats(&conn, &deck_id).unwrap();
+
+        let summary: Vec<_> = stats
+            .tags
+            .iter()
+            .map(|u| (u.tag.name.as_str(), u.card_count, u.total_score))
+            .collect();
+        assert_eq!(summary, vec![("idea", 3, 5), ("urgent", 2, 3), ("done", 1, 0)]);
+
+        let idea = &stats.tags[0];
+        let first = card::get_by_id(&conn, &ids[0]).unwrap();
+        assert_eq!(idea.first_used_at, first.created_at);
+        assert!(idea.last_used_at >= first.updated_at);
+
+        let pairs: Vec<_> = stats
+            .cooccurrences
+            .iter()
+            .map(|p| (p.tag_a.as_str(), p.tag_b.as_str(), p.count))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![("idea", "urgent", 2), ("done", "idea", 1), ("done", "urgent", 1)]
+        );
+    }
+
+    #[test]
+    fn test_extract_hierarchical_tags() {
+        assert_eq!(
+            extract_tags("#project/alpha and #a/b/c, #trailing/ #x//y"),
+            vec!["project/alpha", "a/b/c", "trailing", "x"]
+        );
+        assert_eq!(parent_name("a/b/c"), Some("a/b"));
+        assert_eq!(parent_name("a"), None);
+    }
+
+    #[test]
+    fn test_hierarchical_tags() {
+        let (conn, deck_id, ids) = setup_tagged(&["#project/alpha", "#project/beta/x", "#project", "#projects"]);
+
+        // 祖先タグも作成され、親子関係が記録される
+        let alpha = get_or_create(&conn, "project/alpha").unwrap();
+        let project = get_or_create(&conn, "project").unwrap();
+        assert_eq!(alpha.parent_id.as_deref(), Some(project.id.as_str()));
+        assert!(project.parent_id.is_none());
+
+        // 親タグで絞り込むと子孫も一致する（名前が前方一致するだけのタグは含まない）
+        let mut found = get_cards_by_tag(&conn, &deck_id, "project").unwrap();
+        found.sort();
+        assert_eq!(found, ids[..3].to_vec());
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "project/beta").unwrap(), vec![ids[1].clone()]);
+        let cards = query_cards(&conn, &deck_id, "project & !project/alpha").unwrap();
+        assert_eq!(cards.len(), 2);
+
+        // フラットな一覧は従来どおり使われているタグのみ
+        let flat: Vec<_> = get_tags_by_deck(&conn, &deck_id).unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(flat, vec!["project", "project/alpha", "project/beta/x", "projects"]);
+
+        let tree = get_tag_tree_by_deck(&conn, &deck_id).unwrap();
+        let roots: Vec<_> = tree.iter().map(|n| n.tag.name.as_str()).collect();
+        assert_eq!(roots, vec!["project", "projects"]);
+        let children: Vec<_> = tree[0].children.iter().map(|n| n.tag.name.as_str()).collect();
+        assert_eq!(children, vec!["project/alpha", "project/beta"]);
+        assert_eq!(tree[0].children[1].children[0].tag.name, "project/beta/x");
+    }
+
+    #[test]
+    fn test_rename_moves_descendants() {
+        let (conn, deck_id, ids) = setup_tagged(&["#project/alpha #projects", "#project"]);
+
+        rename(&conn, &deck_id, "project", "work", false).unwrap();
+        assert_eq!(card::get_by_id(&conn, &ids[0]).unwrap().content, "#work/alpha #projects");
+        assert_eq!(card::get_by_id(&conn, &ids[1]).unwrap().content, "#work");
+        assert!(get_cards_by_tag(&conn, &deck_id, "project").unwrap().is_empty());
+    }
+
+    fn usage(conn: &Connection, name: &str) -> i64 {
+        live_card_count(conn, &get_or_create(conn, name).unwrap().id).unwrap()
+    }
+
+    fn live_names(conn: &Connection) -> Vec<String> {
+        get_all_live(conn).unwrap().into_iter().map(|t| t.name).collect()
+    }
+
+    #[test]
+    fn test_live_usage_follows_cards() {
+        let (conn, deck_id, ids) = setup_tagged(&["#idea #project/alpha", "#idea", "#trash"]);
+        let column_id = card::get_by_id(&conn, &ids[0]).unwrap().column_id;
+        assert_eq!(usage(&conn, "idea"), 2);
+        assert_eq!(live_names(&conn), vec!["idea", "project", "project/alpha", "trash"]);
+
+        // ゴミ箱の Card にしか付いていないタグは含めない
+        card::soft_delete(&conn, &ids[2]).unwrap();
+        assert_eq!(usage(&conn, "trash"), 0);
+        assert_eq!(live_names(&conn), vec!["idea", "project", "project/alpha"]);
+        card::restore(&conn, &ids[2]).unwrap();
+        assert_eq!(usage(&conn, "trash"), 1);
+
+        // 本文の編集
+        card::update_content(&conn, &ids[1], "no tags").unwrap();
+        assert_eq!(usage(&conn, "idea"), 1);
+
+        // Column / Deck ごとの削除と復元
+        column::soft_delete(&conn, &column_id).unwrap();
+        assert!(live_names(&conn).is_empty());
+        column::restore(&conn, &column_id).unwrap();
+        assert_eq!(usage(&conn, "idea"), 1);
+        deck::soft_delete(&conn, &deck_id).unwrap();
+        assert!(live_names(&conn).is_empty());
+        deck::restore(&conn, &deck_id).unwrap();
+        assert_eq!(live_names(&conn), vec!["idea", "project", "project/alpha", "trash"]);
+
+        // undo / redo でもずれない
+        journal::undo(&conn).unwrap();
+        assert!(live_names(&conn).is_empty());
+        journal::redo(&conn).unwrap();
+        assert_eq!(usage(&conn, "idea"), 1);
+        assert!(crate::db::check_integrity(&conn).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_restore_after_tag_purge() {
+        let (mut conn, deck_id, ids) = setup_tagged(&["#only #shared", "#shared", "#col", "#deck", "#undo"]);
+        let column_id = card::get_by_id(&conn, &ids[0]).unwrap().column_id;
+        let other = column::create(
+            &conn,
+            NewColumn {
+                deck_id: deck_id.clone(),
+                name: "Other".to_string(),
+            },
+        )
+        .unwrap();
+        card::move_to_column(&conn, &ids[2], &other.id).unwrap();
+
+        card::soft_delete(&conn, &ids[0]).unwrap();
+        column::soft_delete(&conn, &other.id).unwrap();
+        card::soft_delete(&conn, &ids[4]).unwrap();
+
+        // ゴミ箱の Card にしか使われていないタグを削除する（Card 自体は残す）
+        let policy = crate::cleanup::CleanupPolicy {
+            cards: crate::cleanup::Retention::Never,
+            columns: crate::cleanup::Retention::Never,
+            tags: crate::cleanup::Retention::Days(0),
+            ..Default::default()
+        };
+        let result = crate::cleanup::cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert_eq!(result.deleted_cards, 0);
+        let all: Vec<String> = conn
+            .prepare("SELECT name FROM tags ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(all, vec!["deck", "shared"]);
+        // 生きている Card でも使われているタグの関連は残る
+        assert_eq!(get_tags_by_card(&conn, &ids[0]).unwrap().len(), 1);
+
+        // undo での復元
+        journal::undo(&conn).unwrap();
+        assert!(card::get_by_id(&conn, &ids[4]).unwrap().deleted_at.is_none());
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "undo").unwrap(), vec![ids[4].clone()]);
+
+        // Card の復元
+        card::restore(&conn, &ids[0]).unwrap();
+        let names: Vec<_> = get_tags_by_card(&conn, &ids[0]).unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, vec!["only", "shared"]);
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "only").unwrap(), vec![ids[0].clone()]);
+
+        // Column の復元
+        column::restore(&conn, &other.id).unwrap();
+        assert_eq!(get_cards_by_tag(&conn, &deck_id, "col").unwrap(), vec![ids[2].clone()]);
+
+        // Deck の復元
+        deck::soft_delete(&conn, &deck_id).unwrap();
+        crate::cleanup::cleanup_with_policy(&mut conn, &policy).unwrap();
+        assert!(get_all_live(&conn).unwrap().is_empty());
+        deck::restore(&conn, &deck_id).unwrap();
+        assert_eq!(live_names(&conn), vec!["col", "deck", "only", "shared", "undo"]);
+        assert_eq!(column_id, card::get_by_id(&conn, &ids[3]).unwrap().column_id);
+        assert!(crate::db::check_integrity(&conn).unwrap().is_ok());
+    }
 }
@@ -0,0 +1,267 @@
+use rusqlite::types::Value;
+
+use crate::error::{JotDeckError, Result};
+use crate::repository::tag;
+
+/// 括弧と `!` の入れ子の上限
+const MAX_DEPTH: usize = 64;
+
+/// タグの論理式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+/// タグ名に使える文字か（タグ抽出の正規表現と同じく英数字・アンダースコア・日本語。階層の区切りは別に扱う）
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn syntax_error(message: impl std::fmt::Display) -> JotDeckError {
+    JotDeckError::InvalidOperation(format!("Invalid tag query: {}", message))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            c if c == '#' || is_tag_char(c) => {
+                let mut name = String::new();
+                if c != '#' {
+                    name.push(c);
+                }
+                while let Some(&(_, next)) = chars.peek() {
+                    if !is_tag_char(next) && next != '/' {
+                        break;
+                    }
+                    name.push(next);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    return Err(syntax_error(format!("empty tag name at {}", i)));
+                }
+                if name.starts_with('/') || name.ends_with('/') || name.contains("//") {
+                    return Err(syntax_error(format!("invalid tag name '{}' at {}", name, i)));
+                }
+                Token::Tag(name)
+            }
+            c => return Err(syntax_error(format!("unexpected '{}' at {}", c, i))),
+        };
+        tokens.push((i, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map(|(i, _)| *i).unwrap_or(self.end)
+    }
+
+    fn parse_or(&mut self, depth: usize) -> Result<TagExpr> {
+        let mut expr = self.parse_and(depth)?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            let rhs = self.parse_and(depth)?;
+            expr = TagExpr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<TagExpr> {
+        let mut expr = self.parse_unary(depth)?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                Some(Token::Tag(_) | Token::Not | Token::LParen) => {}
+                _ => break,
+            }
+            let rhs = self.parse_unary(depth)?;
+            expr = TagExpr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self, depth: usize) -> Result<TagExpr> {
+        if depth > MAX_DEPTH {
+            return Err(syntax_error("expression is nested too deeply"));
+        }
+
+        let offset = self.offset();
+        match self.tokens.get(self.pos).map(|(_, t)| t.clone()) {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(TagExpr::Not(Box::new(self.parse_unary(depth + 1)?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.parse_or(depth + 1)?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(syntax_error(format!("expected ')' at {}", self.offset())));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Tag(name)) => {
+                self.pos += 1;
+                Ok(TagExpr::Tag(name))
+            }
+            Some(_) => Err(syntax_error(format!("expected a tag at {}", offset))),
+            None => Err(syntax_error("unexpected end of query")),
+        }
+    }
+}
+
+/// タグの論理式をパースする
+///
+/// ```text
+/// expr  := and ("|" and)*
+/// and   := unary ("&"? unary)*     // 並べただけでも AND
+/// unary := "!" unary | "(" expr ")" | tag
+/// tag   := "#"? name ("/" name)*
+/// ```
+///
+/// 優先順位は `!` > `&` > `|`。例: `idea & !done | urgent` は `(idea AND NOT done) OR urgent`。
+/// 階層タグは子孫も一致する（`project` は `project/alpha` にも一致する）。
+pub fn parse(input: &str) -> Result<TagExpr> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        end: input.len(),
+    };
+
+    let expr = parser.parse_or(0)?;
+    if parser.pos < parser.tokens.len() {
+        return Err(syntax_error(format!("unexpected token at {}", parser.offset())));
+    }
+
+    Ok(expr)
+}
+
+impl TagExpr {
+    /// Card ID (`c.id`) に対する SQL の条件式にする。タグ名は values に追加してプレースホルダで参照する
+    pub(crate) fn to_sql(&self, values: &mut Vec<Value>) -> String {
+        match self {
+            TagExpr::Tag(name) => {
+                values.push(Value::Text(name.clone()));
+                format!(
+                    "c.id IN (SELECT ct.card_id FROM card_tags ct JOIN tags t ON ct.tag_id = t.id WHERE {})",
+                    tag::tag_or_descendant_sql(&format!("?{}", values.len()))
+                )
+            }
+            TagExpr::Not(inner) => format!("NOT ({})", inner.to_sql(values)),
+            TagExpr::And(lhs, rhs) => format!("({} AND {})", lhs.to_sql(values), rhs.to_sql(values)),
+            TagExpr::Or(lhs, rhs) => format!("({} OR {})", lhs.to_sql(values), rhs.to_sql(values)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str) -> TagExpr {
+        TagExpr::Tag(name.to_string())
+    }
+
+    fn not(e: TagExpr) -> TagExpr {
+        TagExpr::Not(Box::new(e))
+    }
+
+    fn and(l: TagExpr, r: TagExpr) -> TagExpr {
+        TagExpr::And(Box::new(l), Box::new(r))
+    }
+
+    fn or(l: TagExpr, r: TagExpr) -> TagExpr {
+        TagExpr::Or(Box::new(l), Box::new(r))
+    }
+
+    #[test]
+    fn test_parse_single_tag() {
+        assert_eq!(parse("idea").unwrap(), tag("idea"));
+        assert_eq!(parse("  #idea ").unwrap(), tag("idea"));
+        assert_eq!(parse("#重要_メモ").unwrap(), tag("重要_メモ"));
+        assert_eq!(parse("#project/alpha").unwrap(), tag("project/alpha"));
+    }
+
+    #[test]
+    fn test_parse_precedence() {
+        assert_eq!(
+            parse("idea & !done | urgent").unwrap(),
+            or(and(tag("idea"), not(tag("done"))), tag("urgent"))
+        );
+        assert_eq!(
+            parse("a | b & c").unwrap(),
+            or(tag("a"), and(tag("b"), tag("c")))
+        );
+        assert_eq!(
+            parse("(a | b) & c").unwrap(),
+            and(or(tag("a"), tag("b")), tag("c"))
+        );
+        assert_eq!(parse("!!a").unwrap(), not(not(tag("a"))));
+    }
+
+    #[test]
+    fn test_parse_implicit_and() {
+        assert_eq!(
+            parse("#idea #urgent !done").unwrap(),
+            and(and(tag("idea"), tag("urgent")), not(tag("done")))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for input in ["", "   ", "a &", "| a", "(a | b", "a)", "a & & b", "#", "a $ b", "!", "a/", "/a", "a//b"] {
+            assert!(
+                matches!(parse(input), Err(JotDeckError::InvalidOperation(_))),
+                "should reject {:?}",
+                input
+            );
+        }
+
+        let deep = format!("{}a{}", "(".repeat(MAX_DEPTH + 1), ")".repeat(MAX_DEPTH + 1));
+        assert!(parse(&deep).is_err());
+    }
+
+    #[test]
+    fn test_to_sql() {
+        let mut values = Vec::new();
+        let sql = parse("a & !b").unwrap().to_sql(&mut values);
+        assert_eq!(values, vec![Value::Text("a".into()), Value::Text("b".into())]);
+        assert!(sql.starts_with("(c.id IN ("));
+        assert!(sql.contains("?1"));
+        assert!(sql.contains("AND NOT (c.id IN ("));
+        assert!(sql.contains("?2"));
+    }
+}
@@ -1,15 +1,34 @@
 use jot_deck_core::{
-    create_file_db,
-    repository::{card, column, deck, tag},
-    Card, Column, Connection, Deck, NewCard, NewColumn, NewDeck, SortOrder, Tag,
+    backup::{self, ImportMode, ImportResult},
+    card::ScoreMerge,
+    cleanup::{self, CleanupResult},
+    db::{self, IntegrityReport},
+    export::{self, MarkdownOptions},
+    journal,
+    maintenance::{MaintenanceReport, MaintenanceTask, TaskError},
+    repository::{card, column, deck, delete_stack, search, tag},
+    search::SearchResult,
+    tag::{SuggestionOrder, TagNode, TagRewrite, TagStats},
+    Card, CardRevision, Column, Connection, ConnectionPool, Deck, DeleteStackEntry, NewCard, NewColumn, NewDeck, Operation, SortOrder,
+    Tag, UndoResult,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::{Mutex, MutexGuard};
-use tauri::{Manager, State};
+use std::sync::MutexGuard;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+/// 定期メンテナンスの間隔
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 定期メンテナンスの結果を通知するイベント名
+const MAINTENANCE_EVENT: &str = "maintenance-completed";
+
+/// 読み取り用の接続の数
+const READER_CONNECTIONS: usize = 2;
 
 /// アプリケーションの状態
 struct AppState {
-    conn: Mutex<Connection>,
+    pool: ConnectionPool,
 }
 
 /// エラーをシリアライズ可能な形式に変換する
@@ -28,9 +47,18 @@ impl From<jot_deck_core::JotDeckError> for CommandError {
 
 type CommandResult<T> = Result<T, CommandError>;
 
-/// Mutex ロックを取得するヘルパー関数（poisoning 対応）
+/// 書き込み用の接続を取得するヘルパー関数（poisoning 対応）
 fn get_conn<'a>(state: &'a State<'a, AppState>) -> CommandResult<MutexGuard<'a, Connection>> {
-    state.conn.lock().map_err(|e| CommandError {
+    state.pool.writer().map_err(|e| CommandError {
+        message: format!("Database lock poisoned: {}", e),
+    })
+}
+
+/// 読み取り用の接続を取得するヘルパー関数
+///
+/// 書き込みと並行に動くので、検索やエクスポートの最中でも編集を待たせない。
+fn get_reader<'a>(state: &'a State<'a, AppState>) -> CommandResult<MutexGuard<'a, Connection>> {
+    state.pool.reader().map_err(|e| CommandError {
         message: format!("Database lock poisoned: {}", e),
     })
 }
@@ -39,13 +67,13 @@ fn get_conn<'a>(state: &'a State<'a, AppState>) -> CommandResult<MutexGuard<'a,
 
 #[tauri::command]
 fn get_all_decks(state: State<AppState>) -> CommandResult<Vec<Deck>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     deck::get_all(&conn).map_err(Into::into)
 }
 
 #[tauri::command]
 fn get_deck(state: State<AppState>, id: String) -> CommandResult<Deck> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     deck::get_by_id(&conn, &id).map_err(Into::into)
 }
 
@@ -87,20 +115,32 @@ fn update_deck(
 #[tauri::command]
 fn delete_deck(state: State<AppState>, id: String) -> CommandResult<()> {
     let conn = get_conn(&state)?;
-    deck::delete(&conn, &id).map_err(Into::into)
+    deck::soft_delete(&conn, &id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn restore_deck(state: State<AppState>, id: String) -> CommandResult<Deck> {
+    let conn = get_conn(&state)?;
+    deck::restore(&conn, &id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_deleted_decks(state: State<AppState>) -> CommandResult<Vec<Deck>> {
+    let conn = get_reader(&state)?;
+    deck::get_deleted(&conn).map_err(Into::into)
 }
 
 // ========== Column Commands ==========
 
 #[tauri::command]
 fn get_columns_by_deck(state: State<AppState>, deck_id: String) -> CommandResult<Vec<Column>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     column::get_by_deck_id(&conn, &deck_id).map_err(Into::into)
 }
 
 #[tauri::command]
 fn get_column(state: State<AppState>, id: String) -> CommandResult<Column> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     column::get_by_id(&conn, &id).map_err(Into::into)
 }
 
@@ -137,6 +177,18 @@ fn move_column(state: State<AppState>, id: String, position: i32) -> CommandResu
     column::move_to_position(&conn, &id, position).map_err(Into::into)
 }
 
+#[tauri::command]
+fn move_column_to_deck(state: State<AppState>, id: String, deck_id: String) -> CommandResult<Column> {
+    let conn = get_conn(&state)?;
+    column::move_to_deck(&conn, &id, &deck_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn duplicate_column(state: State<AppState>, id: String, deck_id: String) -> CommandResult<Column> {
+    let conn = get_conn(&state)?;
+    column::duplicate(&conn, &id, &deck_id).map_err(Into::into)
+}
+
 #[tauri::command]
 fn delete_column(state: State<AppState>, id: String) -> CommandResult<()> {
     let conn = get_conn(&state)?;
@@ -151,7 +203,7 @@ fn restore_column(state: State<AppState>, id: String) -> CommandResult<Column> {
 
 #[tauri::command]
 fn get_deleted_columns(state: State<AppState>, deck_id: String) -> CommandResult<Vec<Column>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     column::get_deleted(&conn, &deck_id).map_err(Into::into)
 }
 
@@ -159,13 +211,28 @@ fn get_deleted_columns(state: State<AppState>, deck_id: String) -> CommandResult
 
 #[tauri::command]
 fn get_cards_by_column(state: State<AppState>, column_id: String) -> CommandResult<Vec<Card>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     card::get_by_column_id(&conn, &column_id).map_err(Into::into)
 }
 
+/// sort_order を省略した場合は所属する Deck のソート順で並べる
+#[tauri::command]
+fn get_sorted_cards_by_column(
+    state: State<AppState>,
+    column_id: String,
+    sort_order: Option<String>,
+) -> CommandResult<Vec<Card>> {
+    let conn = get_reader(&state)?;
+    match sort_order {
+        Some(s) => card::get_by_column_id_sorted(&conn, &column_id, SortOrder::from_db_value(&s)),
+        None => card::get_by_column_id_in_deck_order(&conn, &column_id),
+    }
+    .map_err(Into::into)
+}
+
 #[tauri::command]
 fn get_card(state: State<AppState>, id: String) -> CommandResult<Card> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     card::get_by_id(&conn, &id).map_err(Into::into)
 }
 
@@ -211,9 +278,20 @@ fn move_card_to_column(
     state: State<AppState>,
     id: String,
     column_id: String,
+    position: Option<i32>,
 ) -> CommandResult<Card> {
     let conn = get_conn(&state)?;
-    card::move_to_column(&conn, &id, &column_id).map_err(Into::into)
+    match position {
+        Some(position) => card::move_to(&conn, &id, &column_id, position),
+        None => card::move_to_column(&conn, &id, &column_id),
+    }
+    .map_err(Into::into)
+}
+
+#[tauri::command]
+fn duplicate_card(state: State<AppState>, id: String, column_id: String) -> CommandResult<Card> {
+    let conn = get_conn(&state)?;
+    card::duplicate(&conn, &id, &column_id).map_err(Into::into)
 }
 
 #[tauri::command]
@@ -235,37 +313,305 @@ fn restore_card(state: State<AppState>, id: String) -> CommandResult<Card> {
 }
 
 #[tauri::command]
-fn get_deleted_cards(state: State<AppState>, deck_id: String) -> CommandResult<Vec<Card>> {
+fn move_cards_to_column(
+    state: State<AppState>,
+    ids: Vec<String>,
+    column_id: String,
+) -> CommandResult<Vec<Card>> {
+    let conn = get_conn(&state)?;
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    card::move_many_to_column(&conn, &ids, &column_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn delete_cards(state: State<AppState>, ids: Vec<String>) -> CommandResult<Vec<Card>> {
+    let conn = get_conn(&state)?;
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    card::soft_delete_many(&conn, &ids).map_err(Into::into)
+}
+
+#[tauri::command]
+fn restore_cards(state: State<AppState>, ids: Vec<String>) -> CommandResult<Vec<Card>> {
+    let conn = get_conn(&state)?;
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    card::restore_many(&conn, &ids).map_err(Into::into)
+}
+
+#[tauri::command]
+fn update_cards_score(state: State<AppState>, ids: Vec<String>, delta: i32) -> CommandResult<Vec<Card>> {
+    let conn = get_conn(&state)?;
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    card::update_score_many(&conn, &ids, delta).map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_card_history(state: State<AppState>, id: String) -> CommandResult<Vec<CardRevision>> {
+    let conn = get_reader(&state)?;
+    card::history(&conn, &id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn revert_card(state: State<AppState>, id: String, revision_id: i64) -> CommandResult<Card> {
     let conn = get_conn(&state)?;
+    card::revert_to(&conn, &id, revision_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn merge_cards(
+    state: State<AppState>,
+    ids: Vec<String>,
+    separator: String,
+    score: Option<ScoreMerge>,
+) -> CommandResult<Card> {
+    let conn = get_conn(&state)?;
+    let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+    card::merge(&conn, &ids, &separator, score.unwrap_or_default()).map_err(Into::into)
+}
+
+#[tauri::command]
+fn split_card(state: State<AppState>, id: String, at: usize) -> CommandResult<(Card, Card)> {
+    let conn = get_conn(&state)?;
+    card::split(&conn, &id, at).map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_deleted_cards(state: State<AppState>, deck_id: String) -> CommandResult<Vec<Card>> {
+    let conn = get_reader(&state)?;
     card::get_deleted_by_deck(&conn, &deck_id).map_err(Into::into)
 }
 
+// ========== Delete Stack Commands ==========
+
+#[tauri::command]
+fn get_delete_stack(
+    state: State<AppState>,
+    deck_id: String,
+) -> CommandResult<Vec<DeleteStackEntry>> {
+    let conn = get_reader(&state)?;
+    delete_stack::list_stack(&conn, &deck_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn undo_delete(state: State<AppState>, deck_id: String) -> CommandResult<Option<DeleteStackEntry>> {
+    let conn = get_conn(&state)?;
+    delete_stack::undo_last(&conn, &deck_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn restore_delete_stack_entry(state: State<AppState>, id: i64) -> CommandResult<DeleteStackEntry> {
+    let conn = get_conn(&state)?;
+    delete_stack::restore_entry(&conn, id).map_err(Into::into)
+}
+
+// ========== Journal Commands ==========
+
+#[tauri::command]
+fn undo_operation(state: State<AppState>) -> CommandResult<UndoResult> {
+    let conn = get_conn(&state)?;
+    journal::undo(&conn).map_err(Into::into)
+}
+
+#[tauri::command]
+fn redo_operation(state: State<AppState>) -> CommandResult<UndoResult> {
+    let conn = get_conn(&state)?;
+    journal::redo(&conn).map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_operations(state: State<AppState>, limit: usize) -> CommandResult<Vec<Operation>> {
+    let conn = get_reader(&state)?;
+    journal::list(&conn, limit).map_err(Into::into)
+}
+
+// ========== Maintenance Commands ==========
+
+#[tauri::command]
+fn check_integrity(state: State<AppState>, repair: Option<bool>) -> CommandResult<IntegrityReport> {
+    if repair.unwrap_or(false) {
+        let conn = get_conn(&state)?;
+        db::repair_integrity(&conn).map_err(Into::into)
+    } else {
+        let conn = get_reader(&state)?;
+        db::check_integrity(&conn).map_err(Into::into)
+    }
+}
+
+#[tauri::command]
+fn empty_trash(state: State<AppState>, deck_id: String, secure: Option<bool>) -> CommandResult<CleanupResult> {
+    let mut conn = get_conn(&state)?;
+    cleanup::empty_trash(&mut conn, &deck_id, secure.unwrap_or(false)).map_err(Into::into)
+}
+
+/// 定期メンテナンスを起動時と一定間隔ごとにバックグラウンドで実行する
+fn spawn_maintenance(app: AppHandle) {
+    std::thread::spawn(move || loop {
+        let report = run_maintenance_tasks(&app);
+        // 通知できなくても次の実行で改めて通知するので、失敗は無視する
+        let _ = app.emit(MAINTENANCE_EVENT, &report);
+        std::thread::sleep(MAINTENANCE_INTERVAL);
+    });
+}
+
+/// メンテナンス作業を 1 つずつ実行する
+///
+/// 作業ごとにロックを取り直し、合間に UI からのコマンドを通す。
+/// 整合性チェックは書き込まないので読み取り用の接続で実行し、編集を待たせない。
+fn run_maintenance_tasks(app: &AppHandle) -> MaintenanceReport {
+    let state = app.state::<AppState>();
+    let mut report = MaintenanceReport::new();
+    for task in MaintenanceTask::ALL {
+        let conn = match task {
+            MaintenanceTask::Integrity => state.pool.reader(),
+            _ => state.pool.writer(),
+        };
+        match conn {
+            Ok(mut conn) => report.run(&mut conn, task),
+            Err(e) => report.errors.push(TaskError {
+                task,
+                message: format!("Database lock poisoned: {}", e),
+            }),
+        }
+    }
+    report
+}
+
 // ========== Tag Commands ==========
 
 #[tauri::command]
 fn get_tags_by_deck(state: State<AppState>, deck_id: String) -> CommandResult<Vec<Tag>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     tag::get_tags_by_deck(&conn, &deck_id).map_err(Into::into)
 }
 
+#[tauri::command]
+fn get_all_live_tags(state: State<AppState>) -> CommandResult<Vec<Tag>> {
+    let conn = get_reader(&state)?;
+    tag::get_all_live(&conn).map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_tag_tree_by_deck(state: State<AppState>, deck_id: String) -> CommandResult<Vec<TagNode>> {
+    let conn = get_reader(&state)?;
+    tag::get_tag_tree_by_deck(&conn, &deck_id).map_err(Into::into)
+}
+
 #[tauri::command]
 fn get_cards_by_tag(
     state: State<AppState>,
     deck_id: String,
     tag_name: String,
 ) -> CommandResult<Vec<String>> {
-    let conn = get_conn(&state)?;
+    let conn = get_reader(&state)?;
     tag::get_cards_by_tag(&conn, &deck_id, &tag_name).map_err(Into::into)
 }
 
+/// タグの論理式（`idea & !done | urgent` など）で Card を絞り込む
+#[tauri::command]
+fn query_cards_by_tags(
+    state: State<AppState>,
+    deck_id: String,
+    query: String,
+) -> CommandResult<Vec<Card>> {
+    let conn = get_reader(&state)?;
+    tag::query_cards(&conn, &deck_id, &query).map_err(Into::into)
+}
+
 #[tauri::command]
 fn get_tag_suggestions(
     state: State<AppState>,
     deck_id: String,
     prefix: String,
+    order: Option<SuggestionOrder>,
 ) -> CommandResult<Vec<Tag>> {
+    let conn = get_reader(&state)?;
+    tag::get_tag_suggestions_ordered(&conn, &deck_id, &prefix, order.unwrap_or_default())
+        .map_err(Into::into)
+}
+
+#[tauri::command]
+fn get_tag_stats(state: State<AppState>, deck_id: String) -> CommandResult<TagStats> {
+    let conn = get_reader(&state)?;
+    tag::stats(&conn, &deck_id).map_err(Into::into)
+}
+
+#[tauri::command]
+fn rename_tag(
+    state: State<AppState>,
+    deck_id: String,
+    old_name: String,
+    new_name: String,
+    dry_run: Option<bool>,
+) -> CommandResult<Vec<TagRewrite>> {
+    let conn = get_conn(&state)?;
+    tag::rename(&conn, &deck_id, &old_name, &new_name, dry_run.unwrap_or(false)).map_err(Into::into)
+}
+
+#[tauri::command]
+fn merge_tags(
+    state: State<AppState>,
+    deck_id: String,
+    from: String,
+    into: String,
+    dry_run: Option<bool>,
+) -> CommandResult<Vec<TagRewrite>> {
+    let conn = get_conn(&state)?;
+    tag::merge(&conn, &deck_id, &from, &into, dry_run.unwrap_or(false)).map_err(Into::into)
+}
+
+// ========== Search Commands ==========
+
+/// 検索結果の既定の最大件数
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+
+#[tauri::command]
+fn search_cards(
+    state: State<AppState>,
+    deck_id: String,
+    query: String,
+    limit: Option<usize>,
+) -> CommandResult<Vec<SearchResult>> {
+    let conn = get_reader(&state)?;
+    search::search(&conn, &deck_id, &query, limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+        .map_err(Into::into)
+}
+
+// ========== Export Commands ==========
+
+#[derive(Debug, Deserialize)]
+struct ExportMarkdownParams {
+    deck_id: String,
+    path: String,
+    #[serde(default)]
+    include_score: bool,
+    #[serde(default)]
+    include_timestamps: bool,
+    #[serde(default)]
+    include_tags: bool,
+}
+
+#[tauri::command]
+fn export_deck_markdown(state: State<AppState>, params: ExportMarkdownParams) -> CommandResult<()> {
+    let conn = get_reader(&state)?;
+    let options = MarkdownOptions {
+        include_score: params.include_score,
+        include_timestamps: params.include_timestamps,
+        include_tags: params.include_tags,
+    };
+    export::write_deck_markdown(&conn, &params.deck_id, &params.path, &options).map_err(Into::into)
+}
+
+// ========== Backup Commands ==========
+
+#[tauri::command]
+fn export_backup(state: State<AppState>, path: String) -> CommandResult<()> {
+    let conn = get_reader(&state)?;
+    backup::write_backup(&conn, &path).map_err(Into::into)
+}
+
+#[tauri::command]
+fn import_backup(state: State<AppState>, path: String, mode: ImportMode) -> CommandResult<ImportResult> {
     let conn = get_conn(&state)?;
-    tag::get_tag_suggestions(&conn, &deck_id, &prefix).map_err(Into::into)
+    backup::restore_backup_file(&conn, &path, mode).map_err(Into::into)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -278,12 +624,12 @@ pub fn run() {
             std::fs::create_dir_all(&app_data_dir).expect("Failed to create app data dir");
             let db_path = app_data_dir.join("jot-deck.db");
 
-            let conn = create_file_db(db_path.to_str().unwrap())
+            let pool = ConnectionPool::open(db_path.to_str().unwrap(), READER_CONNECTIONS)
                 .expect("Failed to create database");
 
-            app.manage(AppState {
-                conn: Mutex::new(conn),
-            });
+            app.manage(AppState { pool });
+
+            spawn_maintenance(app.handle().clone());
 
             Ok(())
         })
@@ -294,30 +640,68 @@ pub fn run() {
             create_deck,
             update_deck,
             delete_deck,
+            restore_deck,
+            get_deleted_decks,
             // Column commands
             get_columns_by_deck,
             get_column,
             create_column,
             update_column,
             move_column,
+            move_column_to_deck,
+            duplicate_column,
             delete_column,
             restore_column,
             get_deleted_columns,
             // Card commands
             get_cards_by_column,
+            get_sorted_cards_by_column,
             get_card,
             create_card,
             update_card_content,
             update_card_score,
             move_card_to_column,
             move_card,
+            duplicate_card,
             delete_card,
             restore_card,
             get_deleted_cards,
+            move_cards_to_column,
+            delete_cards,
+            restore_cards,
+            update_cards_score,
+            get_card_history,
+            revert_card,
+            merge_cards,
+            split_card,
+            // Delete stack commands
+            get_delete_stack,
+            undo_delete,
+            restore_delete_stack_entry,
+            // Journal commands
+            undo_operation,
+            redo_operation,
+            get_operations,
+            // Maintenance commands
+            check_integrity,
+            empty_trash,
             // Tag commands
             get_tags_by_deck,
+            get_all_live_tags,
+            get_tag_tree_by_deck,
             get_cards_by_tag,
+            query_cards_by_tags,
             get_tag_suggestions,
+            get_tag_stats,
+            rename_tag,
+            merge_tags,
+            // Search commands
+            search_cards,
+            // Export commands
+            export_deck_markdown,
+            // Backup commands
+            export_backup,
+            import_backup,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -17,11 +17,14 @@ export interface Column {
 }
 
 /** Tag regex pattern source (without flags). Keep in sync with crates/core/repository/tag.rs */
-export const TAG_PATTERN = "#([\\w\\u3040-\\u309f\\u30a0-\\u30ff\\u4e00-\\u9faf]+)";
+export const TAG_PATTERN =
+  "#((?:[\\w\\u3040-\\u309f\\u30a0-\\u30ff\\u4e00-\\u9faf]+/)*[\\w\\u3040-\\u309f\\u30a0-\\u30ff\\u4e00-\\u9faf]+)";
 
 export interface Tag {
   id: string;
   name: string;
+  /** Parent tag id for hierarchical tags (`a` for `a/b`) */
+  parent_id?: string | null;
 }
 
 export interface Card {